@@ -0,0 +1,60 @@
+use std::hint::black_box;
+
+use ajazz_sdk::{convert_image, flush_packets, parse_input, Kind};
+use criterion::{criterion_group, criterion_main, Criterion};
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Gradient is harder to compress than a flat color, so it's closer to real icons
+fn gradient(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+        Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+    }))
+}
+
+fn bench_convert_image(c: &mut Criterion) {
+    let mut group = c.benchmark_group("convert_image");
+    let image = gradient(256, 256);
+
+    for kind in Kind::ALL {
+        group.bench_function(format!("{kind:?}"), |b| {
+            b.iter(|| convert_image(kind, black_box(image.clone())).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_flush_packets(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flush_packets");
+
+    for kind in [Kind::Akp153, Kind::Akp815, Kind::Akp03] {
+        let image_data = convert_image(kind, gradient(256, 256)).unwrap();
+        let images: Vec<(u8, &[u8])> = (0..kind.display_key_count())
+            .map(|key| (key, image_data.as_slice()))
+            .collect();
+
+        group.bench_function(format!("{kind:?}"), |b| {
+            b.iter(|| flush_packets(kind, black_box(&images)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_parse_input(c: &mut Criterion) {
+    let mut report = vec![0u8; 512];
+    report[0] = 0x41;
+    report[9] = 0x01;
+
+    c.bench_function("parse_input", |b| {
+        b.iter(|| parse_input(Kind::Akp153, black_box(&report)).unwrap())
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_convert_image,
+    bench_flush_packets,
+    bench_parse_input
+);
+criterion_main!(benches);
@@ -49,6 +49,12 @@ fn main() {
                 Event::ButtonUp(button) => {
                     println!("Button {} up", button);
                 }
+                Event::TouchKeyDown(key) => {
+                    println!("Touch key {} down", key);
+                }
+                Event::TouchKeyUp(key) => {
+                    println!("Touch key {} up", key);
+                }
                 Event::EncoderTwist(dial, ticks) => {
                     println!("Dial {} twisted by {}", dial, ticks);
                 }
@@ -58,6 +64,18 @@ fn main() {
                 Event::EncoderUp(dial) => {
                     println!("Dial {} up", dial);
                 }
+                Event::EncoderPositionChanged(dial, position) => {
+                    println!("Dial {} moved to {}", dial, position);
+                }
+                Event::Tick(timer) => {
+                    println!("Timer {} fired", timer);
+                }
+                Event::Idle(inactive) => {
+                    println!("Idle for {:?}", inactive);
+                }
+                Event::Active => {
+                    println!("Active again");
+                }
             }
         }
     }
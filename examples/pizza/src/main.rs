@@ -29,6 +29,7 @@ async fn setup_device(device: &AsyncAjazz) -> Result<(), Box<dyn std::error::Err
     Ok(())
 }
 
+#[allow(clippy::collapsible_match)]
 async fn handle_input_events(
     device: AsyncAjazz,
     game_state: Arc<Mutex<GameState>>,
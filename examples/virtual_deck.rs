@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use ajazz_sdk::{Ajazz, Event, Kind, VirtualAjazz};
+use image::{DynamicImage, Rgb, RgbImage};
+
+#[allow(clippy::arc_with_non_send_sync)]
+fn main() {
+    let mut args = std::env::args();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <output_path.jpg>", args.next().unwrap());
+        return;
+    }
+    let output_path = args.nth(1).expect("output path is required");
+
+    // Virtual deck stands in for the hardware, the device doesn't know the difference
+    let deck = VirtualAjazz::new(Kind::Akp153);
+    let device = Arc::new(Ajazz::from_transport(deck.kind(), deck.clone()));
+    let reader = device.get_reader();
+
+    let (width, height) = device.key_image_format().size;
+    for key in 0..device.display_key_count() {
+        let shade = 255 - key * 12;
+        let tile = RgbImage::from_pixel(width as u32, height as u32, Rgb([shade, 64, 128]));
+        device
+            .set_button_image(key, DynamicImage::ImageRgb8(tile))
+            .unwrap();
+    }
+    device.flush().unwrap();
+
+    // Input is injected into the deck and read back like from a real device
+    deck.press_key(4).unwrap();
+    for event in reader.read(None).unwrap() {
+        if let Event::ButtonDown(key) = event {
+            println!("Key {} pressed, clearing it", key);
+            device.clear_button_images(&[key]).unwrap();
+        }
+    }
+
+    deck.framebuffer().save(&output_path).unwrap();
+    println!("Deck rendered to {}", output_path);
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use image::{DynamicImage, ImageError, ImageReader};
+
+use crate::device::Ajazz;
+use crate::formats::{ImageFormat, ImageOptions};
+use crate::images::convert_image_with_options;
+use crate::{AjazzError, Kind};
+
+/// Key image encoded for a specific image format, cheap to clone
+#[derive(Clone, Debug)]
+pub struct Asset {
+    format: ImageFormat,
+    image_data: Arc<[u8]>,
+}
+
+impl Asset {
+    /// Returns format the image was encoded for
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// Returns encoded image data
+    pub fn image_data(&self) -> &Arc<[u8]> {
+        &self.image_data
+    }
+}
+
+/// Collection of icons decoded, scaled and encoded once for the key image format,
+/// so assigning them to keys doesn't repeat the work
+///
+/// ```no_run
+/// # fn example(device: &ajazz_sdk::Ajazz) -> Result<(), ajazz_sdk::AjazzError> {
+/// let mut library = ajazz_sdk::AssetLibrary::for_device(device);
+/// library.load_dir("icons")?;
+///
+/// if let Some(icon) = library.get("play") {
+///     device.set_button_image_cached(0, &icon)?;
+/// }
+/// device.flush()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AssetLibrary {
+    format: ImageFormat,
+    options: ImageOptions,
+    assets: BTreeMap<String, Asset>,
+}
+
+impl AssetLibrary {
+    /// Creates empty library encoding images in the format
+    pub fn new(format: ImageFormat) -> AssetLibrary {
+        AssetLibrary {
+            format,
+            options: ImageOptions::default(),
+            assets: BTreeMap::new(),
+        }
+    }
+
+    /// Creates empty library for key images of the device kind
+    pub fn for_kind(kind: Kind) -> AssetLibrary {
+        AssetLibrary::new(kind.key_image_format())
+    }
+
+    /// Creates empty library for key images of the device, respecting overridden layout
+    /// and image options
+    pub fn for_device(device: &Ajazz) -> AssetLibrary {
+        AssetLibrary::new(device.key_image_format()).with_options(device.image_options())
+    }
+
+    /// Sets options used to convert images added later
+    pub fn with_options(mut self, options: ImageOptions) -> AssetLibrary {
+        self.options = options;
+        self
+    }
+
+    /// Encodes the image and adds it under the name, replacing previous asset
+    pub fn insert(&mut self, name: &str, image: DynamicImage) -> Result<Asset, ImageError> {
+        let asset = Asset {
+            format: self.format,
+            image_data: convert_image_with_options(self.format, image, self.options)?.into(),
+        };
+        self.assets.insert(name.to_string(), asset.clone());
+
+        Ok(asset)
+    }
+
+    /// Loads every image of the directory, named by file name without extension.
+    /// Files with extensions that are not images are skipped. Returns amount of loaded images
+    pub fn load_dir(&mut self, path: impl AsRef<Path>) -> Result<usize, ImageError> {
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(path).map_err(ImageError::IoError)? {
+            let path = entry.map_err(ImageError::IoError)?.path();
+            if !path.is_file() || image::ImageFormat::from_path(&path).is_err() {
+                continue;
+            }
+
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+
+            let image = ImageReader::open(&path)
+                .map_err(ImageError::IoError)?
+                .decode()?;
+            self.insert(name, image)?;
+            loaded += 1;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Returns handle of the asset
+    pub fn get(&self, name: &str) -> Option<Asset> {
+        self.assets.get(name).cloned()
+    }
+
+    /// Returns names of all assets
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.assets.keys().map(String::as_str)
+    }
+}
+
+impl Ajazz {
+    /// Sets button's image from the asset without encoding it again, changes must be flushed
+    /// with `.flush()` before they will appear on the device!
+    pub fn set_button_image_cached(&self, key: u8, asset: &Asset) -> Result<(), AjazzError> {
+        let format = self.key_image_format();
+        if asset.format != format {
+            let (width, height) = asset.format.size;
+            let (expected_width, expected_height) = format.size;
+            return Err(AjazzError::InvalidImageSize(
+                width,
+                height,
+                expected_width,
+                expected_height,
+            ));
+        }
+
+        self.set_button_image_data(key, asset.image_data.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayTransport;
+
+    #[test]
+    fn test_assets_are_encoded_once() {
+        let dir = std::env::temp_dir().join(format!("ajazz-assets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        DynamicImage::new_rgb8(100, 100)
+            .save(dir.join("play.jpg"))
+            .unwrap();
+        std::fs::write(dir.join("README.txt"), "not an image").unwrap();
+
+        let mut library = AssetLibrary::for_kind(Kind::Akp03);
+        let loaded = library.load_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap(), 1);
+        assert_eq!(library.names().collect::<Vec<_>>(), ["play"]);
+
+        let first = library.get("play").unwrap();
+        let second = library.get("play").unwrap();
+        assert!(Arc::ptr_eq(first.image_data(), second.image_data()));
+
+        let device = Ajazz::from_transport(Kind::Akp03, ReplayTransport::default());
+        device.set_button_image_cached(0, &first).unwrap();
+
+        let other = AssetLibrary::for_kind(Kind::Akp153)
+            .insert("play", DynamicImage::new_rgb8(10, 10))
+            .unwrap();
+        assert!(matches!(
+            device.set_button_image_cached(0, &other),
+            Err(AjazzError::InvalidImageSize(85, 85, 60, 60))
+        ));
+    }
+}
@@ -1,43 +1,233 @@
-//! Code from this module is using [block_in_place](tokio::task::block_in_place),
-//! and so they cannot be used in [current_thread](tokio::runtime::Builder::new_current_thread) runtimes
+//! [AsyncAjazz] runs device commands on a dedicated thread, other code from this module
+//! is using [block_in_place](tokio::task::block_in_place), and so it cannot be used in
+//! [current_thread](tokio::runtime::Builder::new_current_thread) runtimes
 
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use hidapi::{HidApi, HidResult};
+#[cfg(feature = "image")]
 use image::DynamicImage;
-use tokio::sync::Mutex;
-use tokio::task::block_in_place;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::{block_in_place, spawn_blocking, JoinHandle};
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
-use crate::{AjazzError, AjazzInput, DeviceState, Event, Kind};
-use crate::device::{handle_input_state_change, Ajazz};
+use crate::{
+    AjazzError, AjazzInput, BrightnessZones, ColorProfile, ConnectOptions, DeckState,
+    DropPolicy, Event, FlushProgress, EncoderTracker, HandshakeInfo, ImageOptions, Kind,
+    Metrics, Transaction,
+};
+#[cfg(feature = "image")]
+use crate::{ImageRect, LogoScaling, PressFeedback};
+use crate::device::Ajazz;
+use crate::locks::lock;
+use crate::idle::IdleTracker;
+#[cfg(feature = "image")]
+use crate::press_feedback::PressFeedbackState;
+use crate::recording::{InputLog, Recorder};
+use crate::reader::{handle_input_state_change, is_press, DeviceState};
 use crate::hid::list_devices;
-use crate::images::convert_image_async;
+#[cfg(feature = "image")]
+use crate::images::convert_image_with_options;
 
 /// Actually refreshes the device list, can be safely ran inside [multi_thread](tokio::runtime::Builder::new_multi_thread) runtime
 pub fn refresh_device_list_async(hidapi: &mut HidApi) -> HidResult<()> {
     block_in_place(move || hidapi.refresh_devices())
 }
 
-/// Returns a list of devices as (Kind, Serial Number) that could be found using HidApi,
-/// can be safely ran inside [multi_thread](tokio::runtime::Builder::new_multi_thread) runtime
-///
-/// **WARNING:** To refresh the list, use [refresh_device_list]
-pub fn list_devices_async(hidapi: &HidApi) -> Vec<(Kind, String)> {
-    block_in_place(move || list_devices(hidapi))
+/// Returns a list of devices as (Kind, Serial Number) that are currently connected.
+/// Enumeration runs on the blocking thread pool with a fresh HidApi,
+/// so the list is always up to date and the runtime is never blocked
+pub async fn list_devices_async() -> Result<Vec<(Kind, String)>, AjazzError> {
+    spawn_blocking(|| {
+        let hidapi = HidApi::new()?;
+        Ok(list_devices(&hidapi))
+    })
+    .await?
+}
+
+/// Change in the set of connected devices, see [DeviceWatcher]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HotplugEvent {
+    /// Device was connected, as (Kind, Serial Number)
+    Connected(Kind, String),
+    /// Device was disconnected, as (Kind, Serial Number)
+    Disconnected(Kind, String),
+}
+
+/// Watches for connected and disconnected devices by polling the device list
+/// on the blocking thread pool. Watching stops when the watcher is dropped
+pub struct DeviceWatcher {
+    events: broadcast::Sender<HotplugEvent>,
+    task: JoinHandle<()>,
+}
+
+impl DeviceWatcher {
+    /// Starts watching, device list is refreshed every `poll_interval`.
+    /// Devices connected before the start are reported as [HotplugEvent::Connected]
+    /// to the receivers subscribed before the first poll
+    pub fn spawn(poll_interval: Duration) -> DeviceWatcher {
+        DeviceWatcher::spawn_with_shutdown(poll_interval, CancellationToken::new())
+    }
+
+    /// Starts watching like [DeviceWatcher::spawn], watching also stops
+    /// when the token is cancelled
+    pub fn spawn_with_shutdown(
+        poll_interval: Duration,
+        shutdown: CancellationToken,
+    ) -> DeviceWatcher {
+        let (events, _) = broadcast::channel(64);
+        let sender = events.clone();
+
+        let watch = async move {
+            let mut known = HashSet::new();
+            loop {
+                if let Ok(devices) = list_devices_async().await {
+                    let current: HashSet<(Kind, String)> = devices.into_iter().collect();
+                    for event in hotplug_events(&known, &current) {
+                        // Having no receivers is fine, they may subscribe later
+                        let _ = sender.send(event);
+                    }
+                    known = current;
+                }
+
+                sleep(poll_interval).await;
+            }
+        };
+        let task = tokio::spawn(async move {
+            tokio::select! {
+                () = watch => {}
+                () = shutdown.cancelled() => {}
+            }
+        });
+
+        DeviceWatcher { events, task }
+    }
+
+    /// Returns receiver of hot-plug events
+    pub fn subscribe(&self) -> broadcast::Receiver<HotplugEvent> {
+        self.events.subscribe()
+    }
 }
 
-/// Ajazz device interface suitable to be used in async, uses [block_in_place](block_in_place)
-/// so this wrapper cannot be used in [current_thread](tokio::runtime::Builder::new_current_thread) runtimes
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Compares device sets, returning events that turn `known` into `current`
+fn hotplug_events(
+    known: &HashSet<(Kind, String)>,
+    current: &HashSet<(Kind, String)>,
+) -> Vec<HotplugEvent> {
+    let disconnected = known
+        .difference(current)
+        .map(|(kind, serial)| HotplugEvent::Disconnected(*kind, serial.clone()));
+    let connected = current
+        .difference(known)
+        .map(|(kind, serial)| HotplugEvent::Connected(*kind, serial.clone()));
+
+    disconnected.chain(connected).collect()
+}
+
+/// Command executed on the device thread
+type Command = Box<dyn FnOnce(&Ajazz) + Send>;
+
+/// Urgent commands waiting for the device thread
+type UrgentCommands = Arc<std::sync::Mutex<VecDeque<Command>>>;
+
+/// Order in which commands of [AsyncAjazz] are executed
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// Executed in the order they were sent
+    #[default]
+    Normal,
+    /// Executed before every waiting normal command, e.g. a brightness change or an
+    /// indicator key during a large update. The command already running isn't interrupted
+    Urgent,
+}
+
+/// Ajazz device interface suitable to be used in async.
+///
+/// Device is owned by a dedicated thread that executes commands one by one in the order
+/// they were sent, so calls never block the runtime. Clones share the same thread,
+/// which stops when the last clone is dropped.
+///
+/// Every call is sent to the device as a whole, so packets of two images written from
+/// different tasks are never interleaved on the wire. Calls made from different tasks
+/// may still run between each other, e.g. a flush from one task may send images staged
+/// by another. Use [AsyncAjazz::exclusive] to run several calls without interruption.
+///
+/// Brightness changes are [urgent](Priority::Urgent), they skip ahead of queued image
+/// updates. Use [AsyncAjazz::with_priority] to make other calls urgent
 #[derive(Clone)]
 pub struct AsyncAjazz {
     kind: Kind,
-    device: Arc<Mutex<Ajazz>>,
+    commands: std::sync::mpsc::Sender<Command>,
+    /// Commands executed before the ones waiting in the channel
+    urgent: UrgentCommands,
+    /// Inputs read from the device, but not yet returned to the caller
+    unread: Arc<std::sync::Mutex<VecDeque<AjazzInput>>>,
+    /// Stops reads waiting for input, see [AsyncAjazz::shutdown_token]
+    shutdown: CancellationToken,
+}
+
+/// Spawns thread owning the device, returns sender of commands for it and queue
+/// of urgent commands. Every urgent command is followed by a command in the channel,
+/// so the thread wakes up for it
+fn spawn_device_thread(device: Ajazz) -> (std::sync::mpsc::Sender<Command>, UrgentCommands) {
+    let (commands, receiver) = std::sync::mpsc::channel::<Command>();
+    let urgent = UrgentCommands::default();
+    let queue = urgent.clone();
+    std::thread::spawn(move || {
+        for command in receiver {
+            loop {
+                // Queue isn't locked while the command runs, so senders never wait for it
+                let next = lock(&queue).pop_front();
+                let Some(next) = next else {
+                    break;
+                };
+                next(&device);
+            }
+
+            command(&device);
+        }
+    });
+
+    (commands, urgent)
 }
 
 /// Static functions of the struct
 impl AsyncAjazz {
+    /// Wraps connected device, moving it to its own thread
+    pub fn from_device(device: Ajazz) -> AsyncAjazz {
+        AsyncAjazz::from_device_with_shutdown(device, CancellationToken::new())
+    }
+
+    /// Wraps connected device like [AsyncAjazz::from_device], using the token as
+    /// the [shutdown token](AsyncAjazz::shutdown_token). Pass a child token
+    /// of the application's own token to stop reads together with the application
+    pub fn from_device_with_shutdown(
+        device: Ajazz,
+        shutdown: CancellationToken,
+    ) -> AsyncAjazz {
+        let kind = device.kind();
+        let (commands, urgent) = spawn_device_thread(device);
+        AsyncAjazz {
+            kind,
+            commands,
+            urgent,
+            unread: Arc::default(),
+            shutdown,
+        }
+    }
+
     /// Attempts to connect to the device, can be safely ran inside [multi_thread](tokio::runtime::Builder::new_multi_thread) runtime
     pub fn connect(
         hidapi: &HidApi,
@@ -46,10 +236,7 @@ impl AsyncAjazz {
     ) -> Result<AsyncAjazz, AjazzError> {
         let device = block_in_place(move || Ajazz::connect(hidapi, kind, serial))?;
 
-        Ok(AsyncAjazz {
-            kind,
-            device: Arc::new(Mutex::new(device)),
-        })
+        Ok(AsyncAjazz::from_device(device))
     }
 
     /// Attempts to connect to the device, can be safely ran inside [multi_thread](tokio::runtime::Builder::new_multi_thread) runtime
@@ -63,15 +250,101 @@ impl AsyncAjazz {
             Ajazz::connect_with_retries(hidapi, kind, serial, attempts)
         })?;
 
-        Ok(AsyncAjazz {
-            kind,
-            device: Arc::new(Mutex::new(device)),
+        Ok(AsyncAjazz::from_device(device))
+    }
+
+    /// Attempts to connect to the device without blocking the runtime.
+    /// Device is opened on the blocking thread pool with a fresh HidApi,
+    /// so it can be used in any runtime
+    pub async fn connect_async(kind: Kind, serial: &str) -> Result<AsyncAjazz, AjazzError> {
+        let serial = serial.to_string();
+        let device = spawn_blocking(move || {
+            let hidapi = HidApi::new()?;
+            Ajazz::connect(&hidapi, kind, &serial)
         })
+        .await??;
+
+        Ok(AsyncAjazz::from_device(device))
+    }
+
+    /// Attempts to connect to the device using provided options, can be safely ran inside [multi_thread](tokio::runtime::Builder::new_multi_thread) runtime
+    pub fn connect_with_options(
+        hidapi: &HidApi,
+        kind: Kind,
+        serial: &str,
+        options: &ConnectOptions,
+    ) -> Result<AsyncAjazz, AjazzError> {
+        let device = block_in_place(move || {
+            Ajazz::connect_with_options(hidapi, kind, serial, options)
+        })?;
+
+        Ok(AsyncAjazz::from_device(device))
     }
 }
 
 /// Instance methods of the struct
 impl AsyncAjazz {
+    /// Returns token shared by all clones, cancelling it makes reads waiting for input,
+    /// including ones of [readers](AsyncAjazz::get_reader), return
+    /// [AjazzError::ShutDown] right away, so the application doesn't hang on shutdown.
+    ///
+    /// Commands still run after the cancellation, e.g. to clear the keys before exiting
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Runs the closure on the device thread and waits for its result
+    async fn run<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Ajazz) -> R + Send + 'static,
+    ) -> Result<R, AjazzError> {
+        self.with_priority(Priority::Normal, f).await
+    }
+
+    /// Queues the command for the device thread
+    fn send(&self, priority: Priority, command: Command) -> Result<(), AjazzError> {
+        let command = match priority {
+            Priority::Normal => command,
+            Priority::Urgent => {
+                lock(&self.urgent).push_back(command);
+                Box::new(|_: &Ajazz| {})
+            }
+        };
+
+        self.commands
+            .send(command)
+            .map_err(|_| AjazzError::DeviceClosed)
+    }
+
+    /// Runs the closure on the device thread with the priority and waits for its result,
+    /// no other command is executed until it returns. Urgent closures run before calls
+    /// already waiting for the device
+    pub async fn with_priority<R: Send + 'static>(
+        &self,
+        priority: Priority,
+        f: impl FnOnce(&Ajazz) -> R + Send + 'static,
+    ) -> Result<R, AjazzError> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(
+            priority,
+            Box::new(move |device| {
+                // Caller may stop waiting for the result, that's fine
+                let _ = sender.send(f(device));
+            }),
+        )?;
+
+        receiver.await.map_err(|_| AjazzError::DeviceClosed)
+    }
+
+    /// Runs the closure on the device thread, no other command is executed until it returns.
+    /// Useful for staging images and flushing them without other tasks getting in between
+    pub async fn exclusive<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Ajazz) -> R + Send + 'static,
+    ) -> Result<R, AjazzError> {
+        self.run(f).await
+    }
+
     /// Returns kind of the Stream Deck
     pub fn kind(&self) -> Kind {
         self.kind
@@ -79,80 +352,290 @@ impl AsyncAjazz {
 
     /// Returns manufacturer string of the device
     pub async fn manufacturer(&self) -> Result<String, AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.manufacturer())
+        self.run(move |device| device.manufacturer()).await?
     }
 
     /// Returns product string of the device
     pub async fn product(&self) -> Result<String, AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.product())
+        self.run(move |device| device.product()).await?
     }
 
     /// Returns serial number of the device
     pub async fn serial_number(&self) -> Result<String, AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.serial_number())
+        self.run(move |device| device.serial_number()).await?
     }
 
     /// Returns firmware version of the StreamDeck
     pub async fn firmware_version(&self) -> Result<String, AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.firmware_version())
+        self.run(move |device| device.firmware_version()).await?
     }
 
     /// Reads button states, awaits until there's data.
-    /// Poll rate determines how often button state gets checked
+    /// Poll rate determines how often button state gets checked.
+    ///
+    /// Device is polled without blocking, so the future can be cancelled at any point,
+    /// e.g. with [timeout](tokio::time::timeout). Input read by a cancelled call
+    /// is returned by the next one. Returns [AjazzError::ShutDown] once
+    /// the [shutdown token](AsyncAjazz::shutdown_token) is cancelled
     pub async fn read_input(&self, poll_rate: f32) -> Result<AjazzInput, AjazzError> {
         loop {
-            let device = self.device.lock().await;
-            let data = block_in_place(move || device.read_input(None))?;
+            if self.shutdown.is_cancelled() {
+                return Err(AjazzError::ShutDown);
+            }
 
-            if !data.is_empty() {
-                return Ok(data);
+            if let Some(input) = lock(&self.unread).pop_front() {
+                return Ok(input);
             }
 
-            sleep(Duration::from_secs_f32(1.0 / poll_rate)).await;
+            let unread = self.unread.clone();
+            self.run(move |device| {
+                device.set_blocking_mode(false)?;
+                let input = device.read_input(None)?;
+                if !input.is_empty() {
+                    lock(&unread).push_back(input);
+                }
+
+                Ok::<(), AjazzError>(())
+            })
+            .await??;
+
+            if let Some(input) = lock(&self.unread).pop_front() {
+                return Ok(input);
+            }
+
+            tokio::select! {
+                () = sleep(Duration::from_secs_f32(1.0 / poll_rate)) => {}
+                () = self.shutdown.cancelled() => return Err(AjazzError::ShutDown),
+            }
         }
     }
 
+    /// Sends the command framed with the request header, see [Ajazz::send_raw_command]
+    pub async fn send_raw_command(&self, command: &[u8]) -> Result<(), AjazzError> {
+        let command = command.to_vec();
+        self.run(move |device| device.send_raw_command(&command))
+            .await?
+    }
+
+    /// Reads a raw report, see [Ajazz::read_raw_report]
+    pub async fn read_raw_report(
+        &self,
+        length: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, AjazzError> {
+        self.run(move |device| device.read_raw_report(length, timeout))
+            .await?
+    }
+
+    /// Reads feature report with the id, see [Ajazz::read_raw_feature_report]
+    pub async fn read_raw_feature_report(
+        &self,
+        report_id: u8,
+        length: usize,
+    ) -> Result<Vec<u8>, AjazzError> {
+        self.run(move |device| device.read_raw_feature_report(report_id, length))
+            .await?
+    }
+
     /// Resets the device
     pub async fn reset(&self) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.reset())
+        self.run(move |device| device.reset()).await?
+    }
+
+    /// Overrides geometry of the device, see [Ajazz::override_layout]
+    pub async fn override_layout(
+        &self,
+        rows: u8,
+        cols: u8,
+        key_image_size: (usize, usize),
+    ) -> Result<(), AjazzError> {
+        self.run(move |device| device.override_layout(rows, cols, key_image_size))
+            .await?
+    }
+
+    /// Sets options used when key images are converted, see [Ajazz::set_image_options]
+    pub async fn set_image_options(&self, options: ImageOptions) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_image_options(options))
+            .await
+    }
+
+    /// Sets color correction of key images, see [Ajazz::set_color_profile]
+    pub async fn set_color_profile(&self, profile: ColorProfile) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_color_profile(profile))
+            .await
+    }
+
+    /// Sets what to do with the device when the last clone of this wrapper is dropped
+    pub async fn set_drop_policy(&self, policy: DropPolicy) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_drop_policy(policy)).await
+    }
+
+    /// Checks device responses to commands, see [Ajazz::set_ack_verification]
+    pub async fn set_ack_verification(&self, enabled: bool) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_ack_verification(enabled))
+            .await
+    }
+
+    /// Sets how long to wait for device responses, see [Ajazz::set_command_timeout]
+    pub async fn set_command_timeout(&self, timeout: Duration) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_command_timeout(timeout))
+            .await
+    }
+
+    /// Starts logging reports to the file, see [Ajazz::enable_command_log]
+    pub async fn enable_command_log(&self, path: PathBuf) -> Result<(), AjazzError> {
+        self.run(move |device| device.enable_command_log(path))
+            .await??;
+        Ok(())
+    }
+
+    /// Stops logging reports, see [Ajazz::disable_command_log]
+    pub async fn disable_command_log(&self) -> Result<(), AjazzError> {
+        self.run(|device| device.disable_command_log()).await
+    }
+
+    /// Sends images left by a failed flush, see [Ajazz::retry_flush]
+    pub async fn retry_flush(&self) -> Result<Vec<u8>, AjazzError> {
+        self.run(|device| device.retry_flush()).await?
+    }
+
+    /// Limits bytes of images waiting for the flush, see [Ajazz::set_image_cache_limit]
+    pub async fn set_image_cache_limit(&self, limit: Option<usize>) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_image_cache_limit(limit))
+            .await
+    }
+
+    /// Sets delay between image data reports, see [Ajazz::set_report_pacing]
+    pub async fn set_report_pacing(&self, delay: Option<Duration>) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_report_pacing(delay))
+            .await
+    }
+
+    /// Sets how many times partially accepted reports are written,
+    /// see [Ajazz::set_write_attempts]
+    pub async fn set_write_attempts(&self, attempts: u8) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_write_attempts(attempts))
+            .await
+    }
+
+    /// Returns amount of key images waiting for the flush
+    pub async fn cache_len(&self) -> Result<usize, AjazzError> {
+        self.run(|device| device.cache_len()).await
+    }
+
+    /// Drops key images waiting for the flush, see [Ajazz::clear_cache]
+    pub async fn clear_cache(&self) -> Result<(), AjazzError> {
+        self.run(|device| device.clear_cache()).await
+    }
+
+    /// Initializes the device again and returns what it reports, see [Ajazz::handshake]
+    pub async fn handshake(&self) -> Result<HandshakeInfo, AjazzError> {
+        self.run(|device| device.handshake()).await?
+    }
+
+    /// Returns true if the initialization packet was sent to the device
+    pub async fn is_initialized(&self) -> Result<bool, AjazzError> {
+        self.run(|device| device.is_initialized()).await
+    }
+
+    /// Returns true if the device stopped responding, see [Ajazz::is_wedged]
+    pub async fn is_wedged(&self) -> Result<bool, AjazzError> {
+        self.run(|device| device.is_wedged()).await
+    }
+
+    /// Returns snapshot of what the deck shows, see [Ajazz::export_state]
+    pub async fn export_state(&self) -> Result<DeckState, AjazzError> {
+        self.run(|device| device.export_state()).await
+    }
+
+    /// Returns brightness last set through this device, see [Ajazz::get_brightness]
+    pub async fn get_brightness(&self) -> Result<Option<u8>, AjazzError> {
+        self.run(|device| device.get_brightness()).await
+    }
+
+    /// Renders what the deck shows into a single image, see [Ajazz::render_deck_preview]
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub async fn render_deck_preview(&self) -> Result<DynamicImage, AjazzError> {
+        self.run(|device| device.render_deck_preview()).await
+    }
+
+    /// Restores snapshot of the deck, see [Ajazz::apply_state]
+    pub async fn apply_state(&self, state: DeckState) -> Result<(), AjazzError> {
+        self.run(move |device| device.apply_state(&state)).await?
     }
 
     /// Sets brightness of the device, value range is 0 - 100
     pub async fn set_brightness(&self, percent: u8) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.set_brightness(percent))
+        self.with_priority(Priority::Urgent, move |device| {
+            device.set_brightness(percent)
+        })
+        .await?
+    }
+
+    /// Sets brightness of keys and the strip separately, value range is 0 - 100,
+    /// see [Ajazz::set_brightness_zones]
+    pub async fn set_brightness_zones(
+        &self,
+        zones: BrightnessZones,
+    ) -> Result<(), AjazzError> {
+        self.with_priority(Priority::Urgent, move |device| {
+            device.set_brightness_zones(zones)
+        })
+        .await?
     }
 
     /// Sets button's image to blank, changes must be flushed with `.flush()` before
     /// they will appear on the device!
     pub async fn clear_button_image(&self, key: u8) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.clear_button_image(key))
+        self.run(move |device| device.clear_button_image(key))
+            .await?
     }
 
     /// Sets blank images to every button, changes must be flushed with `.flush()` before
     /// they will appear on the device!
     pub async fn clear_all_button_images(&self) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.clear_all_button_images())
+        self.run(move |device| device.clear_all_button_images())
+            .await?
+    }
+
+    /// Sets blank images to the listed buttons using as few commands as possible
+    pub async fn clear_button_images(&self, keys: &[u8]) -> Result<(), AjazzError> {
+        let keys = keys.to_vec();
+        self.run(move |device| device.clear_button_images(&keys))
+            .await?
     }
 
     /// Sets specified button's image, changes must be flushed with `.flush()` before
     /// they will appear on the device!
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
     pub async fn set_button_image(
         &self,
         key: u8,
         image: DynamicImage,
     ) -> Result<(), AjazzError> {
-        let image = convert_image_async(self.kind, image)?;
+        let (format, options) = self
+            .run(|device| (device.key_image_format(), device.image_options()))
+            .await?;
+        let image = spawn_blocking(move || convert_image_with_options(format, image, options))
+            .await??;
 
-        let device = self.device.lock().await;
-        block_in_place(move || device.set_button_image_data(key, &image))
+        self.run(move |device| device.set_button_image_data(key, image))
+            .await?
+    }
+
+    /// Encodes the image and sends it right away, skipping the cache,
+    /// see [Ajazz::stream_button_image]
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub async fn stream_button_image(
+        &self,
+        key: u8,
+        image: DynamicImage,
+    ) -> Result<(), AjazzError> {
+        self.run(move |device| device.stream_button_image(key, image))
+            .await?
     }
 
     /// Sets specified button's image, changes must be flushed with `.flush()` before
@@ -160,40 +643,113 @@ impl AsyncAjazz {
     pub async fn set_button_image_data(
         &self,
         key: u8,
-        image_data: &[u8],
+        image_data: impl Into<Arc<[u8]>>,
     ) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.set_button_image_data(key, image_data))
+        let image_data = image_data.into();
+        self.run(move |device| device.set_button_image_data(key, image_data))
+            .await?
+    }
+
+    /// Stages key changes made in the closure and commits them with a single flush,
+    /// see [Ajazz::transaction]
+    pub async fn transaction<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<R, AjazzError> + Send + 'static,
+    ) -> Result<R, AjazzError> {
+        self.run(move |device| device.transaction(f)).await?
     }
 
     /// Set logo image
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
     pub async fn set_logo_image(&self, image: DynamicImage) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.set_logo_image(image))
+        self.run(move |device| device.set_logo_image(image)).await?
+    }
+
+    /// Sets logo image, see [Ajazz::set_logo_image_with_scaling]
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub async fn set_logo_image_with_scaling(
+        &self,
+        image: DynamicImage,
+        scaling: LogoScaling,
+    ) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_logo_image_with_scaling(image, scaling))
+            .await?
+    }
+
+    /// Fills the whole LCD strip with the image, see [Ajazz::write_lcd_fill]
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub async fn write_lcd_fill(&self, rect: &ImageRect) -> Result<(), AjazzError> {
+        let rect = rect.clone();
+        self.run(move |device| device.write_lcd_fill(&rect)).await?
+    }
+
+    /// Draws the image onto the LCD strip at (x, y), see [Ajazz::write_lcd]
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub async fn write_lcd(&self, x: u16, y: u16, rect: &ImageRect) -> Result<(), AjazzError> {
+        let rect = rect.clone();
+        self.run(move |device| device.write_lcd(x, y, &rect))
+            .await?
     }
 
     /// Sleeps the device
     pub async fn sleep(&self) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.sleep())
+        self.run(move |device| device.sleep()).await?
+    }
+
+    /// Wakes the device up after [sleep](AsyncAjazz::sleep)
+    pub async fn wake(&self) -> Result<(), AjazzError> {
+        self.run(move |device| device.wake()).await?
+    }
+
+    /// Returns true if the device was put to sleep and wasn't woken up since
+    pub async fn is_asleep(&self) -> Result<bool, AjazzError> {
+        self.run(|device| device.is_asleep()).await
+    }
+
+    /// Wakes the device up and restores what it showed, see [Ajazz::wake_and_restore]
+    pub async fn wake_and_restore(&self) -> Result<(), AjazzError> {
+        self.run(|device| device.wake_and_restore()).await?
     }
 
     /// Make periodic events to the device, to keep it alive
     pub async fn keep_alive(&self) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.keep_alive())
+        self.run(move |device| device.keep_alive()).await?
+    }
+
+    /// Sets receiver of connection counters, see [Ajazz::set_metrics]
+    pub async fn set_metrics(
+        &self,
+        metrics: Option<Arc<dyn Metrics>>,
+    ) -> Result<(), AjazzError> {
+        self.run(move |device| device.set_metrics(metrics)).await
+    }
+
+    /// Checks that the device responds, returning latency, see [Ajazz::ping]
+    pub async fn ping(&self) -> Result<Duration, AjazzError> {
+        self.run(|device| device.ping()).await?
     }
 
     /// Shutdown the device
     pub async fn shutdown(&self) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.shutdown())
+        self.run(move |device| device.shutdown()).await?
     }
 
     /// Flushes the button's image to the device
     pub async fn flush(&self) -> Result<(), AjazzError> {
-        let device = self.device.lock().await;
-        block_in_place(move || device.flush())
+        self.run(move |device| device.flush()).await?
+    }
+
+    /// Flushes the button's image to the device, calling `progress` after every key is sent
+    pub async fn flush_with_progress(
+        &self,
+        progress: impl FnMut(FlushProgress) + Send + 'static,
+    ) -> Result<(), AjazzError> {
+        self.run(move |device| device.flush_with_progress(progress))
+            .await?
     }
 
     /// Returns button state reader for this device
@@ -203,7 +759,10 @@ impl AsyncAjazz {
             states: Mutex::new(DeviceState {
                 buttons: vec![false; self.kind.key_count() as usize],
                 encoders: vec![false; self.kind.encoder_count() as usize],
+                touch_key_range: self.kind.touch_keys(),
+                ..Default::default()
             }),
+            subscribers: Mutex::new(vec![]),
         })
     }
 }
@@ -212,15 +771,399 @@ impl AsyncAjazz {
 pub struct AsyncDeviceStateReader {
     device: AsyncAjazz,
     states: Mutex<DeviceState>,
+    subscribers: Mutex<Vec<UnboundedSender<Event>>>,
 }
 
 impl AsyncDeviceStateReader {
     /// Reads states and returns updates
     pub async fn read(&self, poll_rate: f32) -> Result<Vec<Event>, AjazzError> {
-        let input = self.device.read_input(poll_rate).await?;
+        let until_tick = self.states.lock().await.until_next_event(Instant::now());
+        let input = match until_tick {
+            // Reading input is cancel-safe, input arriving later is returned by the next read
+            Some(until_tick) => {
+                tokio::time::timeout(until_tick, self.device.read_input(poll_rate))
+                    .await
+                    .unwrap_or(Ok(AjazzInput::NoData))?
+            }
+            None => self.device.read_input(poll_rate).await?,
+        };
         let mut current_state = self.states.lock().await;
 
         let updates = handle_input_state_change(input, &mut current_state)?;
+        if current_state.wake_on_press && updates.iter().any(is_press) {
+            self.device
+                .run(|device| {
+                    if device.is_asleep() {
+                        device.wake_and_restore()
+                    } else {
+                        Ok(())
+                    }
+                })
+                .await??;
+        }
+        #[cfg(feature = "image")]
+        if let Some(mut feedback) = current_state.press_feedback.take() {
+            let (feedback, result) = {
+                let updates = updates.clone();
+                self.device
+                    .run(move |device| {
+                        let result = feedback.handle(device, &updates);
+                        (feedback, result)
+                    })
+                    .await?
+            };
+            current_state.press_feedback = Some(feedback);
+            result?;
+        }
+        if let Some(recorder) = current_state.recorder.as_mut() {
+            recorder.push(&updates, Instant::now());
+        }
+
+        let mut subscribers = self.subscribers.lock().await;
+        subscribers.retain(|subscriber| updates.iter().all(|u| subscriber.send(*u).is_ok()));
+
         Ok(updates)
     }
+
+    /// Reads until an event matching the predicate appears and returns it.
+    /// Returns `None` if the timeout passes first, waits forever if timeout is `None`.
+    /// Events that don't match are still delivered to subscribers
+    pub async fn wait_for(
+        &self,
+        mut predicate: impl FnMut(&Event) -> bool,
+        poll_rate: f32,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Event>, AjazzError> {
+        let wait = async {
+            loop {
+                let updates = self.read(poll_rate).await?;
+                if let Some(update) = updates.into_iter().find(|update| predicate(update)) {
+                    return Ok(update);
+                }
+            }
+        };
+
+        // Input that is not read yet stays queued on the device when the timeout passes
+        match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, wait).await {
+                Ok(update) => update.map(Some),
+                Err(_) => Ok(None),
+            },
+            None => wait.await.map(Some),
+        }
+    }
+
+    /// Waits until any button is pressed and returns its index,
+    /// see [AsyncDeviceStateReader::wait_for]
+    pub async fn wait_for_button(
+        &self,
+        poll_rate: f32,
+        timeout: Option<Duration>,
+    ) -> Result<Option<u8>, AjazzError> {
+        let update = self
+            .wait_for(|u| matches!(u, Event::ButtonDown(_)), poll_rate, timeout)
+            .await?;
+        Ok(update.and_then(|update| match update {
+            Event::ButtonDown(key) => Some(key),
+            _ => None,
+        }))
+    }
+
+    /// Waits until any encoder is pressed or twisted and returns the event,
+    /// see [AsyncDeviceStateReader::wait_for]
+    pub async fn wait_for_encoder(
+        &self,
+        poll_rate: f32,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Event>, AjazzError> {
+        self.wait_for(
+            |u| matches!(u, Event::EncoderDown(_) | Event::EncoderTwist(..)),
+            poll_rate,
+            timeout,
+        )
+        .await
+    }
+
+    /// Returns receiver that gets every event read by this reader, no matter who calls
+    /// [AsyncDeviceStateReader::read]. Lets several tasks observe input
+    /// while a single loop reads it
+    pub async fn subscribe(&self) -> UnboundedReceiver<Event> {
+        let (sender, receiver) = unbounded_channel();
+        self.subscribers.lock().await.push(sender);
+        receiver
+    }
+
+    /// Enables tracking of absolute encoder positions,
+    /// which are reported as [Event::EncoderPositionChanged]
+    pub async fn enable_encoder_tracking(&self, tracker: EncoderTracker) {
+        let mut current_state = self.states.lock().await;
+        current_state.encoder_tracker = Some(tracker);
+    }
+
+    /// Returns absolute position of the encoder, if encoder tracking is enabled
+    pub async fn encoder_position(&self, encoder: u8) -> Option<i32> {
+        let current_state = self.states.lock().await;
+        current_state
+            .encoder_tracker
+            .as_ref()
+            .and_then(|tracker| tracker.position(encoder))
+    }
+
+    /// Doesn't report button state changes happening sooner than `debounce` after
+    /// the reported one, see [DeviceStateReader::set_debounce]
+    ///
+    /// [DeviceStateReader::set_debounce]: crate::DeviceStateReader::set_debounce
+    pub async fn set_debounce(&self, debounce: Duration) {
+        let mut current_state = self.states.lock().await;
+        // Setting debounce of every key can't fail
+        let _ = current_state.set_debounce(None, debounce);
+    }
+
+    /// Sets debounce of the single key, see [AsyncDeviceStateReader::set_debounce]
+    pub async fn set_key_debounce(
+        &self,
+        key: u8,
+        debounce: Duration,
+    ) -> Result<(), AjazzError> {
+        let mut current_state = self.states.lock().await;
+        current_state.set_debounce(Some(key), debounce)
+    }
+
+    /// Registers timer reported as [Event::Tick] with the returned id every interval,
+    /// see [AsyncDeviceStateReader::read]. Reads return early when a timer is due
+    pub async fn every(&self, interval: Duration) -> u32 {
+        let mut current_state = self.states.lock().await;
+        current_state.timers.add(interval, Instant::now())
+    }
+
+    /// Removes timer registered with [AsyncDeviceStateReader::every].
+    /// Returns false if there was no such timer
+    pub async fn cancel_timer(&self, id: u32) -> bool {
+        let mut current_state = self.states.lock().await;
+        current_state.timers.remove(id)
+    }
+
+    /// Sets predicate deciding which events are returned, other events are dropped
+    pub async fn set_filter(&self, filter: impl Fn(&Event) -> bool + Send + Sync + 'static) {
+        let mut current_state = self.states.lock().await;
+        current_state.filter = Some(Box::new(filter));
+    }
+
+    /// Makes held keys show darkened or highlighted version of their image, or disables it
+    /// with `None`, see [DeviceStateReader::set_press_feedback]
+    ///
+    /// [DeviceStateReader::set_press_feedback]: crate::DeviceStateReader::set_press_feedback
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub async fn set_press_feedback(&self, feedback: Option<PressFeedback>) {
+        let mut current_state = self.states.lock().await;
+        current_state.press_feedback = feedback.map(PressFeedbackState::new);
+    }
+
+    /// Wakes the device up when something is pressed after sleep, see
+    /// [DeviceStateReader::set_wake_on_press]
+    ///
+    /// [DeviceStateReader::set_wake_on_press]: crate::DeviceStateReader::set_wake_on_press
+    pub async fn set_wake_on_press(&self, enabled: bool) {
+        self.states.lock().await.wake_on_press = enabled;
+    }
+
+    /// Reports [Event::Idle] after nothing was pressed for the timeout, see
+    /// [DeviceStateReader::set_idle_timeout]
+    ///
+    /// [DeviceStateReader::set_idle_timeout]: crate::DeviceStateReader::set_idle_timeout
+    pub async fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        let now = Instant::now();
+        self.states.lock().await.idle = timeout.map(|timeout| IdleTracker::new(timeout, now));
+    }
+
+    /// Starts recording events read by this reader, see [DeviceStateReader::record]
+    ///
+    /// [DeviceStateReader::record]: crate::DeviceStateReader::record
+    pub async fn record(&self) {
+        self.states.lock().await.recorder = Some(Recorder::new());
+    }
+
+    /// Stops recording and returns the recorded events, or `None` if nothing was recorded
+    pub async fn stop_recording(&self) -> Option<InputLog> {
+        self.states
+            .lock()
+            .await
+            .recorder
+            .take()
+            .map(Recorder::finish)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codes;
+    use crate::replay::ReplayTransport;
+
+    #[test]
+    fn test_urgent_commands_skip_queue() {
+        let device = AsyncAjazz::from_device(Ajazz::from_transport(
+            Kind::Akp03,
+            ReplayTransport::default(),
+        ));
+        let (release, blocked) = std::sync::mpsc::channel::<()>();
+        let (done, finished) = std::sync::mpsc::channel::<&str>();
+
+        device
+            .send(Priority::Normal, Box::new(move |_| blocked.recv().unwrap()))
+            .unwrap();
+        for (priority, name) in [
+            (Priority::Normal, "wallpaper"),
+            (Priority::Normal, "flush"),
+            (Priority::Urgent, "brightness"),
+        ] {
+            let done = done.clone();
+            let command: Command = Box::new(move |_| done.send(name).unwrap());
+            device.send(priority, command).unwrap();
+        }
+        release.send(()).unwrap();
+
+        let order: Vec<&str> = finished.iter().take(3).collect();
+        assert_eq!(order, ["brightness", "wallpaper", "flush"]);
+    }
+
+    #[tokio::test]
+    async fn test_commands_run_in_order() {
+        let replay = ReplayTransport::default();
+        let device =
+            AsyncAjazz::from_device(Ajazz::from_transport(Kind::Akp03, replay.clone()));
+        let clone = device.clone();
+
+        device.set_brightness(50).await.unwrap();
+        clone.sleep().await.unwrap();
+        device.wake().await.unwrap();
+
+        let commands: Vec<Vec<u8>> =
+            replay.written().iter().map(|p| p[6..9].to_vec()).collect();
+        assert_eq!(commands, [b"DIS", b"LIG", b"HAN", b"DIS"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_shutdown_token_stops_reads() {
+        let device = AsyncAjazz::from_device(Ajazz::from_transport(
+            Kind::Akp03,
+            ReplayTransport::default(),
+        ));
+        let reader = device.get_reader();
+
+        let token = device.clone().shutdown_token();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(20)).await;
+            token.cancel();
+        });
+
+        // Polling every second, the read returns only because of the shutdown
+        let read = tokio::time::timeout(Duration::from_millis(500), reader.read(1.0)).await;
+        assert!(matches!(read, Ok(Err(AjazzError::ShutDown))));
+        assert!(matches!(
+            device.read_input(1.0).await,
+            Err(AjazzError::ShutDown)
+        ));
+
+        // Commands still run, so the device can be cleaned up
+        device.set_brightness(10).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_application_token_stops_reads() {
+        let application = CancellationToken::new();
+        let device = AsyncAjazz::from_device_with_shutdown(
+            Ajazz::from_transport(Kind::Akp03, ReplayTransport::default()),
+            application.child_token(),
+        );
+
+        application.cancel();
+        assert!(device.shutdown_token().is_cancelled());
+        assert!(matches!(
+            device.read_input(1.0).await,
+            Err(AjazzError::ShutDown)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_clones_never_interleave_images() {
+        let replay = ReplayTransport::default();
+        let device =
+            AsyncAjazz::from_device(Ajazz::from_transport(Kind::Akp03, replay.clone()));
+
+        let tasks: Vec<_> = [0xAAu8, 0xBB]
+            .into_iter()
+            .enumerate()
+            .map(|(key, byte)| {
+                let device = device.clone();
+                tokio::spawn(async move {
+                    for _ in 0..10 {
+                        device
+                            .exclusive(move |device| {
+                                device.set_button_image_data(key as u8, vec![byte; 3000])?;
+                                device.flush()
+                            })
+                            .await
+                            .unwrap()
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Every image is announced and followed by its own data reports only
+        let written = replay.written();
+        let mut images = vec![];
+        for packet in written.iter() {
+            match &packet[6..9] {
+                b"BAT" => images.push(vec![]),
+                b"STP" | b"DIS" => {}
+                _ => images.last_mut().unwrap().push(packet[1]),
+            }
+        }
+        assert_eq!(images.len(), 20);
+        assert!(images
+            .iter()
+            .all(|image| image.iter().all(|b| *b == image[0])));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "akp03")]
+    async fn test_read_can_be_cancelled() {
+        let replay = ReplayTransport::default();
+        let device =
+            AsyncAjazz::from_device(Ajazz::from_transport(Kind::Akp03, replay.clone()));
+        let reader = device.get_reader();
+
+        let read = tokio::time::timeout(Duration::from_millis(50), reader.read(100.0)).await;
+        assert!(read.is_err());
+
+        let mut report = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        report[codes::OFFSET_DATA_LENGTH] = 0x41;
+        report[codes::OFFSET_ACTION_CODE] = 0x01;
+        replay.push_read(&report);
+
+        let updates = reader.read(100.0).await.unwrap();
+        assert_eq!(updates, [Event::ButtonDown(0)]);
+    }
+
+    #[test]
+    fn test_hotplug_events() {
+        let device = |kind, serial: &str| (kind, serial.to_string());
+        let known = HashSet::from([device(Kind::Akp03, "a"), device(Kind::Akp153, "b")]);
+        let current = HashSet::from([device(Kind::Akp153, "b"), device(Kind::Akp815, "c")]);
+
+        assert_eq!(
+            hotplug_events(&known, &current),
+            [
+                HotplugEvent::Disconnected(Kind::Akp03, "a".to_string()),
+                HotplugEvent::Connected(Kind::Akp815, "c".to_string()),
+            ]
+        );
+        assert!(hotplug_events(&current, &current).is_empty());
+    }
 }
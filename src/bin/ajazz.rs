@@ -0,0 +1,164 @@
+//! Command line tool for Ajazz devices, built on the public API of the crate
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ajazz_sdk::{list_devices, new_hidapi, write_json_lines, Ajazz, AjazzError, Event, Kind};
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(name = "ajazz", version, about = "Control Ajazz stream controllers")]
+struct Cli {
+    /// Serial number of the device, the first connected device is used if omitted
+    #[arg(short, long, global = true)]
+    serial: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List connected devices
+    List,
+    /// Print information about the device
+    Info,
+    /// Show image from the file on the key
+    SetImage {
+        /// Index of the key
+        key: u8,
+        /// Path of the image file
+        path: PathBuf,
+    },
+    /// Set brightness of the device, 0 - 100
+    SetBrightness {
+        /// Brightness in percent
+        percent: u8,
+    },
+    /// Clear the key image, or every key image if no key is given
+    Clear {
+        /// Index of the key
+        key: Option<u8>,
+    },
+    /// Print input events until interrupted
+    WatchEvents {
+        /// Print events as JSON, one per line
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let hidapi = new_hidapi()?;
+    let devices = list_devices(&hidapi);
+
+    if let Command::List = cli.command {
+        for (kind, serial) in devices {
+            println!("{serial}\t{kind}\t{}", kind.display_name());
+        }
+        return Ok(());
+    }
+
+    let (kind, serial) = select_device(devices, cli.serial.as_deref())?;
+    let device = Ajazz::connect(&hidapi, kind, &serial)?;
+
+    match cli.command {
+        Command::List => unreachable!("devices are listed before connecting"),
+        Command::Info => {
+            println!("Kind:       {kind} ({})", kind.display_name());
+            println!("Serial:     {serial}");
+            println!("Firmware:   {}", device.firmware_version()?);
+            println!(
+                "Keys:       {} ({} with display)",
+                device.key_count(),
+                device.display_key_count()
+            );
+            println!(
+                "Layout:     {}x{}",
+                device.row_count(),
+                device.column_count()
+            );
+            println!("Encoders:   {}", kind.encoder_count());
+            println!("Ping:       {:?}", device.ping()?);
+        }
+        Command::SetImage { key, path } => {
+            let image = image::open(path)?;
+            device.set_button_image(key, image)?;
+            device.flush()?;
+        }
+        Command::SetBrightness { percent } => device.set_brightness(percent)?,
+        Command::Clear { key } => {
+            match key {
+                Some(key) => device.clear_button_image(key)?,
+                None => device.clear_all_button_images()?,
+            }
+            device.flush()?;
+        }
+        Command::WatchEvents { json } => watch_events(device, json)?,
+    }
+
+    Ok(())
+}
+
+/// Returns the device with the serial, or the first one if no serial is given
+fn select_device(
+    devices: Vec<(Kind, String)>,
+    serial: Option<&str>,
+) -> Result<(Kind, String), AjazzError> {
+    let found = match serial {
+        Some(serial) => devices.into_iter().find(|(_, s)| s == serial),
+        None => devices.into_iter().next(),
+    };
+
+    found.ok_or_else(|| AjazzError::UnknownDevice(serial.unwrap_or("<any>").to_string()))
+}
+
+#[allow(clippy::arc_with_non_send_sync)]
+fn watch_events(device: Ajazz, json: bool) -> Result<(), Box<dyn Error>> {
+    let device = Arc::new(device);
+    let reader = device.get_reader();
+
+    loop {
+        let events = reader.read(Some(Duration::from_secs(1)))?;
+        if json {
+            write_json_lines(&mut std::io::stdout().lock(), &events)?;
+        } else {
+            for event in events {
+                println!("{}", describe(event));
+            }
+        }
+    }
+}
+
+/// Returns human readable description of the event
+fn describe(event: Event) -> String {
+    match event {
+        Event::ButtonDown(key) => format!("Key {key} down"),
+        Event::ButtonUp(key) => format!("Key {key} up"),
+        Event::TouchKeyDown(key) => format!("Touch key {key} down"),
+        Event::TouchKeyUp(key) => format!("Touch key {key} up"),
+        Event::EncoderDown(encoder) => format!("Encoder {encoder} down"),
+        Event::EncoderUp(encoder) => format!("Encoder {encoder} up"),
+        Event::EncoderTwist(encoder, ticks) => format!("Encoder {encoder} twisted by {ticks}"),
+        Event::EncoderPositionChanged(encoder, position) => {
+            format!("Encoder {encoder} moved to {position}")
+        }
+        Event::Tick(timer) => format!("Timer {timer} fired"),
+        Event::Idle(inactive) => format!("Idle for {inactive:?}"),
+        Event::Active => "Active again".to_string(),
+    }
+}
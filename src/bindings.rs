@@ -0,0 +1,196 @@
+use crate::{AjazzError, DeviceStateReader, Event};
+
+/// Which events the handler is called for
+enum Trigger {
+    ButtonDown(u8),
+    ButtonUp(u8),
+    TouchKeyDown(u8),
+    TouchKeyUp(u8),
+    EncoderDown(u8),
+    EncoderUp(u8),
+    EncoderTwist(u8),
+    Tick(u32),
+    Any,
+}
+
+impl Trigger {
+    fn matches(&self, event: &Event) -> bool {
+        match (self, event) {
+            (Trigger::ButtonDown(key), Event::ButtonDown(index))
+            | (Trigger::ButtonUp(key), Event::ButtonUp(index))
+            | (Trigger::TouchKeyDown(key), Event::TouchKeyDown(index))
+            | (Trigger::TouchKeyUp(key), Event::TouchKeyUp(index))
+            | (Trigger::EncoderDown(key), Event::EncoderDown(index))
+            | (Trigger::EncoderUp(key), Event::EncoderUp(index))
+            | (Trigger::EncoderTwist(key), Event::EncoderTwist(index, _)) => key == index,
+            (Trigger::Tick(timer), Event::Tick(id)) => timer == id,
+            (Trigger::Any, _) => true,
+            _ => false,
+        }
+    }
+}
+
+type Handler = Box<dyn FnMut(&Event)>;
+
+/// Closures bound to keys and encoders, called for events read from the device.
+///
+/// Handlers are called on the thread running [Bindings::run] in order of registration,
+/// so they don't need to be `Send` and can use the device directly
+///
+/// ```no_run
+/// # fn example(device: std::sync::Arc<ajazz_sdk::Ajazz>) -> Result<(), ajazz_sdk::AjazzError> {
+/// let mut bindings = ajazz_sdk::Bindings::new();
+///
+/// let deck = device.clone();
+/// bindings.on_button_down(3, move || {
+///     let _ = deck.set_brightness(100);
+/// });
+/// bindings.on_encoder_twist(0, |ticks| println!("volume {ticks:+}"));
+///
+/// bindings.run(&device.get_reader())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Bindings {
+    handlers: Vec<(Trigger, Handler)>,
+}
+
+impl Bindings {
+    /// Creates bindings without handlers
+    pub fn new() -> Bindings {
+        Bindings::default()
+    }
+
+    /// Calls the handler when the button gets pressed down
+    pub fn on_button_down(
+        &mut self,
+        key: u8,
+        mut handler: impl FnMut() + 'static,
+    ) -> &mut Self {
+        self.bind(Trigger::ButtonDown(key), move |_| handler())
+    }
+
+    /// Calls the handler when the button gets released
+    pub fn on_button_up(&mut self, key: u8, mut handler: impl FnMut() + 'static) -> &mut Self {
+        self.bind(Trigger::ButtonUp(key), move |_| handler())
+    }
+
+    /// Calls the handler when the touch key gets pressed down, see [Event::TouchKeyDown]
+    pub fn on_touch_key_down(
+        &mut self,
+        key: u8,
+        mut handler: impl FnMut() + 'static,
+    ) -> &mut Self {
+        self.bind(Trigger::TouchKeyDown(key), move |_| handler())
+    }
+
+    /// Calls the handler when the touch key gets released, see [Event::TouchKeyUp]
+    pub fn on_touch_key_up(
+        &mut self,
+        key: u8,
+        mut handler: impl FnMut() + 'static,
+    ) -> &mut Self {
+        self.bind(Trigger::TouchKeyUp(key), move |_| handler())
+    }
+
+    /// Calls the handler when the encoder gets pressed down
+    pub fn on_encoder_down(
+        &mut self,
+        encoder: u8,
+        mut handler: impl FnMut() + 'static,
+    ) -> &mut Self {
+        self.bind(Trigger::EncoderDown(encoder), move |_| handler())
+    }
+
+    /// Calls the handler when the encoder gets released
+    pub fn on_encoder_up(
+        &mut self,
+        encoder: u8,
+        mut handler: impl FnMut() + 'static,
+    ) -> &mut Self {
+        self.bind(Trigger::EncoderUp(encoder), move |_| handler())
+    }
+
+    /// Calls the handler with amount of ticks when the encoder gets twisted
+    pub fn on_encoder_twist(
+        &mut self,
+        encoder: u8,
+        mut handler: impl FnMut(i8) + 'static,
+    ) -> &mut Self {
+        self.bind(Trigger::EncoderTwist(encoder), move |event| {
+            if let Event::EncoderTwist(_, ticks) = event {
+                handler(*ticks)
+            }
+        })
+    }
+
+    /// Calls the handler when the timer registered with [DeviceStateReader::every] fires
+    pub fn on_tick(&mut self, timer: u32, mut handler: impl FnMut() + 'static) -> &mut Self {
+        self.bind(Trigger::Tick(timer), move |_| handler())
+    }
+
+    /// Calls the handler for every event, e.g. for encoder positions or idleness
+    pub fn on_event(&mut self, handler: impl FnMut(&Event) + 'static) -> &mut Self {
+        self.bind(Trigger::Any, handler)
+    }
+
+    /// Calls handlers bound to the event. Returns amount of called handlers
+    pub fn dispatch(&mut self, event: &Event) -> usize {
+        let mut called = 0;
+        for (trigger, handler) in self.handlers.iter_mut() {
+            if trigger.matches(event) {
+                handler(event);
+                called += 1;
+            }
+        }
+        called
+    }
+
+    /// Reads events from the reader and dispatches them to handlers.
+    /// Returns only when reading fails, e.g. when the device gets disconnected
+    pub fn run(&mut self, reader: &DeviceStateReader) -> Result<(), AjazzError> {
+        loop {
+            for event in reader.read(None)? {
+                self.dispatch(&event);
+            }
+        }
+    }
+
+    fn bind(&mut self, trigger: Trigger, handler: impl FnMut(&Event) + 'static) -> &mut Self {
+        self.handlers.push((trigger, Box::new(handler)));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn test_dispatch_calls_bound_handlers() {
+        let calls = Rc::new(RefCell::new(vec![]));
+        let mut bindings = Bindings::new();
+
+        let log = calls.clone();
+        bindings.on_button_down(3, move || log.borrow_mut().push("down 3"));
+        let log = calls.clone();
+        bindings.on_encoder_twist(1, move |ticks| {
+            log.borrow_mut()
+                .push(if ticks > 0 { "right" } else { "left" })
+        });
+        let log = calls.clone();
+        bindings.on_event(move |_| log.borrow_mut().push("any"));
+
+        assert_eq!(bindings.dispatch(&Event::ButtonDown(3)), 2);
+        assert_eq!(bindings.dispatch(&Event::ButtonUp(3)), 1);
+        assert_eq!(bindings.dispatch(&Event::EncoderTwist(0, 1)), 1);
+        assert_eq!(bindings.dispatch(&Event::EncoderTwist(1, -2)), 2);
+        assert_eq!(
+            *calls.borrow(),
+            ["down 3", "any", "any", "any", "left", "any"]
+        );
+    }
+}
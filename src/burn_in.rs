@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+/// Settings of the burn-in protection for 24/7 use, see [FramePump::set_burn_in_protection].
+///
+/// Key images are periodically shifted by a pixel around their original position,
+/// and dimmed for a short time at long intervals, so static pixels don't wear out
+/// the OLED keys.
+///
+/// [FramePump::set_burn_in_protection]: crate::FramePump::set_burn_in_protection
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BurnInProtection {
+    /// How often images are moved to the next position
+    pub shift_interval: Duration,
+    /// Largest distance in pixels from the original position
+    pub max_shift: u32,
+    /// How often images are dimmed
+    pub dim_interval: Duration,
+    /// How long images stay dimmed
+    pub dim_duration: Duration,
+    /// Brightness of dimmed images, from 0.0 to 1.0
+    pub dim_level: f32,
+}
+
+impl Default for BurnInProtection {
+    fn default() -> Self {
+        Self {
+            shift_interval: Duration::from_secs(10 * 60),
+            max_shift: 2,
+            dim_interval: Duration::from_secs(60 * 60),
+            dim_duration: Duration::from_secs(60),
+            dim_level: 0.5,
+        }
+    }
+}
+
+/// State of the protection at some point in time, images are re-sent when it changes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Phase {
+    shift: (i32, i32),
+    dimmed: bool,
+}
+
+impl BurnInProtection {
+    /// Returns phase of the protection after `elapsed` time since it was enabled
+    pub(crate) fn phase(&self, elapsed: Duration) -> Phase {
+        let side = 2 * self.max_shift as u64 + 1;
+        let step = (elapsed.as_millis() / self.shift_interval.as_millis().max(1)) as u64;
+        let position = step % (side * side);
+
+        // Walking rows back and forth, so every move is a single pixel
+        let row = position / side;
+        let column = if row.is_multiple_of(2) {
+            position % side
+        } else {
+            side - 1 - position % side
+        };
+        let max_shift = self.max_shift as i32;
+
+        Phase {
+            shift: (column as i32 - max_shift, row as i32 - max_shift),
+            dimmed: self.dim_offset(elapsed) < self.dim_duration,
+        }
+    }
+
+    /// Returns time left until the phase changes
+    pub(crate) fn until_next_phase(&self, elapsed: Duration) -> Duration {
+        let shift_interval = self.shift_interval.max(Duration::from_millis(1));
+        let to_shift = shift_interval - nanos_rem(elapsed, shift_interval);
+
+        let offset = self.dim_offset(elapsed);
+        let to_dim = if offset < self.dim_duration {
+            self.dim_duration - offset
+        } else {
+            self.dim_interval.saturating_sub(offset)
+        };
+
+        to_shift.min(to_dim).max(Duration::from_millis(1))
+    }
+
+    /// Returns the image altered according to the phase
+    pub(crate) fn apply(&self, image: &DynamicImage, phase: Phase) -> DynamicImage {
+        let source = image.to_rgba8();
+        let mut shifted =
+            RgbaImage::from_pixel(source.width(), source.height(), Rgba([0, 0, 0, 255]));
+        imageops::overlay(
+            &mut shifted,
+            &source,
+            i64::from(phase.shift.0),
+            i64::from(phase.shift.1),
+        );
+
+        if phase.dimmed {
+            let level = self.dim_level.clamp(0.0, 1.0);
+            for pixel in shifted.pixels_mut() {
+                for channel in pixel.0.iter_mut().take(3) {
+                    *channel = (f32::from(*channel) * level) as u8;
+                }
+            }
+        }
+
+        DynamicImage::ImageRgba8(shifted)
+    }
+
+    fn dim_offset(&self, elapsed: Duration) -> Duration {
+        if self.dim_interval.is_zero() {
+            return Duration::MAX;
+        }
+
+        nanos_rem(elapsed, self.dim_interval)
+    }
+}
+
+fn nanos_rem(value: Duration, interval: Duration) -> Duration {
+    Duration::from_nanos((value.as_nanos() % interval.as_nanos()) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_walks_single_pixels() {
+        let protection = BurnInProtection {
+            shift_interval: Duration::from_secs(1),
+            max_shift: 1,
+            dim_interval: Duration::from_secs(100),
+            dim_duration: Duration::from_secs(10),
+            dim_level: 0.5,
+        };
+
+        let phases: Vec<Phase> = (0..10)
+            .map(|s| protection.phase(Duration::from_secs(s)))
+            .collect();
+        assert_eq!(phases[0].shift, (-1, -1));
+        assert_eq!(phases[3].shift, (1, 0));
+        assert_eq!(phases[9], phases[0]);
+        for pair in phases.windows(2) {
+            let (a, b) = (pair[0].shift, pair[1].shift);
+            assert!((a.0 - b.0).abs() + (a.1 - b.1).abs() <= 1 || b == phases[0].shift);
+        }
+
+        assert!(phases[5].dimmed);
+        assert!(!protection.phase(Duration::from_secs(50)).dimmed);
+        assert_eq!(
+            protection.until_next_phase(Duration::from_millis(9_500)),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn test_apply_shifts_and_dims() {
+        let protection = BurnInProtection::default();
+        let image =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([200, 100, 50, 255])));
+
+        let altered = protection
+            .apply(
+                &image,
+                Phase {
+                    shift: (1, 0),
+                    dimmed: true,
+                },
+            )
+            .to_rgba8();
+        assert_eq!(altered.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(altered.get_pixel(1, 0).0, [100, 50, 25, 255]);
+    }
+}
@@ -0,0 +1,289 @@
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::device::Ajazz;
+use crate::protocol::{codes, AjazzRequestBuilder};
+use crate::AjazzError;
+
+/// Brightness of the separately lit parts of the device, in percent,
+/// see [Ajazz::set_brightness_zones]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BrightnessZones {
+    /// Brightness of the key displays
+    pub keys: u8,
+    /// Brightness of the LCD strip
+    pub strip: u8,
+}
+
+/// Device commands
+impl Ajazz {
+    /// Sleeps the device
+    pub fn sleep(&self) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        let packet = self.kind().sleep_packet();
+        self.write_packet(&packet)?;
+        self.asleep.store(true, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Wakes the device up after [sleep](Ajazz::sleep)
+    pub fn wake(&self) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        let packet = self.kind().initialize_packet();
+        self.write_packet(&packet)?;
+        self.asleep.store(false, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Returns true if the device was put to sleep and wasn't woken up since
+    pub fn is_asleep(&self) -> bool {
+        self.asleep.load(Ordering::Acquire)
+    }
+
+    /// Wakes the device up and sends again images, brightness and logo shown before
+    /// the sleep, as the display doesn't always keep them
+    pub fn wake_and_restore(&self) -> Result<(), AjazzError> {
+        self.wake()?;
+        self.apply_state(&self.export_state())
+    }
+
+    /// Make periodic events to the device, to keep it alive
+    pub fn keep_alive(&self) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        let packet = self.kind().keep_alive_packet();
+        self.write_packet(&packet)?;
+
+        Ok(())
+    }
+
+    /// Sends the keep-alive packet and waits for the device to respond, returning
+    /// how long it took. Fails with [AjazzError::NoAck] if there's no response within
+    /// the [command timeout](Ajazz::set_command_timeout), e.g. when the device was
+    /// unplugged from a hub
+    pub fn ping(&self) -> Result<Duration, AjazzError> {
+        self.initialize()?;
+
+        let started = Instant::now();
+        let packet = self.kind().keep_alive_packet();
+        self.write_packet(&packet)?;
+        self.assert_write_complete()?;
+
+        Ok(started.elapsed())
+    }
+
+    /// Shutdown the device
+    pub fn shutdown(&self) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        let packet = self.kind().shutdown_packet();
+        self.write_packet(&packet)?;
+
+        let packet = self.kind().sleep_packet();
+        self.write_packet(&packet)?;
+
+        Ok(())
+    }
+
+    /// Resets the device
+    pub fn reset(&self) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        self.set_brightness(100)?;
+        self.clear_all_button_images()
+    }
+
+    /// Sets brightness of the device, value range is 0 - 100.
+    /// Does nothing if the brightness was already set to the value by this instance
+    pub fn set_brightness(&self, percent: u8) -> Result<(), AjazzError> {
+        if self.get_brightness() == Some(percent) {
+            return Ok(());
+        }
+
+        self.write_brightness(percent)
+    }
+
+    /// Sends the brightness to the device, even if it didn't change
+    pub(crate) fn write_brightness(&self, percent: u8) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        let buf = self.kind().brightness_packet(percent);
+        self.write_verified_packet(&buf)?;
+        self.record_brightness(percent);
+
+        Ok(())
+    }
+
+    /// Sets brightness of keys and the strip separately, value range is 0 - 100.
+    ///
+    /// Devices without [brightness zones](crate::Kind::has_brightness_zones), which is
+    /// every device for now, only take the same brightness for both and return
+    /// [AjazzError::UnsupportedOperation] otherwise
+    pub fn set_brightness_zones(&self, zones: BrightnessZones) -> Result<(), AjazzError> {
+        if zones.keys != zones.strip {
+            return Err(AjazzError::UnsupportedOperation);
+        }
+
+        self.set_brightness(zones.keys)
+    }
+
+    /// Sets button's image to blank, changes must be flushed with `.flush()` before
+    /// they will appear on the device!
+    pub fn clear_button_image(&self, key: u8) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        let packet = self.kind().clear_button_image_packet(key);
+        self.write_verified_packet(&packet)?;
+        self.record_cleared_key(key);
+
+        Ok(())
+    }
+
+    /// Sets blank images to every button, changes must be flushed with `.flush()` before
+    /// they will appear on the device!
+    pub fn clear_all_button_images(&self) -> Result<(), AjazzError> {
+        self.initialize()?;
+        self.clear_button_image(codes::CMD_CLEAR_ALL)?;
+
+        if self.kind().is_v2_api() {
+            // Mirabox "v2" requires flush to commit clearing the background
+            let packet = self.kind().flush_packet();
+            self.write_verified_packet(&packet)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets blank images to the listed buttons using as few commands as possible.
+    /// Clearing every display key is sent as a single command, and "v2" devices
+    /// get a single commit after all keys are cleared
+    pub fn clear_button_images(&self, keys: &[u8]) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+
+        for key in &keys {
+            self.check_display_key(*key)?;
+        }
+
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        if keys.len() == self.display_key_count() as usize {
+            return self.clear_all_button_images();
+        }
+
+        for key in keys {
+            let packet = self.kind().clear_button_image_packet(key);
+            self.write_verified_packet(&packet)?;
+            self.record_cleared_key(key);
+        }
+
+        if self.kind().is_v2_api() {
+            let packet = self.kind().flush_packet();
+            self.write_verified_packet(&packet)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayTransport;
+    use crate::Kind;
+
+    fn commands(written: &[Vec<u8>]) -> Vec<(&[u8], u8)> {
+        written.iter().map(|p| (&p[6..9], p[12])).collect()
+    }
+
+    #[test]
+    fn test_brightness_zones() {
+        let zones = BrightnessZones {
+            keys: 80,
+            strip: 80,
+        };
+        for kind in [Kind::Akp153, Kind::Akp03] {
+            let replay = ReplayTransport::default();
+            let device = Ajazz::from_transport(kind, replay.clone());
+            assert!(matches!(
+                device.set_brightness_zones(BrightnessZones { strip: 20, ..zones }),
+                Err(AjazzError::UnsupportedOperation)
+            ));
+            assert!(replay.written().is_empty());
+
+            device.set_brightness_zones(zones).unwrap();
+            let written = replay.written();
+            assert_eq!(commands(&written)[1..], [(b"LIG".as_slice(), 0)]);
+            assert_eq!(written[1][11], 80);
+            assert_eq!(device.get_brightness(), Some(80));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "akp153")]
+    fn test_clear_button_images() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.clear_button_images(&[4, 1, 4]).unwrap();
+        assert_eq!(
+            commands(&replay.written()),
+            [
+                (b"DIS".as_slice(), 0),
+                (b"CLE", 2),
+                (b"CLE", 5),
+                (b"STP", 0)
+            ]
+        );
+
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp153, replay.clone());
+        device.clear_button_images(&[0]).unwrap();
+        assert_eq!(
+            commands(&replay.written()),
+            [(b"DIS".as_slice(), 0), (b"CLE", 13)]
+        );
+    }
+
+    #[test]
+    fn test_ping() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.set_command_timeout(Duration::from_millis(1));
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        assert!(device.ping().is_ok());
+        assert_eq!(&replay.written()[1][6..9], b"CON");
+
+        assert!(matches!(device.ping(), Err(AjazzError::NoAck)));
+    }
+
+    #[test]
+    fn test_clear_every_button_image() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03R, replay.clone());
+        device.clear_button_images(&[5, 4, 3, 2, 1, 0]).unwrap();
+        assert_eq!(
+            commands(&replay.written()),
+            [(b"DIS".as_slice(), 0), (b"CLE", 0xff), (b"STP", 0)]
+        );
+    }
+
+    #[test]
+    fn test_clear_button_images_validates_keys() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03R, replay.clone());
+        assert!(matches!(
+            device.clear_button_images(&[0, 6]),
+            Err(AjazzError::NoScreenOnKey(6))
+        ));
+        assert_eq!(replay.written().len(), 1);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::{AjazzError, Event, Kind};
+
+/// Command sent by a client of the daemon, one per line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DaemonCommand {
+    /// Lists connected devices
+    Devices,
+    /// Shows image from the file on the key and flushes it
+    SetImage {
+        /// Serial number of the device
+        serial: String,
+        /// Index of the key
+        key: u8,
+        /// Path of the image file, read by the daemon
+        path: PathBuf,
+    },
+    /// Sets brightness of the device, value range is 0 - 100
+    SetBrightness {
+        /// Serial number of the device
+        serial: String,
+        /// Brightness in percent
+        percent: u8,
+    },
+    /// Starts sending hot-plug and input events to the client
+    Subscribe,
+}
+
+impl FromStr for DaemonCommand {
+    type Err = AjazzError;
+
+    /// Parses command line, the image path is the rest of the line, so it may have spaces
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || AjazzError::InvalidCommand(line.to_string());
+        let line = line.trim();
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+
+        match name {
+            "devices" if args.is_empty() => Ok(DaemonCommand::Devices),
+            "subscribe" if args.is_empty() => Ok(DaemonCommand::Subscribe),
+            "image" => {
+                let mut args = args.trim_start().splitn(3, ' ');
+                let serial = args.next().filter(|s| !s.is_empty()).ok_or_else(invalid)?;
+                let key = args
+                    .next()
+                    .and_then(|k| k.parse().ok())
+                    .ok_or_else(invalid)?;
+                let path = args.next().filter(|p| !p.is_empty()).ok_or_else(invalid)?;
+
+                Ok(DaemonCommand::SetImage {
+                    serial: serial.to_string(),
+                    key,
+                    path: PathBuf::from(path),
+                })
+            }
+            "brightness" => match args.split_whitespace().collect::<Vec<_>>()[..] {
+                [serial, percent] => Ok(DaemonCommand::SetBrightness {
+                    serial: serial.to_string(),
+                    percent: percent.parse().map_err(|_| invalid())?,
+                }),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Line sent by the daemon to a client
+#[derive(Clone, Debug, PartialEq)]
+pub enum DaemonMessage {
+    /// Command succeeded
+    Ok,
+    /// Command failed with the message
+    Error(String),
+    /// Connected device, listed in response to [DaemonCommand::Devices]
+    Device(Kind, String),
+    /// Device was connected, sent to subscribers
+    Connected(Kind, String),
+    /// Device was disconnected, sent to subscribers
+    Disconnected(Kind, String),
+    /// Input was read from the device with the serial, sent to subscribers
+    Event(String, Event),
+}
+
+impl Display for DaemonMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonMessage::Ok => f.write_str("ok"),
+            // Message must stay on its line
+            DaemonMessage::Error(message) => write!(f, "error {}", message.replace('\n', " ")),
+            DaemonMessage::Device(kind, serial) => write!(f, "device {kind} {serial}"),
+            DaemonMessage::Connected(kind, serial) => write!(f, "connected {kind} {serial}"),
+            DaemonMessage::Disconnected(kind, serial) => {
+                write!(f, "disconnected {kind} {serial}")
+            }
+            DaemonMessage::Event(serial, event) => write!(f, "event {serial} {event:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            "devices".parse::<DaemonCommand>().unwrap(),
+            DaemonCommand::Devices
+        );
+        assert_eq!(
+            " subscribe\n".parse::<DaemonCommand>().unwrap(),
+            DaemonCommand::Subscribe
+        );
+        assert_eq!(
+            "image ABC123 4 /tmp/my icon.png"
+                .parse::<DaemonCommand>()
+                .unwrap(),
+            DaemonCommand::SetImage {
+                serial: "ABC123".to_string(),
+                key: 4,
+                path: PathBuf::from("/tmp/my icon.png"),
+            }
+        );
+        assert_eq!(
+            "brightness ABC123 40".parse::<DaemonCommand>().unwrap(),
+            DaemonCommand::SetBrightness {
+                serial: "ABC123".to_string(),
+                percent: 40,
+            }
+        );
+
+        for line in [
+            "",
+            "devices now",
+            "image ABC123 key /tmp/a.png",
+            "image ABC123 4",
+            "brightness ABC123",
+        ] {
+            assert!(matches!(
+                line.parse::<DaemonCommand>(),
+                Err(AjazzError::InvalidCommand(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_format_messages() {
+        let serial = "ABC123".to_string();
+
+        assert_eq!(DaemonMessage::Ok.to_string(), "ok");
+        assert_eq!(
+            DaemonMessage::Error("no\ndevice".into()).to_string(),
+            "error no device"
+        );
+        assert_eq!(
+            DaemonMessage::Connected(Kind::Akp03R, serial.clone()).to_string(),
+            format!("connected {} ABC123", Kind::Akp03R)
+        );
+        assert_eq!(
+            DaemonMessage::Event(serial, Event::ButtonDown(3)).to_string(),
+            "event ABC123 ButtonDown(3)"
+        );
+    }
+}
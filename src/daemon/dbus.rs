@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, interface, Connection};
+
+use crate::daemon::{DeviceManager, ManagerEvent};
+use crate::{AjazzError, AsyncAjazz, Event, Kind, CancellationToken};
+
+/// Well-known name requested on the session bus by [serve_dbus]
+pub const DBUS_NAME: &str = "io.github.mishamyrt.Ajazz";
+
+/// Prefix of device object paths, the serial is appended to it, see [device_path]
+pub const DBUS_PATH_PREFIX: &str = "/io/github/mishamyrt/Ajazz/";
+
+/// Returns D-Bus object path of the device with the serial. Characters not allowed
+/// in object paths are replaced with `_`
+pub fn device_path(serial: &str) -> String {
+    let mut element: String = serial
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if element.is_empty() {
+        element.push('_');
+    }
+
+    format!("{DBUS_PATH_PREFIX}{element}")
+}
+
+/// Device exposed on the bus as `io.github.mishamyrt.Ajazz.Device`
+struct DeviceObject {
+    kind: Kind,
+    serial: String,
+    device: AsyncAjazz,
+}
+
+#[interface(name = "io.github.mishamyrt.Ajazz.Device")]
+impl DeviceObject {
+    /// Sets brightness of the device, value range is 0 - 100
+    async fn set_brightness(&self, percent: u8) -> fdo::Result<()> {
+        self.device.set_brightness(percent).await.map_err(failed)
+    }
+
+    /// Shows the image on the key, image is the content of a file in any supported format
+    async fn set_key_image(&self, key: u8, image: Vec<u8>) -> fdo::Result<()> {
+        let image = image::load_from_memory(&image).map_err(|e| failed(e.into()))?;
+        self.device
+            .set_button_image(key, image)
+            .await
+            .map_err(failed)?;
+        self.device.flush().await.map_err(failed)
+    }
+
+    /// Clears the key image
+    async fn clear_key_image(&self, key: u8) -> fdo::Result<()> {
+        self.device.clear_button_image(key).await.map_err(failed)?;
+        self.device.flush().await.map_err(failed)
+    }
+
+    /// Kind of the device, as returned by [Kind::identifier]
+    #[zbus(property)]
+    async fn kind(&self) -> String {
+        self.kind.to_string()
+    }
+
+    /// Serial number of the device
+    #[zbus(property)]
+    async fn serial(&self) -> String {
+        self.serial.clone()
+    }
+
+    #[zbus(signal)]
+    async fn button_down(emitter: &SignalEmitter<'_>, key: u8) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn button_up(emitter: &SignalEmitter<'_>, key: u8) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn touch_key_down(emitter: &SignalEmitter<'_>, key: u8) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn touch_key_up(emitter: &SignalEmitter<'_>, key: u8) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn encoder_down(emitter: &SignalEmitter<'_>, encoder: u8) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn encoder_up(emitter: &SignalEmitter<'_>, encoder: u8) -> zbus::Result<()>;
+
+    /// D-Bus has no 8-bit signed type, so ticks are sent as 16-bit
+    #[zbus(signal)]
+    async fn encoder_twist(
+        emitter: &SignalEmitter<'_>,
+        encoder: u8,
+        ticks: i16,
+    ) -> zbus::Result<()>;
+}
+
+/// Converts error of the device to the D-Bus error
+fn failed(error: AjazzError) -> fdo::Error {
+    fdo::Error::Failed(error.to_string())
+}
+
+/// Exposes every device of the manager on the session bus under [DBUS_NAME]
+/// until the token is cancelled.
+///
+/// Each device is an object at [device_path] with `SetBrightness`, `SetKeyImage` and
+/// `ClearKeyImage` methods, and signals for key, touch key and encoder input
+pub async fn serve_dbus(
+    manager: Arc<DeviceManager>,
+    shutdown: CancellationToken,
+) -> Result<(), AjazzError> {
+    let connection = zbus::connection::Builder::session()?
+        .name(DBUS_NAME)?
+        .build()
+        .await?;
+
+    // Subscribe first, so devices connected in between are not missed
+    let mut events = manager.subscribe();
+    for (kind, serial) in manager.devices().await {
+        add_device(&connection, &manager, kind, serial).await?;
+    }
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            () = shutdown.cancelled() => return Ok(()),
+        };
+
+        match event {
+            Ok(ManagerEvent::Connected(kind, serial)) => {
+                add_device(&connection, &manager, kind, serial).await?;
+            }
+            Ok(ManagerEvent::Disconnected(_, serial)) => {
+                connection
+                    .object_server()
+                    .remove::<DeviceObject, _>(device_path(&serial))
+                    .await?;
+            }
+            Ok(ManagerEvent::Input(serial, event)) => {
+                // Input may arrive before the object of a just connected device is added
+                let Ok(object) = connection
+                    .object_server()
+                    .interface::<_, DeviceObject>(device_path(&serial))
+                    .await
+                else {
+                    continue;
+                };
+                emit(object.signal_emitter(), event).await?;
+            }
+            // Slow bus misses some input rather than stalling the devices
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+/// Adds object of the device, if it's still connected
+async fn add_device(
+    connection: &Connection,
+    manager: &DeviceManager,
+    kind: Kind,
+    serial: String,
+) -> Result<(), AjazzError> {
+    let Some(device) = manager.device(&serial).await else {
+        return Ok(());
+    };
+
+    let path = device_path(&serial);
+    let object = DeviceObject {
+        kind,
+        serial,
+        device,
+    };
+    connection.object_server().at(path, object).await?;
+
+    Ok(())
+}
+
+/// Emits signal of the event, events without a signal are skipped
+async fn emit(emitter: &SignalEmitter<'_>, event: Event) -> zbus::Result<()> {
+    match event {
+        Event::ButtonDown(key) => DeviceObject::button_down(emitter, key).await,
+        Event::ButtonUp(key) => DeviceObject::button_up(emitter, key).await,
+        Event::TouchKeyDown(key) => DeviceObject::touch_key_down(emitter, key).await,
+        Event::TouchKeyUp(key) => DeviceObject::touch_key_up(emitter, key).await,
+        Event::EncoderDown(encoder) => DeviceObject::encoder_down(emitter, encoder).await,
+        Event::EncoderUp(encoder) => DeviceObject::encoder_up(emitter, encoder).await,
+        Event::EncoderTwist(encoder, ticks) => {
+            DeviceObject::encoder_twist(emitter, encoder, ticks.into()).await
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_path() {
+        assert_eq!(device_path("AB12"), "/io/github/mishamyrt/Ajazz/AB12");
+        assert_eq!(device_path("3.1-x"), "/io/github/mishamyrt/Ajazz/3_1_x");
+        assert!(zbus::zvariant::ObjectPath::try_from(device_path("a/b c")).is_ok());
+    }
+}
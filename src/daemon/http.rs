@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+use crate::daemon::{DeviceManager, ManagerEvent};
+use crate::{AjazzError, AsyncAjazz, Event, CancellationToken};
+
+/// Device listed by `GET /devices`
+#[derive(Serialize)]
+struct DeviceInfo {
+    kind: String,
+    serial: String,
+}
+
+/// Message of the `/events` WebSocket
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum EventMessage {
+    Connected { kind: String, serial: String },
+    Disconnected { kind: String, serial: String },
+    Input { serial: String, event: Event },
+}
+
+impl From<ManagerEvent> for EventMessage {
+    fn from(event: ManagerEvent) -> Self {
+        match event {
+            ManagerEvent::Connected(kind, serial) => EventMessage::Connected {
+                kind: kind.to_string(),
+                serial,
+            },
+            ManagerEvent::Disconnected(kind, serial) => EventMessage::Disconnected {
+                kind: kind.to_string(),
+                serial,
+            },
+            ManagerEvent::Input(serial, event) => EventMessage::Input { serial, event },
+        }
+    }
+}
+
+/// Error returned by the handlers, converted to the status and the error text
+struct HttpError(StatusCode, String);
+
+impl From<AjazzError> for HttpError {
+    fn from(error: AjazzError) -> Self {
+        let status = match error {
+            AjazzError::UnknownDevice(_) => StatusCode::NOT_FOUND,
+            AjazzError::ImageError(_)
+            | AjazzError::InvalidKeyIndex(_)
+            | AjazzError::NoScreenOnKey(_)
+            | AjazzError::InvalidImageSize(..) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        HttpError(status, error.to_string())
+    }
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+/// Returns router of the REST and WebSocket API of the manager's devices:
+///
+/// - `GET /devices` lists connected devices as JSON
+/// - `POST /devices/{serial}/keys/{key}/image` shows image from the body, which is
+///   content of an image file, on the key
+/// - `PUT /devices/{serial}/brightness` sets brightness from the body, in percent
+/// - `GET /events` upgrades to WebSocket sending connection changes and input
+///   as JSON text messages
+///
+/// Routes can be merged into a bigger application, see [serve_http] to run them alone
+pub fn router(manager: Arc<DeviceManager>) -> Router {
+    Router::new()
+        .route("/devices", get(list_devices))
+        .route("/devices/{serial}/keys/{key}/image", post(set_key_image))
+        .route("/devices/{serial}/brightness", put(set_brightness))
+        .route("/events", get(events))
+        .with_state(manager)
+}
+
+/// Serves [router] on the listener until the token is cancelled
+pub async fn serve_http(
+    listener: TcpListener,
+    manager: Arc<DeviceManager>,
+    shutdown: CancellationToken,
+) -> Result<(), AjazzError> {
+    axum::serve(listener, router(manager))
+        .with_graceful_shutdown(async move { shutdown.cancelled().await })
+        .await?;
+
+    Ok(())
+}
+
+async fn list_devices(State(manager): State<Arc<DeviceManager>>) -> Json<Vec<DeviceInfo>> {
+    let devices = manager.devices().await;
+    Json(
+        devices
+            .into_iter()
+            .map(|(kind, serial)| DeviceInfo {
+                kind: kind.to_string(),
+                serial,
+            })
+            .collect(),
+    )
+}
+
+async fn set_key_image(
+    State(manager): State<Arc<DeviceManager>>,
+    Path((serial, key)): Path<(String, u8)>,
+    image: Bytes,
+) -> Result<StatusCode, HttpError> {
+    let device = device(&manager, serial).await?;
+    let image = image::load_from_memory(&image).map_err(AjazzError::from)?;
+    device.set_button_image(key, image).await?;
+    device.flush().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_brightness(
+    State(manager): State<Arc<DeviceManager>>,
+    Path(serial): Path<String>,
+    percent: String,
+) -> Result<StatusCode, HttpError> {
+    let percent = percent
+        .trim()
+        .parse()
+        .map_err(|_| HttpError(StatusCode::BAD_REQUEST, "Invalid brightness".to_string()))?;
+    device(&manager, serial)
+        .await?
+        .set_brightness(percent)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn events(
+    State(manager): State<Arc<DeviceManager>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let events = manager.subscribe();
+    upgrade.on_upgrade(move |socket| forward_events(socket, events))
+}
+
+/// Sends events to the socket until the client goes away
+async fn forward_events(mut socket: WebSocket, mut events: broadcast::Receiver<ManagerEvent>) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            // Slow client misses some events rather than stalling the others
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        let Ok(text) = serde_json::to_string(&EventMessage::from(event)) else {
+            continue;
+        };
+        if socket.send(Message::text(text)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Returns the connected device or the not found error
+async fn device(manager: &DeviceManager, serial: String) -> Result<AsyncAjazz, AjazzError> {
+    manager
+        .device(&serial)
+        .await
+        .ok_or(AjazzError::UnknownDevice(serial))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::daemon::ManagerOptions;
+
+    /// Sends the request and returns the whole response
+    async fn request(address: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_rest_api() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        let manager = Arc::new(DeviceManager::spawn(
+            ManagerOptions::default(),
+            shutdown.clone(),
+        ));
+        let server = tokio::spawn(serve_http(listener, manager, shutdown.clone()));
+
+        let devices = request(
+            address,
+            "GET /devices HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(devices.starts_with("HTTP/1.1 200"));
+        assert!(devices.ends_with("\r\n\r\n[]"));
+
+        let missing = request(
+            address,
+            "PUT /devices/MISSING/brightness HTTP/1.1\r\nHost: test\r\n\
+             Content-Length: 2\r\nConnection: close\r\n\r\n50",
+        )
+        .await;
+        assert!(missing.starts_with("HTTP/1.1 404"));
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_event_json() {
+        let message = EventMessage::from(ManagerEvent::Input(
+            "AB12".to_string(),
+            Event::ButtonDown(2),
+        ));
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"type":"input","serial":"AB12","event":{"ButtonDown":2}}"#
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hidapi::HidApi;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::{spawn_blocking, JoinHandle};
+use tokio::time::sleep;
+
+use crate::device::Ajazz;
+use crate::{
+    AjazzError, AsyncAjazz, ConnectOptions, DeviceWatcher, Event, HotplugEvent, Kind,
+    CancellationToken,
+};
+
+/// Options of [DeviceManager]
+#[derive(Clone, Debug)]
+pub struct ManagerOptions {
+    /// How often the device list is checked for connected and disconnected devices
+    pub poll_interval: Duration,
+    /// Delay before connecting the device again after the connection failed or was lost
+    pub reconnect_delay: Duration,
+    /// Poll rate of device input in Hz
+    pub poll_rate: f32,
+    /// Options used when connecting every device
+    pub connect: ConnectOptions,
+}
+
+impl Default for ManagerOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            reconnect_delay: Duration::from_secs(2),
+            poll_rate: 60.0,
+            connect: ConnectOptions::default(),
+        }
+    }
+}
+
+/// Change reported by [DeviceManager]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ManagerEvent {
+    /// Device was connected and is ready for commands, as (Kind, Serial Number)
+    Connected(Kind, String),
+    /// Device was disconnected or its connection was lost, as (Kind, Serial Number)
+    Disconnected(Kind, String),
+    /// Input was read from the device with the serial
+    Input(String, Event),
+}
+
+/// Connected devices, by serial
+type Devices = Arc<Mutex<HashMap<String, AsyncAjazz>>>;
+
+/// Keeps every supported device connected: devices are connected when they appear,
+/// reconnected when reading from them fails and closed when they are unplugged.
+/// Input of all devices is reported through [DeviceManager::subscribe].
+///
+/// Everything stops when the manager is dropped or the shutdown token is cancelled
+pub struct DeviceManager {
+    devices: Devices,
+    events: broadcast::Sender<ManagerEvent>,
+    task: JoinHandle<()>,
+}
+
+impl DeviceManager {
+    /// Starts managing devices, must be called inside a Tokio runtime
+    pub fn spawn(options: ManagerOptions, shutdown: CancellationToken) -> DeviceManager {
+        let devices = Devices::default();
+        let (events, _) = broadcast::channel(256);
+        let watcher =
+            DeviceWatcher::spawn_with_shutdown(options.poll_interval, shutdown.clone());
+        let mut hotplug = watcher.subscribe();
+
+        let task = {
+            let devices = devices.clone();
+            let events = events.clone();
+            tokio::spawn(async move {
+                // Watcher stops with the task
+                let _watcher = watcher;
+                let mut supervisors: HashMap<String, CancellationToken> = HashMap::new();
+                loop {
+                    let event = tokio::select! {
+                        event = hotplug.recv() => event,
+                        () = shutdown.cancelled() => break,
+                    };
+
+                    match event {
+                        Ok(HotplugEvent::Connected(kind, serial)) => {
+                            let stop = shutdown.child_token();
+                            supervisors.insert(serial.clone(), stop.clone());
+                            tokio::spawn(supervise(
+                                kind,
+                                serial,
+                                options.clone(),
+                                devices.clone(),
+                                events.clone(),
+                                stop,
+                            ));
+                        }
+                        Ok(HotplugEvent::Disconnected(_, serial)) => {
+                            if let Some(stop) = supervisors.remove(&serial) {
+                                stop.cancel();
+                            }
+                        }
+                        // Missed events are reported again by later polls
+                        Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+
+                for stop in supervisors.values() {
+                    stop.cancel();
+                }
+            })
+        };
+
+        DeviceManager {
+            devices,
+            events,
+            task,
+        }
+    }
+
+    /// Returns receiver of connection changes and input of every device
+    pub fn subscribe(&self) -> broadcast::Receiver<ManagerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns connected devices as (Kind, Serial Number)
+    pub async fn devices(&self) -> Vec<(Kind, String)> {
+        let devices = self.devices.lock().await;
+        let mut list: Vec<_> = devices
+            .iter()
+            .map(|(serial, device)| (device.kind(), serial.clone()))
+            .collect();
+        list.sort_by(|a, b| a.1.cmp(&b.1));
+        list
+    }
+
+    /// Returns the device with the serial, if it's connected
+    pub async fn device(&self, serial: &str) -> Option<AsyncAjazz> {
+        self.devices.lock().await.get(serial).cloned()
+    }
+}
+
+impl Drop for DeviceManager {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Keeps the device connected and forwards its input until it's stopped
+async fn supervise(
+    kind: Kind,
+    serial: String,
+    options: ManagerOptions,
+    devices: Devices,
+    events: broadcast::Sender<ManagerEvent>,
+    stop: CancellationToken,
+) {
+    while !stop.is_cancelled() {
+        if let Ok(device) = connect(kind, &serial, &options.connect).await {
+            devices.lock().await.insert(serial.clone(), device.clone());
+            // Having no receivers is fine, they may subscribe later
+            let _ = events.send(ManagerEvent::Connected(kind, serial.clone()));
+
+            let reader = device.get_reader();
+            let forward = async {
+                // Device is reconnected when reading fails
+                while let Ok(updates) = reader.read(options.poll_rate).await {
+                    for event in updates {
+                        let _ = events.send(ManagerEvent::Input(serial.clone(), event));
+                    }
+                }
+            };
+            tokio::select! {
+                () = forward => {}
+                () = stop.cancelled() => device.shutdown_token().cancel(),
+            }
+
+            devices.lock().await.remove(&serial);
+            let _ = events.send(ManagerEvent::Disconnected(kind, serial.clone()));
+        }
+
+        tokio::select! {
+            () = sleep(options.reconnect_delay) => {}
+            () = stop.cancelled() => {}
+        }
+    }
+}
+
+/// Opens the device on the blocking thread pool with a fresh HidApi
+async fn connect(
+    kind: Kind,
+    serial: &str,
+    options: &ConnectOptions,
+) -> Result<AsyncAjazz, AjazzError> {
+    let serial = serial.to_string();
+    let options = options.clone();
+    let device = spawn_blocking(move || {
+        let hidapi = HidApi::new()?;
+        Ajazz::connect_with_options(&hidapi, kind, &serial, &options)
+    })
+    .await??;
+
+    Ok(AsyncAjazz::from_device(device))
+}
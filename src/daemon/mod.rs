@@ -0,0 +1,50 @@
+//! Building blocks of a daemon owning the devices, so one privileged process drives
+//! them and unprivileged applications talk to it over a Unix socket.
+//!
+//! [DeviceManager] connects every supported device, reconnects the ones that drop out
+//! and forwards their input. [serve] accepts clients speaking the line protocol
+//! of [DaemonCommand], one command per line:
+//!
+//! ```text
+//! devices
+//! image <serial> <key> <path to image file>
+//! brightness <serial> <percent>
+//! subscribe
+//! ```
+//!
+//! Every command is answered with `ok` or `error <message>`, `devices` lists
+//! `device <kind> <serial>` lines before the `ok`. After `subscribe` the client also gets
+//! `connected <kind> <serial>`, `disconnected <kind> <serial>` and `event <serial> <event>`
+//! lines as they happen.
+//!
+//! With the `dbus` feature, [serve_dbus] exposes the same devices on the session bus,
+//! with the `mqtt` feature [serve_mqtt] bridges them to an MQTT broker, and with
+//! the `http` feature [serve_http] serves them as a REST and WebSocket API
+
+mod command;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod manager;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(unix)]
+mod server;
+
+#[cfg(feature = "dbus")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dbus")))]
+pub use dbus::{device_path, serve_dbus, DBUS_NAME, DBUS_PATH_PREFIX};
+pub use command::{DaemonCommand, DaemonMessage};
+#[cfg(feature = "mqtt")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mqtt")))]
+pub use mqtt::serve_mqtt;
+#[cfg(feature = "mqtt")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mqtt")))]
+pub use rumqttc::MqttOptions;
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub use http::{router, serve_http};
+pub use manager::{DeviceManager, ManagerEvent, ManagerOptions};
+#[cfg(unix)]
+pub use server::{bind, serve};
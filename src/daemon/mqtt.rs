@@ -0,0 +1,204 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, MqttOptions, Packet, QoS};
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+
+use crate::daemon::{DeviceManager, ManagerEvent};
+use crate::{AjazzError, Event, CancellationToken};
+
+/// Delay before polling the broker again after the connection failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Command received from the broker
+#[derive(Clone, Debug, PartialEq)]
+enum MqttCommand {
+    SetBrightness(String, u8),
+    SetImage(String, u8, Vec<u8>),
+}
+
+/// Bridges devices of the manager to the MQTT broker until the token is cancelled.
+/// Topics start with the prefix, e.g. `ajazz`:
+///
+/// - `<prefix>/<serial>/status` gets retained `online` or `offline`
+/// - `<prefix>/<serial>/key/<key>` gets `down` or `up`
+/// - `<prefix>/<serial>/touch-key/<key>` gets `down` or `up`
+/// - `<prefix>/<serial>/encoder/<encoder>` gets `down` or `up`
+/// - `<prefix>/<serial>/encoder/<encoder>/twist` gets signed amount of ticks
+/// - `<prefix>/<serial>/brightness/set` takes brightness in percent
+/// - `<prefix>/<serial>/key/<key>/image/set` takes content of an image file
+///
+/// The broker is reconnected if the connection is lost
+pub async fn serve_mqtt(
+    manager: Arc<DeviceManager>,
+    options: MqttOptions,
+    prefix: &str,
+    shutdown: CancellationToken,
+) -> Result<(), AjazzError> {
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+    let mut events = manager.subscribe();
+    let brightness_topic = format!("{prefix}/+/brightness/set");
+    let image_topic = format!("{prefix}/+/key/+/image/set");
+    for (_, serial) in manager.devices().await {
+        publish_status(&client, prefix, &serial, true);
+    }
+
+    loop {
+        tokio::select! {
+            notification = eventloop.poll() => match notification {
+                // Subscriptions don't survive reconnects of clean sessions
+                Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => {
+                    client.try_subscribe(&brightness_topic, QoS::AtLeastOnce)?;
+                    client.try_subscribe(&image_topic, QoS::AtLeastOnce)?;
+                }
+                Ok(rumqttc::Event::Incoming(Packet::Publish(publish))) => {
+                    let command = parse_command(prefix, &publish.topic, &publish.payload);
+                    if let Some(command) = command {
+                        // Decoding images takes a while, the event loop must keep going.
+                        // Errors are dropped, there's no one to report them to
+                        let manager = manager.clone();
+                        tokio::spawn(async move {
+                            let _ = execute(manager, command).await;
+                        });
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => sleep(RECONNECT_DELAY).await,
+            },
+            event = events.recv() => match event {
+                Ok(ManagerEvent::Connected(_, serial)) => {
+                    publish_status(&client, prefix, &serial, true);
+                }
+                Ok(ManagerEvent::Disconnected(_, serial)) => {
+                    publish_status(&client, prefix, &serial, false);
+                }
+                Ok(ManagerEvent::Input(serial, event)) => {
+                    if let Some((topic, payload)) = event_message(prefix, &serial, event) {
+                        // Input is dropped rather than stalling while the broker is away
+                        let _ = client.try_publish(topic, QoS::AtMostOnce, false, payload);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            () = shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
+
+/// Publishes retained availability of the device
+fn publish_status(client: &AsyncClient, prefix: &str, serial: &str, online: bool) {
+    let payload = if online { "online" } else { "offline" };
+    let _ = client.try_publish(
+        format!("{prefix}/{serial}/status"),
+        QoS::AtLeastOnce,
+        true,
+        payload,
+    );
+}
+
+/// Returns topic and payload of the event, events without a topic are skipped
+fn event_message(prefix: &str, serial: &str, event: Event) -> Option<(String, String)> {
+    let (subtopic, payload) = match event {
+        Event::ButtonDown(key) => (format!("key/{key}"), "down".to_string()),
+        Event::ButtonUp(key) => (format!("key/{key}"), "up".to_string()),
+        Event::TouchKeyDown(key) => (format!("touch-key/{key}"), "down".to_string()),
+        Event::TouchKeyUp(key) => (format!("touch-key/{key}"), "up".to_string()),
+        Event::EncoderDown(encoder) => (format!("encoder/{encoder}"), "down".to_string()),
+        Event::EncoderUp(encoder) => (format!("encoder/{encoder}"), "up".to_string()),
+        Event::EncoderTwist(encoder, ticks) => {
+            (format!("encoder/{encoder}/twist"), ticks.to_string())
+        }
+        _ => return None,
+    };
+
+    Some((format!("{prefix}/{serial}/{subtopic}"), payload))
+}
+
+/// Parses command topic, returns `None` for unknown topics and invalid payloads
+fn parse_command(prefix: &str, topic: &str, payload: &[u8]) -> Option<MqttCommand> {
+    let rest = topic.strip_prefix(prefix)?.strip_prefix('/')?;
+    let parts: Vec<&str> = rest.split('/').collect();
+
+    match parts[..] {
+        [serial, "brightness", "set"] => {
+            let percent = std::str::from_utf8(payload).ok()?.trim().parse().ok()?;
+            Some(MqttCommand::SetBrightness(serial.to_string(), percent))
+        }
+        [serial, "key", key, "image", "set"] => Some(MqttCommand::SetImage(
+            serial.to_string(),
+            key.parse().ok()?,
+            payload.to_vec(),
+        )),
+        _ => None,
+    }
+}
+
+/// Executes the command on its device
+async fn execute(manager: Arc<DeviceManager>, command: MqttCommand) -> Result<(), AjazzError> {
+    match command {
+        MqttCommand::SetBrightness(serial, percent) => {
+            let device = manager
+                .device(&serial)
+                .await
+                .ok_or(AjazzError::UnknownDevice(serial))?;
+            device.set_brightness(percent).await
+        }
+        MqttCommand::SetImage(serial, key, image) => {
+            let device = manager
+                .device(&serial)
+                .await
+                .ok_or(AjazzError::UnknownDevice(serial))?;
+            let image = image::load_from_memory(&image)?;
+            device.set_button_image(key, image).await?;
+            device.flush().await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_topics() {
+        assert_eq!(
+            event_message("ajazz", "AB12", Event::ButtonDown(3)),
+            Some(("ajazz/AB12/key/3".to_string(), "down".to_string()))
+        );
+        assert_eq!(
+            event_message("ajazz", "AB12", Event::EncoderTwist(1, -2)),
+            Some(("ajazz/AB12/encoder/1/twist".to_string(), "-2".to_string()))
+        );
+        assert_eq!(event_message("ajazz", "AB12", Event::Tick(0)), None);
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse_command("ajazz", "ajazz/AB12/brightness/set", b"40\n"),
+            Some(MqttCommand::SetBrightness("AB12".to_string(), 40))
+        );
+        assert_eq!(
+            parse_command("ajazz", "ajazz/AB12/key/5/image/set", b"png"),
+            Some(MqttCommand::SetImage(
+                "AB12".to_string(),
+                5,
+                b"png".to_vec()
+            ))
+        );
+        assert_eq!(
+            parse_command("ajazz", "ajazz/AB12/brightness/set", b"bright"),
+            None
+        );
+        assert_eq!(
+            parse_command("ajazz", "other/AB12/brightness/set", b"40"),
+            None
+        );
+        assert_eq!(
+            parse_command("ajazz", "ajazzz/AB12/brightness/set", b"40"),
+            None
+        );
+    }
+}
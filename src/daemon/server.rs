@@ -0,0 +1,193 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::{spawn_blocking, JoinHandle};
+
+use crate::daemon::{DaemonCommand, DaemonMessage, DeviceManager, ManagerEvent};
+use crate::{AjazzError, CancellationToken};
+
+/// Binds the socket at the path, replacing the one left by a previous run.
+///
+/// Unprivileged applications need write access to the socket, set its permissions
+/// with [std::fs::set_permissions] after binding
+pub fn bind(path: impl AsRef<Path>) -> Result<UnixListener, AjazzError> {
+    let path = path.as_ref();
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    Ok(UnixListener::bind(path)?)
+}
+
+/// Accepts clients and executes their commands on the devices of the manager,
+/// until the token is cancelled
+pub async fn serve(
+    listener: UnixListener,
+    manager: Arc<DeviceManager>,
+    shutdown: CancellationToken,
+) -> Result<(), AjazzError> {
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            () = shutdown.cancelled() => return Ok(()),
+        };
+
+        let manager = manager.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                () = handle_client(stream, manager) => {}
+                () = shutdown.cancelled() => {}
+            }
+        });
+    }
+}
+
+/// Executes commands of a single client until it disconnects
+async fn handle_client(stream: UnixStream, manager: Arc<DeviceManager>) {
+    let (reader, mut writer) = stream.into_split();
+    let (messages, mut outgoing) = unbounded_channel::<DaemonMessage>();
+
+    // Responses and events are written by one task, so their lines never interleave
+    let writing = tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            let line = format!("{message}\n");
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut subscription: Option<JoinHandle<()>> = None;
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = match line.parse() {
+            Ok(DaemonCommand::Subscribe) => {
+                if subscription.is_none() {
+                    subscription = Some(forward_events(manager.subscribe(), messages.clone()));
+                }
+                Ok(())
+            }
+            Ok(command) => execute(command, &manager, &messages).await,
+            Err(e) => Err(e),
+        };
+
+        let _ = messages.send(match result {
+            Ok(()) => DaemonMessage::Ok,
+            Err(e) => DaemonMessage::Error(e.to_string()),
+        });
+    }
+
+    if let Some(subscription) = subscription {
+        subscription.abort();
+    }
+    drop(messages);
+    let _ = writing.await;
+}
+
+/// Sends events of the manager to the client
+fn forward_events(
+    mut events: broadcast::Receiver<ManagerEvent>,
+    messages: UnboundedSender<DaemonMessage>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let message = match events.recv().await {
+                Ok(ManagerEvent::Connected(kind, serial)) => {
+                    DaemonMessage::Connected(kind, serial)
+                }
+                Ok(ManagerEvent::Disconnected(kind, serial)) => {
+                    DaemonMessage::Disconnected(kind, serial)
+                }
+                Ok(ManagerEvent::Input(serial, event)) => DaemonMessage::Event(serial, event),
+                // Slow client misses some events rather than stalling the others
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            if messages.send(message).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+/// Executes command other than [DaemonCommand::Subscribe]
+async fn execute(
+    command: DaemonCommand,
+    manager: &DeviceManager,
+    messages: &UnboundedSender<DaemonMessage>,
+) -> Result<(), AjazzError> {
+    match command {
+        DaemonCommand::Devices => {
+            for (kind, serial) in manager.devices().await {
+                let _ = messages.send(DaemonMessage::Device(kind, serial));
+            }
+            Ok(())
+        }
+        DaemonCommand::SetImage { serial, key, path } => {
+            let device = manager
+                .device(&serial)
+                .await
+                .ok_or(AjazzError::UnknownDevice(serial))?;
+            let image = spawn_blocking(move || image::open(path)).await??;
+            device.set_button_image(key, image).await?;
+            device.flush().await
+        }
+        DaemonCommand::SetBrightness { serial, percent } => {
+            let device = manager
+                .device(&serial)
+                .await
+                .ok_or(AjazzError::UnknownDevice(serial))?;
+            device.set_brightness(percent).await
+        }
+        DaemonCommand::Subscribe => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    use super::*;
+    use crate::daemon::ManagerOptions;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_client_commands() {
+        let path =
+            std::env::temp_dir().join(format!("ajazz-test-{}.sock", std::process::id()));
+        let listener = bind(&path).unwrap();
+        let shutdown = CancellationToken::new();
+        let manager = Arc::new(DeviceManager::spawn(
+            ManagerOptions::default(),
+            shutdown.clone(),
+        ));
+        let server = tokio::spawn(serve(listener, manager, shutdown.clone()));
+
+        let (reader, mut writer) = UnixStream::connect(&path).await.unwrap().into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer
+            .write_all(b"brightness MISSING 50\nflash everything\nsubscribe\n")
+            .await
+            .unwrap();
+
+        let missing = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(missing, "error No connected device with serial MISSING");
+        let invalid = lines.next_line().await.unwrap().unwrap();
+        assert!(invalid.starts_with("error Invalid daemon command"));
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+        let _ = std::fs::remove_file(path);
+    }
+}
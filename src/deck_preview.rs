@@ -0,0 +1,98 @@
+use image::{imageops, DynamicImage, Rgb, RgbImage};
+
+use crate::device::Ajazz;
+use crate::locks::lock;
+
+/// Space between keys and around the preview, in pixels
+pub(crate) const PREVIEW_GAP: u32 = 8;
+
+/// Background of the preview
+pub(crate) const PREVIEW_BACKGROUND: Rgb<u8> = Rgb([24, 24, 24]);
+
+/// Color of keys without an image
+pub(crate) const EMPTY_KEY: Rgb<u8> = Rgb([48, 48, 48]);
+
+impl Ajazz {
+    /// Renders what the deck shows into a single image: display keys in rows and
+    /// columns as on the device, with the LCD strip content below them.
+    ///
+    /// Only images sent by this instance are known, keys without one are drawn empty
+    pub fn render_deck_preview(&self) -> DynamicImage {
+        let kind = self.kind();
+        let (key_w, key_h) = self.key_image_format().size;
+        let (key_w, key_h) = (key_w as u32, key_h as u32);
+        let columns = u32::from(kind.column_count());
+        let keys = u32::from(kind.display_key_count());
+        let rows = keys.div_ceil(columns);
+
+        let width = columns * (key_w + PREVIEW_GAP) + PREVIEW_GAP;
+        let grid_height = rows * (key_h + PREVIEW_GAP) + PREVIEW_GAP;
+
+        let lcd = lock(&self.lcd_frame).clone().map(|frame| {
+            let lcd_w = width - PREVIEW_GAP * 2;
+            let lcd_h = (frame.height() * lcd_w / frame.width().max(1)).max(1);
+            imageops::resize(&frame, lcd_w, lcd_h, imageops::FilterType::Triangle)
+        });
+        let height = grid_height + lcd.as_ref().map_or(0, |lcd| lcd.height() + PREVIEW_GAP);
+
+        let mut preview = RgbImage::from_pixel(width, height, PREVIEW_BACKGROUND);
+        for key in 0..keys {
+            let x = PREVIEW_GAP + (key % columns) * (key_w + PREVIEW_GAP);
+            let y = PREVIEW_GAP + (key / columns) * (key_h + PREVIEW_GAP);
+            // Images that can't be decoded are drawn as empty keys too
+            let tile = match self.key_image(key as u8) {
+                Ok(Some(image)) => image
+                    .resize_exact(key_w, key_h, imageops::FilterType::Triangle)
+                    .into_rgb8(),
+                _ => RgbImage::from_pixel(key_w, key_h, EMPTY_KEY),
+            };
+            imageops::replace(&mut preview, &tile, x.into(), y.into());
+        }
+
+        if let Some(lcd) = lcd {
+            imageops::replace(&mut preview, &lcd, PREVIEW_GAP.into(), grid_height.into());
+        }
+
+        DynamicImage::ImageRgb8(preview)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codes;
+    use crate::replay::ReplayTransport;
+    use crate::{ImageRect, Kind};
+
+    #[test]
+    fn test_deck_preview_layout() {
+        let replay = ReplayTransport::default();
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        let device = Ajazz::from_transport(Kind::Akp153, replay);
+
+        let white = Rgb([255, 255, 255]);
+        let key = RgbImage::from_pixel(85, 85, white);
+        device
+            .set_button_image(6, DynamicImage::ImageRgb8(key))
+            .unwrap();
+        device.flush().unwrap();
+
+        let lcd = RgbImage::from_pixel(854, 480, Rgb([0, 0, 255]));
+        let rect = ImageRect::from_image(DynamicImage::ImageRgb8(lcd)).unwrap();
+        device.write_lcd_fill(&rect).unwrap();
+
+        let preview = device.render_deck_preview().into_rgb8();
+        // 5 columns and 3 rows of 85px keys
+        assert_eq!(preview.width(), 5 * 93 + 8);
+        let grid_height = 3 * 93 + 8;
+        assert!(preview.height() > grid_height);
+
+        // Key 6 is the second one of the second row
+        let (x, y) = (8 + 93 + 40, 8 + 93 + 40);
+        assert!(preview.get_pixel(x, y).0.iter().all(|c| *c > 200));
+        assert_eq!(preview.get_pixel(8 + 40, 8 + 40), &EMPTY_KEY);
+
+        let lcd_pixel = preview.get_pixel(preview.width() / 2, grid_height + 20);
+        assert!(lcd_pixel.0[2] > 200 && lcd_pixel.0[0] < 50);
+    }
+}
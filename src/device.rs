@@ -1,33 +1,140 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Error as IoError;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering};
 use std::sync::RwLock;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-
-use hidapi::{HidApi, HidDevice, HidError};
-use image::DynamicImage;
-
-use crate::images::{convert_image, WriteImageParameters};
-use crate::info::Kind;
-use crate::protocol::{codes, extract_string, request, AjazzProtocolParser, AjazzRequestBuilder};
-use crate::{convert_image_with_format, AjazzError, AjazzInput, DeviceState, Event};
+use std::time::{Duration, Instant};
+
+use hidapi::{HidApi, HidError};
+#[cfg(feature = "image")]
+use image::{DynamicImage, ImageError, RgbImage};
+
+use crate::device_lock::DeviceLock;
+use crate::hid::open_error;
+use crate::locks::{lock, read, write};
+use crate::formats::{ColorProfile, ImageFormat, ImageOptions, WriteImageParameters};
+#[cfg(feature = "image")]
+use crate::formats::LogoScaling;
+#[cfg(feature = "image")]
+use crate::images::{
+    convert_image_with_format, convert_image_with_options, encode_image_into, scale_image,
+};
+use crate::info::{HandshakeInfo, Kind};
+use crate::options::{ConnectOptions, DropPolicy};
+use crate::protocol::{
+    codes, extract_string, fill_image_report, is_command_response, parse_input, request,
+    AjazzProtocolParser, AjazzRequestBuilder,
+};
+use crate::replay::{CommandLogWriter, Direction};
+use crate::state::ShownState;
+use crate::transport::{write_whole_report, Transport};
+use crate::{AjazzError, AjazzInput, Metrics};
+
+/// Responses missed in a row after which the device is considered wedged
+const WATCHDOG_MISSED_ACKS: u32 = 3;
+/// Input reports kept while waiting for a response, older ones are dropped beyond it
+const MAX_PENDING_INPUTS: usize = 64;
+/// Longest single read while waiting for a response, so a response read by another
+/// thread is noticed quickly
+const RESPONSE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Initialization progress of the device
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum InitState {
+    /// Initialization packet was never sent
+    Uninitialized,
+    /// Initialization packet is being sent, other callers wait for it
+    Initializing,
+    /// Device was initialized
+    Ready,
+    /// Sending the initialization packet failed, the next call tries again
+    Failed,
+}
 
 /// Interface for an Ajazz device
 pub struct Ajazz {
     /// Kind of the device
     kind: Kind,
-    /// Connected HIDDevice
-    hid: HidDevice,
+    /// Transport connected to the device
+    hid: Box<dyn Transport>,
     /// Temporarily cache the image before sending it to the device
-    image_cache: RwLock<Vec<ImageCache>>,
-    /// Device needs to be initialized
-    initialized: AtomicBool,
+    image_cache: RwLock<ImageCache>,
+    /// Limit of bytes waiting in the cache, see [Ajazz::set_image_cache_limit]
+    image_cache_limit: Mutex<Option<usize>>,
+    /// Held while images are sent to the device, so flushes and streamed images
+    /// don't interleave
+    flush_lock: Mutex<()>,
+    /// Delay between image data reports, see [Ajazz::set_report_pacing]
+    report_pacing: Mutex<Option<Duration>>,
+    /// Writes of partially accepted reports, see [Ajazz::set_write_attempts]
+    write_attempts: AtomicU8,
+    /// Initialization state, the lock is held while the initialization packet is sent
+    init_state: Mutex<InitState>,
+    /// Device was put to sleep and not woken up since
+    pub(crate) asleep: AtomicBool,
+    /// Responses to commands are read and checked, see [Ajazz::set_ack_verification]
+    verify_acks: AtomicBool,
+    /// How long to wait for the device response, see [Ajazz::set_command_timeout]
+    command_timeout: Mutex<Duration>,
+    /// Input reports read while waiting for a command response, returned
+    /// by [Ajazz::read_input] before new ones
+    pending_inputs: Mutex<VecDeque<Vec<u8>>>,
+    /// Commands waiting for a response
+    response_waiters: AtomicUsize,
+    /// Responses read by [Ajazz::read_input] while commands were waiting for them
+    routed_responses: Mutex<VecDeque<Vec<u8>>>,
+    /// Responses missed in a row, the device is wedged after [WATCHDOG_MISSED_ACKS]
+    missed_acks: AtomicU32,
+    /// Current blocking mode of the transport, `None` if it was never set
+    blocking: Mutex<Option<bool>>,
+    /// Scratch buffer reused for image reports
+    write_buffer: Mutex<Vec<u8>>,
+    /// Scratch buffer reused for images encoded by [Ajazz::stream_button_image]
+    #[cfg(feature = "image")]
+    encode_buffer: Mutex<Vec<u8>>,
+    /// Images were sent bypassing the cache and wait for the flush
+    streamed: AtomicBool,
+    /// Geometry set by [Ajazz::override_layout]
+    layout: RwLock<Option<LayoutOverride>>,
+    /// What to do with the device when it's dropped
+    drop_policy: Mutex<DropPolicy>,
+    /// Receiver of connection counters, see [Ajazz::set_metrics]
+    metrics: RwLock<Option<Arc<dyn Metrics>>>,
+    /// File reports are logged to, see [Ajazz::enable_command_log]
+    command_log: Mutex<Option<CommandLogWriter>>,
+    /// What was sent to the device, see [Ajazz::export_state]
+    pub(crate) shown: RwLock<ShownState>,
+    /// Content of the LCD strip, which partial writes are drawn onto
+    #[cfg(feature = "image")]
+    pub(crate) lcd_frame: Mutex<Option<RgbImage>>,
+    /// Options of key image conversion, see [Ajazz::set_image_options]
+    image_options: RwLock<ImageOptions>,
+    /// Cross-process lock of the device, held while it's open
+    device_lock: Option<DeviceLock>,
 }
 
-struct ImageCache {
-    key: u8,
-    image_data: Vec<u8>,
+/// Geometry used instead of the one of the device kind, see [Ajazz::override_layout]
+#[derive(Copy, Clone, Debug)]
+struct LayoutOverride {
+    rows: u8,
+    cols: u8,
+    key_image_size: (usize, usize),
 }
 
+/// Progress of the flush, see [Ajazz::flush_with_progress]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FlushProgress {
+    /// Amount of key images already sent
+    pub sent_keys: usize,
+    /// Amount of key images being flushed
+    pub total_keys: usize,
+}
+
+/// Images waiting to be flushed, only the latest image of every key is kept
+pub(crate) type ImageCache = BTreeMap<u8, Arc<[u8]>>;
+
 /// Static functions of the struct
 impl Ajazz {
     /// Attempts to connect to the device
@@ -39,40 +146,144 @@ impl Ajazz {
         serial: &str,
         attempts: u8,
     ) -> Result<Ajazz, AjazzError> {
-        if attempts == 0 {
+        let options = ConnectOptions {
+            attempts,
+            ..Default::default()
+        };
+
+        Self::connect_with_options(hidapi, kind, serial, &options)
+    }
+
+    /// Attempts to connect to the device
+    pub fn connect(hidapi: &HidApi, kind: Kind, serial: &str) -> Result<Ajazz, AjazzError> {
+        Self::connect_with_options(hidapi, kind, serial, &ConnectOptions::default())
+    }
+
+    /// Attempts to connect to the device using provided options
+    pub fn connect_with_options(
+        hidapi: &HidApi,
+        kind: Kind,
+        serial: &str,
+        options: &ConnectOptions,
+    ) -> Result<Ajazz, AjazzError> {
+        if kind.is_unknown() {
+            return Err(AjazzError::UnrecognizedPID(kind.product_id()));
+        }
+
+        Self::connect_any_with_options(hidapi, kind, serial, options)
+    }
+
+    /// Attempts to connect to the device that is not recognized by the library,
+    /// see [Kind::UnknownV1] and [Kind::UnknownV2].
+    ///
+    /// Unknown devices are driven with conservative defaults, so some features
+    /// may not work or may work incorrectly
+    pub fn connect_unknown(
+        hidapi: &HidApi,
+        kind: Kind,
+        serial: &str,
+    ) -> Result<Ajazz, AjazzError> {
+        Self::connect_any_with_options(hidapi, kind, serial, &ConnectOptions::default())
+    }
+
+    // Internal function to connect to the device of any kind, including unknown ones
+    fn connect_any_with_options(
+        hidapi: &HidApi,
+        kind: Kind,
+        serial: &str,
+        options: &ConnectOptions,
+    ) -> Result<Ajazz, AjazzError> {
+        if options.attempts == 0 {
             return Err(AjazzError::UnsupportedOperation);
         }
 
         let mut last_error = None;
-        for _ in 0..attempts {
-            match Self::try_connect(hidapi, kind, serial) {
+        for attempt in 0..options.attempts {
+            // Failure of the last attempt is returned right away
+            if attempt > 0 {
+                std::thread::sleep(options.retry_delay);
+            }
+
+            match Self::try_connect(hidapi, kind, serial, options) {
                 Ok(device) => return Ok(device),
-                Err(e) => {
-                    std::thread::sleep(Duration::from_millis(100));
-                    last_error = Some(e);
-                    continue;
-                }
+                Err(e) => last_error = Some(e),
             }
         }
 
         Err(last_error.expect("error must never be empty at this point"))
     }
 
-    /// Attempts to connect to the device
-    pub fn connect(hidapi: &HidApi, kind: Kind, serial: &str) -> Result<Ajazz, AjazzError> {
-        Self::try_connect(hidapi, kind, serial)
-    }
-
     // Internal function to connect to the device
-    fn try_connect(hidapi: &HidApi, kind: Kind, serial: &str) -> Result<Ajazz, AjazzError> {
-        let device = hidapi.open_serial(kind.vendor_id(), kind.product_id(), serial)?;
+    fn try_connect(
+        hidapi: &HidApi,
+        kind: Kind,
+        serial: &str,
+        options: &ConnectOptions,
+    ) -> Result<Ajazz, AjazzError> {
+        if !kind.is_enabled() {
+            return Err(AjazzError::UnsupportedOperation);
+        }
+
+        let device_lock = if options.lock_device {
+            DeviceLock::acquire(serial)?
+        } else {
+            None
+        };
+
+        let device = hidapi
+            .open_serial(kind.vendor_id(), kind.product_id(), serial)
+            .map_err(open_error)?;
+
+        let mut device = Self::from_transport(kind, device);
+        device.device_lock = device_lock;
+        device.set_blocking_mode(options.blocking)?;
+        device.set_drop_policy(options.on_drop.clone());
+        device.set_ack_verification(options.verify_acks);
+        device.set_command_timeout(options.command_timeout);
+        device.set_image_cache_limit(options.image_cache_limit);
+        device.set_report_pacing(options.report_pacing);
+        device.set_write_attempts(options.write_attempts);
+        if let Some(metrics) = &options.metrics {
+            metrics.connected();
+            device.set_metrics(Some(metrics.clone()));
+        }
 
-        Ok(Ajazz {
+        Ok(device)
+    }
+
+    /// Creates device interface on top of a custom [Transport]
+    pub fn from_transport(kind: Kind, transport: impl Transport + 'static) -> Ajazz {
+        Ajazz {
             kind,
-            hid: device,
-            image_cache: RwLock::new(vec![]),
-            initialized: false.into(),
-        })
+            hid: Box::new(transport),
+            image_cache: RwLock::new(ImageCache::new()),
+            flush_lock: Mutex::new(()),
+            report_pacing: Mutex::new(None),
+            pending_inputs: Mutex::new(VecDeque::new()),
+            response_waiters: AtomicUsize::new(0),
+            routed_responses: Mutex::new(VecDeque::new()),
+            write_attempts: AtomicU8::new(1),
+            image_cache_limit: Mutex::new(None),
+            init_state: Mutex::new(InitState::Uninitialized),
+            asleep: false.into(),
+            verify_acks: false.into(),
+            command_timeout: Mutex::new(Duration::from_millis(1000)),
+            missed_acks: AtomicU32::new(0),
+            blocking: Mutex::new(None),
+            write_buffer: Mutex::new(vec![]),
+            #[cfg(feature = "image")]
+            encode_buffer: Mutex::new(vec![]),
+            streamed: false.into(),
+            layout: RwLock::new(None),
+            drop_policy: Mutex::new(DropPolicy::Nothing),
+            metrics: RwLock::new(None),
+            command_log: Mutex::new(None),
+            shown: RwLock::new(ShownState::default()),
+            #[cfg(feature = "image")]
+            lcd_frame: Mutex::new(None),
+            image_options: RwLock::new(ImageOptions::default()),
+            device_lock: None,
+        }
     }
 }
 
@@ -83,6 +294,212 @@ impl Ajazz {
         self.kind
     }
 
+    /// Overrides geometry of the device, for clones that share Product ID with a known
+    /// device but differ in key count or key resolution.
+    ///
+    /// Display keys are laid out as `rows` x `cols`, keys without display of the kind
+    /// are kept after them. Input is still parsed with key remapping of the kind.
+    /// Cached images of keys outside of the new layout are dropped
+    pub fn override_layout(
+        &self,
+        rows: u8,
+        cols: u8,
+        key_image_size: (usize, usize),
+    ) -> Result<(), AjazzError> {
+        let keys = u16::from(rows) * u16::from(cols);
+        let extra_keys = u16::from(self.kind.key_count() - self.kind.display_key_count());
+        let (width, height) = key_image_size;
+        if keys == 0 || keys + extra_keys > u16::from(u8::MAX) || width == 0 || height == 0 {
+            return Err(AjazzError::UnsupportedOperation);
+        }
+
+        *write(&self.layout) = Some(LayoutOverride {
+            rows,
+            cols,
+            key_image_size,
+        });
+        write(&self.image_cache).retain(|key, _| u16::from(*key) < keys);
+
+        Ok(())
+    }
+
+    /// Returns amount of rows of display keys, see [Ajazz::override_layout]
+    pub fn row_count(&self) -> u8 {
+        match *read(&self.layout) {
+            Some(layout) => layout.rows,
+            None => self.kind.row_count(),
+        }
+    }
+
+    /// Returns amount of columns of display keys, see [Ajazz::override_layout]
+    pub fn column_count(&self) -> u8 {
+        match *read(&self.layout) {
+            Some(layout) => layout.cols,
+            None => self.kind.column_count(),
+        }
+    }
+
+    /// Returns amount of keys with display, see [Ajazz::override_layout]
+    pub fn display_key_count(&self) -> u8 {
+        match *read(&self.layout) {
+            Some(layout) => layout.rows * layout.cols,
+            None => self.kind.display_key_count(),
+        }
+    }
+
+    /// Returns amount of keys, including ones without display, see [Ajazz::override_layout]
+    pub fn key_count(&self) -> u8 {
+        let extra_keys = self.kind.key_count() - self.kind.display_key_count();
+        self.display_key_count() + extra_keys
+    }
+
+    /// Returns indices of keys with display, see [Ajazz::override_layout]
+    pub fn display_keys(&self) -> Range<u8> {
+        0..self.display_key_count()
+    }
+
+    /// Returns indices of touch keys, see [Kind::touch_keys] and [Ajazz::override_layout]
+    pub fn touch_keys(&self) -> Range<u8> {
+        let first = self.display_key_count();
+        first..first + self.kind.touch_key_count()
+    }
+
+    /// Fails unless the key has a display to show images on
+    pub(crate) fn check_display_key(&self, key: u8) -> Result<(), AjazzError> {
+        self.kind.check_display_key(key, self.display_key_count())
+    }
+
+    /// Returns image format used by key images, see [Ajazz::override_layout]
+    pub fn key_image_format(&self) -> ImageFormat {
+        let format = self.kind.key_image_format();
+        match *read(&self.layout) {
+            Some(layout) => ImageFormat {
+                size: layout.key_image_size,
+                ..format
+            },
+            None => format,
+        }
+    }
+
+    /// Sets options used when key images are converted, e.g. dithering
+    pub fn set_image_options(&self, options: ImageOptions) {
+        *write(&self.image_options) = options;
+    }
+
+    /// Sets color correction of key images, which keeps other image options
+    pub fn set_color_profile(&self, profile: ColorProfile) {
+        write(&self.image_options).color_profile = profile;
+    }
+
+    /// Returns options used when key images are converted
+    pub fn image_options(&self) -> ImageOptions {
+        *read(&self.image_options)
+    }
+
+    /// Converts the image to key image data using the device's format and options
+    #[cfg(feature = "image")]
+    pub(crate) fn encode_key_image(&self, image: DynamicImage) -> Result<Vec<u8>, ImageError> {
+        convert_image_with_options(self.key_image_format(), image, self.image_options())
+    }
+
+    /// Sets what to do with the device when it's dropped
+    pub fn set_drop_policy(&self, policy: DropPolicy) {
+        *lock(&self.drop_policy) = policy;
+    }
+
+    /// Makes brightness, clear and flush commands read the device response and fail with
+    /// [AjazzError::NoAck] if it isn't a successful one, instead of silently doing nothing.
+    ///
+    /// Responses are read from the input stream, so it's best to enable it
+    /// when nothing else reads input at the same time
+    pub fn set_ack_verification(&self, enabled: bool) {
+        self.verify_acks.store(enabled, Ordering::Release);
+    }
+
+    /// Sets receiver of connection counters: written packets and bytes, flush durations
+    /// and read errors. `None` stops reporting
+    pub fn set_metrics(&self, metrics: Option<Arc<dyn Metrics>>) {
+        *write(&self.metrics) = metrics;
+    }
+
+    /// Starts appending every report exchanged with the device to the file, with time
+    /// since the call. Replaces the log enabled before.
+    ///
+    /// The log can be read back and printed with [CommandLog](crate::replay::CommandLog)
+    pub fn enable_command_log(&self, path: impl AsRef<Path>) -> Result<(), IoError> {
+        *lock(&self.command_log) = Some(CommandLogWriter::open(path)?);
+        Ok(())
+    }
+
+    /// Stops logging reports enabled by [enable_command_log](Ajazz::enable_command_log)
+    pub fn disable_command_log(&self) {
+        *lock(&self.command_log) = None;
+    }
+
+    /// Logs the report if the command log is enabled
+    fn log_report(&self, direction: Direction, data: &[u8]) {
+        if let Some(log) = lock(&self.command_log).as_mut() {
+            // Failing log must not break the device
+            let _ = log.record(direction, data);
+        }
+    }
+
+    /// Sets how long to wait for the device response to a command, 1 second by default.
+    ///
+    /// After 3 responses in a row don't arrive in time, the device is
+    /// considered wedged: every following call fails with [AjazzError::DeviceWedged]
+    /// right away, so the application can reconnect instead of waiting on a dead device
+    pub fn set_command_timeout(&self, timeout: Duration) {
+        *lock(&self.command_timeout) = timeout;
+    }
+
+    /// Limits bytes of images waiting in the cache for the flush, `None` by default.
+    ///
+    /// Images replace earlier ones of the same key, so the cache only grows with large
+    /// images. Setting an image that doesn't fit fails with [AjazzError::ImageCacheFull],
+    /// leaving the cache as it was
+    pub fn set_image_cache_limit(&self, limit: Option<usize>) {
+        *lock(&self.image_cache_limit) = limit;
+    }
+
+    /// Sets delay between reports carrying image data, `None` writes them back to back.
+    ///
+    /// Some USB 2.0 hubs drop 1025 byte reports written back to back, which shows up
+    /// as corrupted images. A delay of about a millisecond makes them reliable,
+    /// at the cost of slower image updates
+    pub fn set_report_pacing(&self, delay: Option<Duration>) {
+        *lock(&self.report_pacing) = delay.filter(|delay| !delay.is_zero());
+    }
+
+    /// Sets how many times a report is written while the device accepts only a part of it,
+    /// 1 by default. Report still not accepted after the last attempt fails with
+    /// [AjazzError::PartialWrite], short writes otherwise corrupt images silently.
+    /// Values below 1 are treated as 1
+    pub fn set_write_attempts(&self, attempts: u8) {
+        self.write_attempts
+            .store(attempts.max(1), Ordering::Release);
+    }
+
+    /// Returns amount of key images waiting for the flush
+    pub fn cache_len(&self) -> usize {
+        read(&self.image_cache).len()
+    }
+
+    /// Drops key images waiting for the flush, keys keep showing what they showed
+    pub fn clear_cache(&self) {
+        write(&self.image_cache).clear();
+    }
+
+    /// Returns true if the initialization packet was sent to the device
+    pub fn is_initialized(&self) -> bool {
+        *lock(&self.init_state) == InitState::Ready
+    }
+
+    /// Returns true if the device stopped responding, see [Ajazz::set_command_timeout]
+    pub fn is_wedged(&self) -> bool {
+        self.missed_acks.load(Ordering::Acquire) >= WATCHDOG_MISSED_ACKS
+    }
+
     /// Returns manufacturer string of the device
     pub fn manufacturer(&self) -> Result<String, AjazzError> {
         Ok(self
@@ -118,221 +535,410 @@ impl Ajazz {
     pub fn firmware_version(&self) -> Result<String, AjazzError> {
         let mut buff = request::FEATURE_REPORT_VERSION.clone();
         self.hid.get_feature_report(buff.as_mut_slice())?;
+        self.log_report(Direction::FeatureReport, &buff);
 
         let version = extract_string(&buff[0..])?;
         Ok(version)
     }
 
-    /// Sleeps the device
-    pub fn sleep(&self) -> Result<(), AjazzError> {
-        self.initialize()?;
+    /// Sets blocking mode of the connection, used by reads without timeout.
+    /// Does nothing if the mode is already set
+    pub fn set_blocking_mode(&self, blocking: bool) -> Result<(), AjazzError> {
+        let mut current = lock(&self.blocking);
+        if *current == Some(blocking) {
+            return Ok(());
+        }
 
-        let packet = self.kind.sleep_packet();
-        self.hid.write(packet.as_slice())?;
+        self.hid.set_blocking_mode(blocking)?;
+        *current = Some(blocking);
 
         Ok(())
     }
 
-    /// Make periodic events to the device, to keep it alive
-    pub fn keep_alive(&self) -> Result<(), AjazzError> {
+    /// Reads input from the device.
+    /// If timeout is not specified, behavior depends on the blocking mode of the connection
+    pub fn read_input(&self, timeout: Option<Duration>) -> Result<AjazzInput, AjazzError> {
         self.initialize()?;
 
-        let packet = self.kind.keep_alive_packet();
-        self.hid.write(packet.as_slice())?;
+        let pending = lock(&self.pending_inputs).pop_front();
+        let data = match pending {
+            Some(data) => data,
+            None => self.read_data(codes::INPUT_PACKET_LENGTH, timeout)?,
+        };
 
-        Ok(())
-    }
+        if is_command_response(&data) {
+            // Command may be waiting for it on another thread,
+            // responses of commands that didn't wait are dropped
+            if self.response_waiters.load(Ordering::Acquire) > 0 {
+                lock(&self.routed_responses).push_back(data);
+            }
+            return Ok(AjazzInput::NoData);
+        }
 
-    /// Returns device state reader for this device
-    pub fn get_reader(self: &Arc<Self>) -> Arc<DeviceStateReader> {
-        #[allow(clippy::arc_with_non_send_sync)]
-        Arc::new(DeviceStateReader {
-            device: self.clone(),
-            states: Mutex::new(DeviceState {
-                buttons: vec![false; self.kind.key_count() as usize],
-                encoders: vec![false; self.kind.encoder_count() as usize],
-            }),
-        })
+        match parse_input(self.kind, &data)? {
+            AjazzInput::ButtonStateChange(mut buttons) => {
+                buttons.resize(self.key_count() as usize, false);
+                Ok(AjazzInput::ButtonStateChange(buttons))
+            }
+            input => Ok(input),
+        }
     }
 
-    /// Shutdown the device
-    pub fn shutdown(&self) -> Result<(), AjazzError> {
+    /// Sends the command framed with the request header and padded to the report length,
+    /// e.g. `b"LIG\x00\x00\x32"`.
+    ///
+    /// Advanced, meant for exploring undocumented commands. The SDK doesn't know what
+    /// the command does, so its own state may no longer match the device
+    pub fn send_raw_command(&self, command: &[u8]) -> Result<(), AjazzError> {
         self.initialize()?;
 
-        let packet = self.kind.shutdown_packet();
-        self.hid.write(packet.as_slice())?;
-
-        let packet = self.kind.sleep_packet();
-        self.hid.write(packet.as_slice())?;
+        let Some(packet) = self.kind.raw_command_packet(command) else {
+            let limit = self.kind.raw_command_limit();
+            return Err(AjazzError::CommandTooLong(command.len(), limit));
+        };
+        self.write_report(packet.as_slice())?;
 
         Ok(())
     }
 
-    /// Reads input from the device
-    pub fn read_input(&self, timeout: Option<Duration>) -> Result<AjazzInput, AjazzError> {
-        self.initialize()?;
-
-        let data = self.read_data(codes::INPUT_PACKET_LENGTH, timeout)?;
-        self.kind.parse_input(&data)
+    /// Reads a raw report of up to `length` bytes, e.g. a response to
+    /// [Ajazz::send_raw_command]. Returns empty data if the timeout passes.
+    ///
+    /// Advanced, reports read this way are not parsed as input
+    pub fn read_raw_report(
+        &self,
+        length: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, AjazzError> {
+        Ok(self.read_data(length, timeout)?)
     }
 
-    /// Resets the device
-    pub fn reset(&self) -> Result<(), AjazzError> {
-        self.initialize()?;
+    /// Reads feature report with the id, of up to `length` bytes without the id.
+    ///
+    /// Advanced, meant for exploring undocumented reports
+    pub fn read_raw_feature_report(
+        &self,
+        report_id: u8,
+        length: usize,
+    ) -> Result<Vec<u8>, AjazzError> {
+        let mut buf = vec![0x00; length + 1];
+        buf[0] = report_id;
+        let read = self.hid.get_feature_report(buf.as_mut_slice())?;
+        buf.truncate(read);
+        self.log_report(Direction::FeatureReport, &buf);
 
-        self.set_brightness(100)?;
-        self.clear_all_button_images()
+        Ok(buf)
     }
 
-    /// Sets brightness of the device, value range is 0 - 100
-    pub fn set_brightness(&self, percent: u8) -> Result<(), AjazzError> {
-        self.initialize()?;
-
-        let buf = self.kind.brightness_packet(percent);
-        self.hid.write(buf.as_slice())?;
+    /// Flushes the button's image to the device.
+    ///
+    /// If sending fails midway, images already sent leave the cache and the rest stays
+    /// in it, so [retry_flush](Ajazz::retry_flush) sends only what's missing.
+    /// Keys sent before the failure are returned in [AjazzError::PartialFlush]
+    pub fn flush(&self) -> Result<(), AjazzError> {
+        self.flush_with_progress(|_| {})
+    }
 
+    /// Flushes the button's image to the device, calling `progress` after every key is sent.
+    /// Useful for showing progress of large updates on slow devices
+    pub fn flush_with_progress(
+        &self,
+        progress: impl FnMut(FlushProgress),
+    ) -> Result<(), AjazzError> {
+        self.flush_cache(progress)?;
         Ok(())
     }
 
-    /// Sets button's image to blank, changes must be flushed with `.flush()` before
-    /// they will appear on the device!
-    pub fn clear_button_image(&self, key: u8) -> Result<(), AjazzError> {
-        self.initialize()?;
-
-        let packet = self.kind.clear_button_image_packet(key);
-        self.hid.write(packet.as_slice())?;
-
-        Ok(())
+    /// Sends images left in the cache by a failed [flush](Ajazz::flush) and commits
+    /// images sent before the failure, returning keys whose images were sent by this call
+    pub fn retry_flush(&self) -> Result<Vec<u8>, AjazzError> {
+        self.flush_cache(|_| {})
     }
 
-    /// Flushes the button's image to the device
-    pub fn flush(&self) -> Result<(), AjazzError> {
+    /// Sends cached images one by one and commits them, returning the sent keys
+    fn flush_cache(
+        &self,
+        mut progress: impl FnMut(FlushProgress),
+    ) -> Result<Vec<u8>, AjazzError> {
         self.initialize()?;
+        let _flushing = lock(&self.flush_lock);
 
         let is_empty = {
-            let images = self
-                .image_cache
-                .read()
-                .map_err(|_| AjazzError::PoisonError)?;
+            let images = read(&self.image_cache);
 
-            images.is_empty()
+            images.is_empty() && !self.streamed.load(Ordering::Acquire)
         };
 
         if is_empty {
-            return Ok(());
+            return Ok(vec![]);
         }
 
-        let mut images = self
-            .image_cache
-            .write()
-            .map_err(|_| AjazzError::PoisonError)?;
+        let started = Instant::now();
+        // Cache is swapped out, so images can be set while the slow writes go on
+        let mut images = self.take_cached_images();
+
+        let total_keys = images.len();
+        let mut sent = vec![];
+        while let Some((key, image_data)) = images.pop_first() {
+            if let Err(e) = self.write_key_image(key, &image_data) {
+                images.insert(key, image_data);
+                self.restore_unsent_images(images);
+                return Err(flush_error(sent, e));
+            }
 
-        for image in images.iter() {
-            self.write_key_image(image.key, &image.image_data)?;
+            // Sent images wait for the commit like streamed ones, even if it fails
+            self.streamed.store(true, Ordering::Release);
+            sent.push(key);
+            progress(FlushProgress {
+                sent_keys: sent.len(),
+                total_keys,
+            });
         }
 
         let packet = self.kind.flush_packet();
-        self.hid.write(packet.as_slice())?;
-        images.clear();
+        if let Err(e) = self.write_verified_packet(&packet) {
+            return Err(flush_error(sent, e));
+        }
+        self.streamed.store(false, Ordering::Release);
+        if let Some(metrics) = read(&self.metrics).as_ref() {
+            metrics.flushed(started.elapsed());
+        }
 
-        Ok(())
+        Ok(sent)
     }
 
-    /// Sets blank images to every button, changes must be flushed with `.flush()` before
+    /// Sets specified button's image, changes must be flushed with `.flush()` before
     /// they will appear on the device!
-    pub fn clear_all_button_images(&self) -> Result<(), AjazzError> {
+    ///
+    /// Passing [Arc] shares the buffer with the cache without copying it,
+    /// which is useful for animations that show the same frames repeatedly
+    pub fn set_button_image_data(
+        &self,
+        key: u8,
+        image_data: impl Into<Arc<[u8]>>,
+    ) -> Result<(), AjazzError> {
         self.initialize()?;
-        self.clear_button_image(codes::CMD_CLEAR_ALL)?;
-
-        if self.kind.is_v2_api() {
-            // Mirabox "v2" requires flush to commit clearing the background
-            let packet = self.kind.flush_packet();
-            self.hid.write(packet.as_slice())?;
-        }
-
+        self.write_image_to_cache(key, image_data.into())?;
         Ok(())
     }
 
     /// Sets specified button's image, changes must be flushed with `.flush()` before
     /// they will appear on the device!
-    pub fn set_button_image_data(&self, key: u8, image_data: &[u8]) -> Result<(), AjazzError> {
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn set_button_image(&self, key: u8, image: DynamicImage) -> Result<(), AjazzError> {
         self.initialize()?;
-        self.write_image_to_cache(key, image_data)?;
+        let image_data = self.encode_key_image(image)?;
+        self.write_image_to_cache(key, image_data.into())?;
         Ok(())
     }
 
-    /// Sets specified button's image, changes must be flushed with `.flush()` before
-    /// they will appear on the device!
-    pub fn set_button_image(&self, key: u8, image: DynamicImage) -> Result<(), AjazzError> {
+    /// Encodes the image and sends it to the device right away, skipping the cache.
+    /// Changes must be flushed with `.flush()` before they will appear on the device!
+    ///
+    /// Image is encoded into a buffer reused between calls and isn't kept after it's sent,
+    /// so memory used by images stays at a single encoded key, no matter how many keys
+    /// are updated. Useful for hosts with little memory, e.g. Raspberry Pi Zero.
+    /// Streamed images are not included in [Ajazz::export_state]
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn stream_button_image(&self, key: u8, image: DynamicImage) -> Result<(), AjazzError> {
         self.initialize()?;
-        let image_data = convert_image(self.kind, image)?;
-        self.write_image_to_cache(key, &image_data)?;
+        self.check_display_key(key)?;
+
+        let mut buf = lock(&self.encode_buffer);
+        encode_image_into(
+            self.key_image_format(),
+            image,
+            self.image_options(),
+            &mut buf,
+        )?;
+
+        // Image waiting in the cache is older, it must not replace the streamed one
+        let _flushing = lock(&self.flush_lock);
+        write(&self.image_cache).remove(&key);
+
+        let packet = self.kind.key_image_announce_packet(key, &buf);
+        self.write_report(packet.as_slice())?;
+        self.write_image_data_reports(&buf, WriteImageParameters::for_kind(self.kind))?;
+        self.streamed.store(true, Ordering::Release);
+
+        // Image isn't kept, so the key is forgotten instead of exporting an outdated one
+        self.record_cleared_key(key);
         Ok(())
     }
 
-    /// Set logo image
+    /// Set logo image, stretching it to the logo size
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
     pub fn set_logo_image(&self, image: DynamicImage) -> Result<(), AjazzError> {
+        self.set_logo_image_with_scaling(image, LogoScaling::Stretch)
+    }
+
+    /// Sets logo image, fitting it into the logo size according to the scaling
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn set_logo_image_with_scaling(
+        &self,
+        image: DynamicImage,
+        scaling: LogoScaling,
+    ) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        let Some(size) = self.kind.boot_logo_size() else {
+            return Err(AjazzError::UnsupportedOperation);
+        };
+
+        let image = scale_image(image, size, scaling);
+        let image_data = convert_image_with_format(self.kind.logo_image_format(), image)?;
+        self.set_logo_image_data(&image_data)
+    }
+
+    /// Sets boot logo from data already encoded in [Kind::logo_image_format]
+    pub fn set_logo_image_data(&self, image_data: &[u8]) -> Result<(), AjazzError> {
         self.initialize()?;
 
         if self.kind.boot_logo_size().is_none() {
             return Err(AjazzError::UnsupportedOperation);
         }
 
-        let image_data = convert_image_with_format(self.kind.logo_image_format(), image)?;
-        self.hid
-            .write(self.kind.logo_image_packet(&image_data).as_slice())?;
-        self.hid.write(self.kind.flush_packet().as_slice())?;
-        self.write_image_data_reports(&image_data, WriteImageParameters::for_kind(self.kind))?;
+        self.write_report(self.kind.logo_image_packet(image_data).as_slice())?;
+        self.write_report(self.kind.flush_packet().as_slice())?;
+        self.write_image_data_reports(image_data, WriteImageParameters::for_kind(self.kind))?;
         self.assert_write_complete()?;
+        self.record_logo(image_data.into());
 
         Ok(())
     }
 
     /// Initializes the device
-    fn initialize(&self) -> Result<(), AjazzError> {
-        if self.initialized.load(Ordering::Acquire) {
-            return Ok(());
+    pub(crate) fn initialize(&self) -> Result<(), AjazzError> {
+        if self.is_wedged() {
+            return Err(AjazzError::DeviceWedged);
         }
 
-        self.initialized.store(true, Ordering::Release);
+        // Callers arriving while the packet is sent wait on the lock,
+        // so nobody proceeds before the device is initialized
+        let mut state = lock(&self.init_state);
+        if *state == InitState::Ready {
+            return Ok(());
+        }
 
+        *state = InitState::Initializing;
         let packet = self.kind.initialize_packet();
-        self.hid.write(packet.as_slice())?;
+        match self.write_report(packet.as_slice()) {
+            Ok(()) => {
+                *state = InitState::Ready;
+                Ok(())
+            }
+            Err(AjazzError::HidError(e)) => {
+                *state = InitState::Failed;
+                Err(AjazzError::InitializationFailed(e))
+            }
+            Err(e) => {
+                *state = InitState::Failed;
+                Err(e)
+            }
+        }
+    }
 
-        Ok(())
+    /// Initializes the device, even if it was done before, and returns what it reports
+    /// about itself.
+    ///
+    /// Commands initialize the device on first use, so calling it is optional. It's useful
+    /// for checking that the device responds right after connecting, and for initializing
+    /// it again, e.g. after it was re-plugged or woken up by another application.
+    /// Failing to send the initialization packet is reported as
+    /// [AjazzError::InitializationFailed]
+    pub fn handshake(&self) -> Result<HandshakeInfo, AjazzError> {
+        *lock(&self.init_state) = InitState::Uninitialized;
+        self.initialize()?;
+
+        Ok(HandshakeInfo {
+            kind: self.kind,
+            firmware_version: self.firmware_version()?,
+            serial_number: self.serial_number()?,
+        })
     }
 
     /// Writes image data to Ajazz device, changes must be flushed with `.flush()` before
     /// they will appear on the device!
-    fn write_image_to_cache(&self, key: u8, image_data: &[u8]) -> Result<(), AjazzError> {
-        if key >= self.kind.display_key_count() {
-            return Err(AjazzError::InvalidKeyIndex(key));
+    fn write_image_to_cache(&self, key: u8, image_data: Arc<[u8]>) -> Result<(), AjazzError> {
+        self.check_display_key(key)?;
+
+        let mut image_cache = write(&self.image_cache);
+
+        if let Some(limit) = *lock(&self.image_cache_limit) {
+            let cached: usize = image_cache
+                .iter()
+                .filter(|(cached_key, _)| **cached_key != key)
+                .map(|(_, data)| data.len())
+                .sum();
+            if cached + image_data.len() > limit {
+                return Err(AjazzError::ImageCacheFull(limit));
+            }
         }
 
-        let cache_entry = ImageCache {
-            key,
-            image_data: image_data.to_vec(), // Convert &[u8] to Vec<u8>
-        };
+        image_cache.insert(key, image_data);
 
-        let Ok(mut image_cache) = self.image_cache.write() else {
-            return Err(AjazzError::PoisonError);
-        };
+        Ok(())
+    }
 
-        image_cache.push(cache_entry);
+    /// Takes every image waiting in the cache, leaving the cache empty
+    pub(crate) fn take_cached_images(&self) -> ImageCache {
+        std::mem::take(&mut *write(&self.image_cache))
+    }
 
+    /// Puts images back into the cache after a failed flush. Images set during the flush
+    /// are newer, so they are kept
+    fn restore_unsent_images(&self, images: ImageCache) {
+        let mut image_cache = write(&self.image_cache);
+        for (key, image_data) in images {
+            image_cache.entry(key).or_insert(image_data);
+        }
+    }
+
+    /// Writes report to the transport, counting it in the metrics.
+    /// Partially accepted report is written again, up to the write attempts
+    fn write_report(&self, data: &[u8]) -> Result<(), AjazzError> {
+        let attempts = self.write_attempts.load(Ordering::Acquire);
+        write_whole_report(self.hid.as_ref(), data, attempts, |written| {
+            self.log_report(Direction::Write, data);
+            if let Some(metrics) = read(&self.metrics).as_ref() {
+                metrics.packet_written(written);
+            }
+        })
+    }
+
+    /// Writes raw packet to the device
+    pub(crate) fn write_packet(&self, packet: &[u8]) -> Result<(), AjazzError> {
+        self.write_report(packet)?;
         Ok(())
     }
 
-    /// Writes key image to the device
-    fn write_key_image(&self, key: u8, image_data: &[u8]) -> Result<(), AjazzError> {
-        if key >= self.kind.display_key_count() {
-            return Err(AjazzError::InvalidKeyIndex(key));
+    /// Writes the packet, and checks the device response if ACK verification is enabled
+    pub(crate) fn write_verified_packet(&self, packet: &[u8]) -> Result<(), AjazzError> {
+        self.write_packet(packet)?;
+        if self.verify_acks.load(Ordering::Acquire) {
+            self.assert_write_complete()?;
         }
 
+        Ok(())
+    }
+
+    /// Writes key image to the device
+    pub(crate) fn write_key_image(
+        &self,
+        key: u8,
+        image_data: &Arc<[u8]>,
+    ) -> Result<(), AjazzError> {
+        self.check_display_key(key)?;
+
         let packet = self.kind.key_image_announce_packet(key, image_data);
-        self.hid.write(packet.as_slice())?;
+        self.write_report(packet.as_slice())?;
 
         self.write_image_data_reports(image_data, WriteImageParameters::for_kind(self.kind))?;
+        self.record_key_image(key, image_data);
         Ok(())
     }
 
@@ -344,122 +950,765 @@ impl Ajazz {
         let image_report_length = parameters.image_report_length;
         let image_report_payload_length = parameters.image_report_payload_length;
 
-        let mut page_number = 0;
-        let mut bytes_remaining = image_data.len();
-
-        while bytes_remaining > 0 {
-            let this_length = bytes_remaining.min(image_report_payload_length);
-            let bytes_sent = page_number * image_report_payload_length;
+        // Reports are built in the scratch buffer, so animations don't allocate on every report
+        let mut buf = lock(&self.write_buffer);
+        buf.resize(image_report_length, 0x00);
 
-            let mut buf: Vec<u8> = vec![0x00];
-            buf.extend(&image_data[bytes_sent..bytes_sent + this_length]);
-            buf.extend(vec![0x00; image_report_length - buf.len()]);
+        let pacing = *lock(&self.report_pacing);
+        for (i, chunk) in image_data.chunks(image_report_payload_length).enumerate() {
+            if let Some(delay) = pacing.filter(|_| i > 0) {
+                std::thread::sleep(delay);
+            }
 
-            self.hid.write(buf.as_slice())?;
-            bytes_remaining -= this_length;
-            page_number += 1;
+            fill_image_report(&mut buf, chunk);
+            self.write_report(buf.as_slice())?;
         }
 
         Ok(())
     }
 
-    fn assert_write_complete(&self) -> Result<(), AjazzError> {
-        let data = self.read_data(512, Some(Duration::from_millis(1000)))?;
-        if data.len() != 512 {
-            return Err(AjazzError::BadData);
+    /// Waits for the response to the command. Input reports arriving before it are kept
+    /// for [Ajazz::read_input]
+    pub(crate) fn assert_write_complete(&self) -> Result<(), AjazzError> {
+        self.response_waiters.fetch_add(1, Ordering::AcqRel);
+        let response = self.wait_for_response();
+        if self.response_waiters.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Responses arriving after nobody waits mustn't answer later commands
+            lock(&self.routed_responses).clear();
         }
 
-        if !self.kind.is_ack_ok(&data) {
+        if !response?.is_some_and(|data| self.kind.is_ack_ok(&data)) {
+            self.missed_acks.fetch_add(1, Ordering::AcqRel);
+            if self.is_wedged() {
+                return Err(AjazzError::DeviceWedged);
+            }
             return Err(AjazzError::NoAck);
         }
 
+        self.missed_acks.store(0, Ordering::Release);
         Ok(())
     }
 
-    /// Reads data from [HidDevice]. Blocking mode is used if timeout is specified
+    /// Returns the response to the command, `None` if it didn't arrive in time.
+    /// Response may also be read by [Ajazz::read_input] on another thread,
+    /// e.g. by a [DeviceStateReader](crate::DeviceStateReader), which hands it over
+    fn wait_for_response(&self) -> Result<Option<Vec<u8>>, HidError> {
+        let deadline = Instant::now() + *lock(&self.command_timeout);
+        loop {
+            if let Some(data) = lock(&self.routed_responses).pop_front() {
+                return Ok(Some(data));
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(None);
+            }
+
+            let timeout = left.min(RESPONSE_POLL_INTERVAL);
+            let data = self.read_data(codes::INPUT_PACKET_LENGTH, Some(timeout))?;
+            if data.is_empty() {
+                continue;
+            }
+            if is_command_response(&data) {
+                return Ok(Some(data));
+            }
+
+            let mut pending = lock(&self.pending_inputs);
+            if pending.len() == MAX_PENDING_INPUTS {
+                pending.pop_front();
+            }
+            pending.push_back(data);
+        }
+    }
+
+    /// Reads data from the device. If timeout is specified, waits for data at most
+    /// that long, otherwise behavior depends on the blocking mode of the connection.
+    /// Returned buffer is truncated to the amount of bytes actually read
     fn read_data(
         &self,
         length: usize,
         timeout: Option<Duration>,
     ) -> Result<Vec<u8>, HidError> {
-        self.hid.set_blocking_mode(timeout.is_some())?;
-
         let mut buf = vec![0u8; length];
 
-        match timeout {
+        let read = match timeout {
             Some(timeout) => self
                 .hid
                 .read_timeout(buf.as_mut_slice(), timeout.as_millis() as i32),
             None => self.hid.read(buf.as_mut_slice()),
-        }?;
+        }
+        .inspect_err(|e| {
+            if let Some(metrics) = read(&self.metrics).as_ref() {
+                metrics.read_error(e);
+            }
+        })?;
+        buf.truncate(read);
+        if read > 0 {
+            self.log_report(Direction::Read, &buf);
+        }
 
         Ok(buf)
     }
 }
 
-/// Button reader that keeps state of the Ajazz and returns events instead of full states
-pub struct DeviceStateReader {
-    device: Arc<Ajazz>,
-    states: Mutex<DeviceState>,
+impl Drop for Ajazz {
+    fn drop(&mut self) {
+        let policy = std::mem::take(&mut *lock(&self.drop_policy));
+
+        // Device may be already gone, there's nobody to report errors to
+        let _ = match policy {
+            DropPolicy::Nothing => Ok(()),
+            DropPolicy::ClearScreen => self.clear_all_button_images(),
+            #[cfg(feature = "image")]
+            DropPolicy::StandbyImage(image) => self.show_on_every_key(image),
+            DropPolicy::Shutdown => self.shutdown(),
+        };
+    }
 }
 
-pub(crate) fn handle_input_state_change(
-    input: AjazzInput,
-    current_state: &mut DeviceState,
-) -> Result<Vec<Event>, AjazzError> {
-    let mut updates = vec![];
-    match input {
-        AjazzInput::ButtonStateChange(buttons) => {
-            for (index, is_changed) in buttons.iter().enumerate() {
-                if !is_changed {
-                    continue;
-                }
+#[cfg(feature = "image")]
+impl Ajazz {
+    fn show_on_every_key(&self, image: DynamicImage) -> Result<(), AjazzError> {
+        let image_data: Arc<[u8]> = self.encode_key_image(image)?.into();
+        for key in 0..self.display_key_count() {
+            self.set_button_image_data(key, image_data.clone())?;
+        }
 
-                current_state.buttons[index] = !current_state.buttons[index];
-                if current_state.buttons[index] {
-                    updates.push(Event::ButtonDown(index as u8));
-                } else {
-                    updates.push(Event::ButtonUp(index as u8));
-                }
+        self.flush()
+    }
+}
+
+/// Returns error of the flush, telling which keys were sent if there were any
+fn flush_error(applied: Vec<u8>, source: AjazzError) -> AjazzError {
+    if applied.is_empty() {
+        return source;
+    }
+
+    AjazzError::PartialFlush {
+        applied,
+        source: Box::new(source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use hidapi::HidResult;
+
+    use super::*;
+    use crate::flush_packets;
+    use crate::replay::ReplayTransport;
+
+    /// Transport that counts blocking mode changes, and fails or cuts short
+    /// the first writes if asked
+    #[derive(Default)]
+    struct CountingTransport {
+        inner: ReplayTransport,
+        mode_changes: Arc<AtomicUsize>,
+        failing_writes: Arc<AtomicUsize>,
+        short_writes: Arc<AtomicUsize>,
+    }
+
+    impl Transport for CountingTransport {
+        fn write(&self, data: &[u8]) -> HidResult<usize> {
+            let failing = self.failing_writes.load(Ordering::SeqCst);
+            if failing > 0 {
+                self.failing_writes.store(failing - 1, Ordering::SeqCst);
+                return Err(HidError::HidApiError {
+                    message: "Write failed".to_string(),
+                });
+            }
+            let short = self.short_writes.load(Ordering::SeqCst);
+            if short > 0 {
+                self.short_writes.store(short - 1, Ordering::SeqCst);
+                return Ok(data.len() / 2);
             }
+            self.inner.write(data)
         }
 
-        AjazzInput::EncoderStateChange(encoders) => {
-            for (index, is_changed) in encoders.iter().enumerate() {
-                if !is_changed {
-                    continue;
-                }
+        fn read(&self, buf: &mut [u8]) -> HidResult<usize> {
+            self.inner.read(buf)
+        }
 
-                current_state.encoders[index] = !current_state.encoders[index];
-                if current_state.encoders[index] {
-                    updates.push(Event::EncoderDown(index as u8));
-                } else {
-                    updates.push(Event::EncoderUp(index as u8));
-                }
-            }
+        fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> HidResult<usize> {
+            self.inner.read_timeout(buf, timeout)
         }
 
-        AjazzInput::EncoderTwist(twist) => {
-            for (index, change) in twist.iter().enumerate() {
-                if *change != 0 {
-                    updates.push(Event::EncoderTwist(index as u8, *change));
-                }
+        fn set_blocking_mode(&self, blocking: bool) -> HidResult<()> {
+            self.mode_changes.fetch_add(1, Ordering::SeqCst);
+            self.inner.set_blocking_mode(blocking)
+        }
+
+        fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+            self.inner.get_feature_report(buf)
+        }
+
+        fn get_manufacturer_string(&self) -> HidResult<Option<String>> {
+            self.inner.get_manufacturer_string()
+        }
+
+        fn get_product_string(&self) -> HidResult<Option<String>> {
+            self.inner.get_product_string()
+        }
+
+        fn get_serial_number_string(&self) -> HidResult<Option<String>> {
+            self.inner.get_serial_number_string()
+        }
+    }
+
+    #[test]
+    fn test_blocking_mode_is_changed_only_when_needed() {
+        let transport = CountingTransport::default();
+        let mode_changes = transport.mode_changes.clone();
+        let device = Ajazz::from_transport(Kind::Akp153, transport);
+
+        for _ in 0..3 {
+            device.read_input(Some(Duration::from_millis(1))).unwrap();
+        }
+        assert_eq!(mode_changes.load(Ordering::SeqCst), 0);
+
+        device.set_blocking_mode(false).unwrap();
+        for _ in 0..3 {
+            device.set_blocking_mode(false).unwrap();
+            device.read_input(None).unwrap();
+        }
+        assert_eq!(mode_changes.load(Ordering::SeqCst), 1);
+
+        device.set_blocking_mode(true).unwrap();
+        assert_eq!(mode_changes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_cache_shares_image_buffer() {
+        let device = Ajazz::from_transport(Kind::Akp03, ReplayTransport::default());
+        let image_data: Arc<[u8]> = Arc::from([0xAA; 10]);
+
+        device.set_button_image_data(0, image_data.clone()).unwrap();
+        let cached = device.take_cached_images();
+        assert!(Arc::ptr_eq(&cached[&0], &image_data));
+    }
+
+    #[test]
+    fn test_flush_sends_latest_image_per_key() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.set_button_image_data(1, [0xAA; 10]).unwrap();
+        device.set_button_image_data(0, [0xBB; 10]).unwrap();
+        device.set_button_image_data(1, [0xCC; 10]).unwrap();
+        device.flush().unwrap();
+
+        let written = replay.written();
+        assert_eq!(written.iter().filter(|p| &p[6..9] == b"BAT").count(), 2);
+        assert!(!written.iter().any(|p| p[1..11] == [0xAA; 10]));
+        assert!(written.iter().any(|p| p[1..11] == [0xCC; 10]));
+    }
+
+    #[test]
+    fn test_image_reports_are_padded() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp153, replay.clone());
+        let payload_length =
+            WriteImageParameters::for_kind(Kind::Akp153).image_report_payload_length;
+
+        device
+            .set_button_image_data(0, vec![0xAA; payload_length + 3])
+            .unwrap();
+        device.flush().unwrap();
+
+        let written = replay.written();
+        let reports: Vec<&Vec<u8>> = written.iter().filter(|p| p[1] == 0xAA).collect();
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|r| r.len() == 513 && r[0] == 0x00));
+        assert!(reports[0][1..].iter().all(|b| *b == 0xAA));
+        assert_eq!(reports[1][1..4], [0xAA; 3]);
+        assert!(reports[1][4..].iter().all(|b| *b == 0x00));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_streamed_image_skips_cache() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        device
+            .stream_button_image(0, DynamicImage::new_rgb8(60, 60))
+            .unwrap();
+
+        let streamed = replay.written();
+        assert_eq!(&streamed[1][6..9], b"BAT");
+        assert!(!streamed.iter().any(|p| p[1..11] == [0xAA; 10]));
+
+        device.flush().unwrap();
+        let written = replay.written();
+        assert_eq!(written.len(), streamed.len() + 1);
+        assert_eq!(&written.last().unwrap()[6..9], b"STP");
+        assert!(device.export_state().keys.is_empty());
+
+        assert!(matches!(
+            device.stream_button_image(6, DynamicImage::new_rgb8(60, 60)),
+            Err(AjazzError::NoScreenOnKey(6))
+        ));
+    }
+
+    #[test]
+    fn test_flush_matches_flush_packets() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp815, replay.clone());
+        device.set_button_image_data(2, [0xAA; 700]).unwrap();
+        device.set_button_image_data(5, [0xBB; 10]).unwrap();
+        device.flush().unwrap();
+
+        let expected =
+            flush_packets(Kind::Akp815, &[(2, &[0xAA; 700]), (5, &[0xBB; 10])]).unwrap();
+        assert_eq!(replay.written()[1..], expected);
+    }
+
+    #[test]
+    fn test_poisoned_cache_is_recovered() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _cache = device.image_cache.write().unwrap();
+            panic!("panic while holding the cache");
+        }));
+        assert!(result.is_err());
+        assert!(device.image_cache.is_poisoned());
+
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        device.flush().unwrap();
+        assert!(replay.written().iter().any(|p| p[1..11] == [0xAA; 10]));
+    }
+
+    #[derive(Default)]
+    struct CountingMetrics {
+        packets: AtomicUsize,
+        bytes: AtomicUsize,
+        flushes: AtomicUsize,
+    }
+
+    impl Metrics for CountingMetrics {
+        fn packet_written(&self, bytes: usize) {
+            self.packets.fetch_add(1, Ordering::Relaxed);
+            self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+
+        fn flushed(&self, _duration: Duration) {
+            self.flushes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        let metrics = Arc::new(CountingMetrics::default());
+        device.set_metrics(Some(metrics.clone()));
+
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        device.flush().unwrap();
+
+        let written = replay.written();
+        let bytes: usize = written.iter().map(Vec::len).sum();
+        assert_eq!(metrics.packets.load(Ordering::Relaxed), written.len());
+        assert_eq!(metrics.bytes.load(Ordering::Relaxed), bytes);
+        assert_eq!(metrics.flushes.load(Ordering::Relaxed), 1);
+
+        device.set_metrics(None);
+        device.set_brightness(10).unwrap();
+        assert_eq!(metrics.packets.load(Ordering::Relaxed), written.len());
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_unsent_images() {
+        let transport = CountingTransport::default();
+        let failing_writes = transport.failing_writes.clone();
+        let replay = transport.inner.clone();
+        let device = Ajazz::from_transport(Kind::Akp03, transport);
+        for key in 0..3 {
+            device.set_button_image_data(key, [0xAA; 10]).unwrap();
+        }
+
+        // Writes start failing once the first key is sent
+        let result = device.flush_with_progress(|progress| {
+            if progress.sent_keys == 1 {
+                failing_writes.store(1, Ordering::SeqCst);
             }
+        });
+        let Err(AjazzError::PartialFlush { applied, source }) = result else {
+            panic!("unexpected result: {result:?}");
+        };
+        assert_eq!(applied, [0]);
+        assert!(matches!(*source, AjazzError::HidError(_)));
+        assert_eq!(device.cache_len(), 2);
+
+        let sent_before = replay.written().len();
+        assert_eq!(device.retry_flush().unwrap(), [1, 2]);
+        let retried = replay.written()[sent_before..].to_vec();
+        let expected =
+            flush_packets(Kind::Akp03, &[(1, &[0xAA; 10]), (2, &[0xAA; 10])]).unwrap();
+        assert_eq!(retried, expected);
+        assert_eq!(device.cache_len(), 0);
+
+        // Commit failed after every image was sent, so it's sent again
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        let result = device.flush_with_progress(|_| failing_writes.store(1, Ordering::SeqCst));
+        assert!(
+            matches!(result, Err(AjazzError::PartialFlush { applied, .. }) if applied == [0])
+        );
+        assert_eq!(device.cache_len(), 0);
+        assert!(device.retry_flush().unwrap().is_empty());
+        assert_eq!(&replay.written().last().unwrap()[6..9], b"STP");
+    }
+
+    #[test]
+    fn test_images_can_be_set_during_flush() {
+        let transport = CountingTransport::default();
+        let failing_writes = transport.failing_writes.clone();
+        let device = Ajazz::from_transport(Kind::Akp03, transport);
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        device.set_button_image_data(1, [0xAA; 10]).unwrap();
+
+        device
+            .flush_with_progress(|_| device.set_button_image_data(2, [0xBB; 10]).unwrap())
+            .unwrap();
+        assert_eq!(device.cache_len(), 1);
+
+        // Unsent image doesn't replace the one set while the flush failed
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        let result = device.flush_with_progress(|_| {
+            device.set_button_image_data(2, [0xCC; 10]).unwrap();
+            failing_writes.store(1, Ordering::SeqCst);
+        });
+        assert!(result.is_err());
+        assert_eq!(device.take_cached_images()[&2][..], [0xCC; 10]);
+    }
+
+    #[test]
+    fn test_partial_write() {
+        let transport = CountingTransport::default();
+        let short_writes = transport.short_writes.clone();
+        let replay = transport.inner.clone();
+        let device = Ajazz::from_transport(Kind::Akp03, transport);
+        device.initialize().unwrap();
+
+        short_writes.store(1, Ordering::SeqCst);
+        assert!(matches!(
+            device.set_brightness(50),
+            Err(AjazzError::PartialWrite {
+                expected: 1025,
+                written: 512
+            })
+        ));
+
+        device.set_write_attempts(3);
+        short_writes.store(2, Ordering::SeqCst);
+        device.set_brightness(50).unwrap();
+        assert_eq!(&replay.written().last().unwrap()[6..9], b"LIG");
+        assert_eq!(replay.written().len(), 2);
+    }
+
+    #[test]
+    fn test_report_pacing() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.set_report_pacing(Some(Duration::from_millis(10)));
+        device.set_button_image_data(0, [0xAA; 3000]).unwrap();
+
+        let started = Instant::now();
+        device.flush().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(replay.written().len(), 1 + 1 + 3 + 1);
+    }
+
+    #[test]
+    fn test_image_cache_limit() {
+        let device = Ajazz::from_transport(Kind::Akp03, ReplayTransport::default());
+        device.set_image_cache_limit(Some(25));
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        device.set_button_image_data(1, [0xAA; 10]).unwrap();
+        device.set_button_image_data(1, [0xAA; 15]).unwrap();
+        assert!(matches!(
+            device.set_button_image_data(2, [0xAA; 1]),
+            Err(AjazzError::ImageCacheFull(25))
+        ));
+        assert_eq!(device.cache_len(), 2);
+
+        device.clear_cache();
+        assert_eq!(device.cache_len(), 0);
+        device.set_button_image_data(2, [0xAA; 20]).unwrap();
+    }
+
+    #[test]
+    fn test_flush_progress() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay);
+        for key in 0..3 {
+            device.set_button_image_data(key, [0xAA; 10]).unwrap();
         }
 
-        _ => {}
+        let mut updates = vec![];
+        device
+            .flush_with_progress(|progress| updates.push(progress))
+            .unwrap();
+
+        assert_eq!(
+            updates,
+            (1..=3)
+                .map(|sent_keys| FlushProgress {
+                    sent_keys,
+                    total_keys: 3
+                })
+                .collect::<Vec<_>>()
+        );
     }
 
-    Ok(updates)
-}
+    #[test]
+    fn test_ack_verification() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.set_brightness(50).unwrap();
+
+        device.set_ack_verification(true);
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        device.set_brightness(60).unwrap();
+        assert!(matches!(device.set_brightness(70), Err(AjazzError::NoAck)));
+
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        assert!(matches!(
+            device.flush(),
+            Err(AjazzError::PartialFlush { source, .. }) if matches!(*source, AjazzError::NoAck)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "akp03")]
+    fn test_inputs_interleaved_with_acks() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.set_ack_verification(true);
+
+        // Key 1 is pressed while the device works on the command,
+        // input shares the header with the response
+        let mut press = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        press[..codes::RESPONSE_ACK_OK.len()].copy_from_slice(codes::RESPONSE_ACK_OK);
+        press[codes::OFFSET_ACTION_CODE] = 0x01;
+        replay.push_read(&press);
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        device.set_brightness(60).unwrap();
+
+        let input = device.read_input(Some(Duration::ZERO)).unwrap();
+        assert!(matches!(input, AjazzInput::ButtonStateChange(s) if s[0]));
+
+        // Late response of a command that didn't wait for it
+        device.set_ack_verification(false);
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        assert!(matches!(
+            device.read_input(Some(Duration::ZERO)),
+            Ok(AjazzInput::NoData)
+        ));
+    }
+
+    #[test]
+    fn test_response_read_by_input_reader() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+
+        // Reader thread gets the response while a command waits for it
+        device.response_waiters.fetch_add(1, Ordering::AcqRel);
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        assert!(matches!(
+            device.read_input(Some(Duration::ZERO)),
+            Ok(AjazzInput::NoData)
+        ));
+        device.response_waiters.fetch_sub(1, Ordering::AcqRel);
+
+        device.set_ack_verification(true);
+        device.set_brightness(60).unwrap();
+        assert!(lock(&device.routed_responses).is_empty());
+    }
 
-impl DeviceStateReader {
-    /// Reads states and returns updates
-    pub fn read(&self, timeout: Option<Duration>) -> Result<Vec<Event>, AjazzError> {
-        let input = self.device.read_input(timeout)?;
-        let mut current_state = self.states.lock().map_err(|_| AjazzError::PoisonError)?;
+    #[test]
+    fn test_watchdog_detects_wedged_device() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.set_ack_verification(true);
+        device.set_command_timeout(Duration::from_millis(1));
+
+        // A successful response resets the count
+        assert!(matches!(device.set_brightness(10), Err(AjazzError::NoAck)));
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        device.set_brightness(10).unwrap();
+
+        for _ in 1..WATCHDOG_MISSED_ACKS {
+            assert!(matches!(device.set_brightness(20), Err(AjazzError::NoAck)));
+        }
+        assert!(!device.is_wedged());
+        assert!(matches!(
+            device.set_brightness(20),
+            Err(AjazzError::DeviceWedged)
+        ));
+        assert!(device.is_wedged());
+
+        let written = replay.written().len();
+        assert!(matches!(device.flush(), Err(AjazzError::DeviceWedged)));
+        assert_eq!(replay.written().len(), written);
+    }
+
+    #[test]
+    fn test_same_brightness_is_not_resent() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        assert!(!device.is_initialized());
+        assert_eq!(device.get_brightness(), None);
+
+        device.set_brightness(30).unwrap();
+        assert!(device.is_initialized());
+        let written = replay.written().len();
+
+        device.set_brightness(30).unwrap();
+        assert_eq!(replay.written().len(), written);
+        device.set_brightness(40).unwrap();
+        assert_eq!(replay.written().len(), written + 1);
+        assert_eq!(device.get_brightness(), Some(40));
+    }
+
+    #[test]
+    fn test_handshake_initializes_again() {
+        let replay = ReplayTransport::default();
+        replay.push_feature_report(b"\x01V3.1");
+        replay.push_feature_report(b"\x01V3.2");
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+
+        let info = device.handshake().unwrap();
+        assert_eq!(info.kind, Kind::Akp03);
+        assert!(info.firmware_version.ends_with("V3.1"));
+        assert!(device.is_initialized());
+
+        device.set_brightness(30).unwrap();
+        device.handshake().unwrap();
+        let initializations = replay
+            .written()
+            .iter()
+            .filter(|p| &p[6..9] == b"DIS")
+            .count();
+        assert_eq!(initializations, 2);
+    }
 
-        let updates = handle_input_state_change(input, &mut current_state)?;
-        Ok(updates)
+    #[test]
+    fn test_failed_initialization_is_retried() {
+        let transport = CountingTransport::default();
+        transport.failing_writes.store(1, Ordering::SeqCst);
+        let replay = transport.inner.clone();
+        let device = Ajazz::from_transport(Kind::Akp03, transport);
+
+        assert!(matches!(
+            device.set_brightness(30),
+            Err(AjazzError::InitializationFailed(_))
+        ));
+        assert!(!device.is_initialized());
+        assert!(replay.written().is_empty());
+
+        device.set_brightness(30).unwrap();
+        assert!(device.is_initialized());
+        let written = replay.written();
+        assert_eq!(written.len(), 2);
+        assert_eq!(&written[0][6..9], b"DIS");
+    }
+
+    #[test]
+    fn test_raw_command_passthrough() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp153, replay.clone());
+
+        device.send_raw_command(b"LIG\x00\x00\x32").unwrap();
+        assert_eq!(
+            replay.written().last(),
+            Some(&Kind::Akp153.brightness_packet(0x32))
+        );
+
+        assert!(matches!(
+            device.send_raw_command(&[0xAA; 600]),
+            Err(AjazzError::CommandTooLong(600, 507))
+        ));
+        device.send_raw_command(&[0xAA; 507]).unwrap();
+        assert_eq!(replay.written().last().unwrap().len(), 513);
+
+        replay.push_read(b"ACK");
+        assert_eq!(device.read_raw_report(512, None).unwrap(), b"ACK");
+        assert!(device.read_raw_report(512, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_override_layout() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay);
+        assert!(matches!(
+            device.set_button_image_data(6, [0xAA; 10]),
+            Err(AjazzError::NoScreenOnKey(6))
+        ));
+
+        device.override_layout(2, 4, (72, 72)).unwrap();
+        assert_eq!(device.display_key_count(), 8);
+        assert_eq!(device.key_count(), 11);
+        assert_eq!(device.key_image_format().size, (72, 72));
+        device.set_button_image_data(7, [0xAA; 10]).unwrap();
+
+        assert!(matches!(
+            device.override_layout(0, 4, (72, 72)),
+            Err(AjazzError::UnsupportedOperation)
+        ));
+    }
+
+    #[test]
+    fn test_drop_policy() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        device.set_drop_policy(DropPolicy::ClearScreen);
+        drop(device);
+        let written = replay.written();
+        let commands: Vec<&[u8]> = written.iter().map(|p| &p[6..9]).collect();
+        assert_eq!(commands, [b"DIS", b"CLE", b"STP"]);
+
+        #[cfg(feature = "image")]
+        {
+            let replay = ReplayTransport::default();
+            let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+            device.set_drop_policy(DropPolicy::StandbyImage(DynamicImage::new_rgb8(8, 8)));
+            drop(device);
+            let written = replay.written();
+            let announces = written.iter().filter(|p| &p[6..9] == b"BAT").count();
+            assert_eq!(announces, 6);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_logo_uses_kind_format() {
+        for kind in Kind::ALL
+            .into_iter()
+            .filter(|k| k.boot_logo_size().is_some())
+        {
+            let replay = ReplayTransport::default();
+            replay.push_read(codes::RESPONSE_ACK_OK);
+            let device = Ajazz::from_transport(kind, replay.clone());
+            device
+                .set_logo_image(DynamicImage::new_rgb8(10, 10))
+                .unwrap();
+
+            let written = replay.written();
+            let logo = written.iter().position(|p| &p[6..9] == b"LOG").unwrap();
+            // Announce is followed by STP, then by image data reports
+            let image_data: Vec<u8> = written[logo + 2..]
+                .iter()
+                .flat_map(|report| report[1..].iter().copied())
+                .collect();
+
+            let image = image::load_from_memory(&image_data).unwrap();
+            let format = kind.logo_image_format();
+            let size = (image.width() as usize, image.height() as usize);
+            assert_eq!(size, format.size, "{kind:?}");
+        }
     }
 }
@@ -0,0 +1,93 @@
+//! Advisory lock that keeps two processes from driving the same device.
+//!
+//! Devices accept images from anyone who opened them, so two applications writing at
+//! once interleave their reports and both get garbage on the keys. The lock is a file in
+//! the temporary directory named after the device serial, locked for as long as the
+//! device is open. The file keeps PID of the owner to report it to the others
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use crate::AjazzError;
+
+/// Lock of a device held by the current process, released when dropped
+#[derive(Debug)]
+pub(crate) struct DeviceLock {
+    _file: File,
+}
+
+impl DeviceLock {
+    /// Locks the device with the serial.
+    ///
+    /// Returns [AjazzError::DeviceBusy] if another handle holds the lock. If the lock file
+    /// can't be created, e.g. the temporary directory is read-only, the device is left
+    /// unlocked, since the lock is only advisory
+    pub(crate) fn acquire(serial: &str) -> Result<Option<DeviceLock>, AjazzError> {
+        let Ok(mut file) = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path(serial))
+        else {
+            return Ok(None);
+        };
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(AjazzError::DeviceBusy(owner_pid(file)))
+            }
+            Err(TryLockError::Error(_)) => return Ok(None),
+        }
+
+        // Failing to record the owner only makes the error of the others less helpful
+        let _ = file
+            .set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| write!(file, "{}", std::process::id()));
+
+        Ok(Some(DeviceLock { _file: file }))
+    }
+}
+
+/// Path of the lock file of the device with the serial
+fn lock_path(serial: &str) -> PathBuf {
+    let name: String = serial
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    std::env::temp_dir().join(format!("ajazz-{name}.lock"))
+}
+
+/// Reads PID of the lock owner, 0 if it's unknown
+fn owner_pid(mut file: File) -> u32 {
+    let mut content = String::new();
+    match file.read_to_string(&mut content) {
+        Ok(_) => content.trim().parse().unwrap_or(0),
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_lock_reports_owner() {
+        let serial = format!("lock-test-{}", std::process::id());
+
+        let lock = DeviceLock::acquire(&serial).unwrap();
+        assert!(lock.is_some());
+
+        match DeviceLock::acquire(&serial) {
+            Err(AjazzError::DeviceBusy(pid)) => assert_eq!(pid, std::process::id()),
+            other => panic!("expected busy device, got {other:?}"),
+        }
+
+        drop(lock);
+        assert!(DeviceLock::acquire(&serial).unwrap().is_some());
+    }
+}
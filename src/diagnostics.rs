@@ -0,0 +1,153 @@
+//! Helpers for diagnosing why a device can't be opened.
+//!
+//! The most common failure on Linux is a device that shows up in [list_devices](crate::list_devices),
+//! but can't be opened because the current user has no access to the `hidraw` node.
+//! [check_permissions] detects this case, and [udev_rules] returns rules that fix it.
+
+use hidapi::HidApi;
+
+use crate::hid::is_permission_denied;
+use crate::info::{is_mirabox_vendor, Kind};
+
+/// Result of trying to open a single device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccessStatus {
+    /// Device was opened successfully
+    Ok,
+    /// Device is visible, but opening it failed with permission error
+    PermissionDenied,
+    /// Opening the device failed for another reason
+    Error(String),
+}
+
+/// Access information about a single device found by HidApi
+#[derive(Clone, Debug)]
+pub struct DeviceAccess {
+    /// Kind of the device
+    pub kind: Kind,
+    /// Serial number of the device, if HidApi was able to read it
+    pub serial: Option<String>,
+    /// Platform specific path of the device
+    pub path: String,
+    /// Result of the open attempt
+    pub status: AccessStatus,
+}
+
+/// Report produced by [check_permissions]
+#[derive(Clone, Debug, Default)]
+pub struct PermissionReport {
+    /// Every supported device that HidApi can see
+    pub devices: Vec<DeviceAccess>,
+}
+
+impl PermissionReport {
+    /// Returns true if at least one device failed to open because of permissions
+    pub fn has_permission_issues(&self) -> bool {
+        self.devices
+            .iter()
+            .any(|d| d.status == AccessStatus::PermissionDenied)
+    }
+
+    /// Returns true if every visible device can be opened
+    pub fn is_ok(&self) -> bool {
+        self.devices.iter().all(|d| d.status == AccessStatus::Ok)
+    }
+}
+
+/// Tries to open every supported device visible to HidApi and reports the result.
+///
+/// Devices that are already opened by the current process are opened once more,
+/// so it is safe to call this before connecting.
+///
+/// **WARNING:** To refresh the device list, use [refresh_device_list](crate::refresh_device_list)
+pub fn check_permissions(hidapi: &HidApi) -> PermissionReport {
+    let devices = hidapi
+        .device_list()
+        .filter(|d| is_mirabox_vendor(d.vendor_id()))
+        .filter_map(|d| {
+            let kind = Kind::from_vid_pid(d.vendor_id(), d.product_id())?;
+            let status = match d.open_device(hidapi) {
+                Ok(_) => AccessStatus::Ok,
+                Err(err) if is_permission_denied(&err) => AccessStatus::PermissionDenied,
+                Err(err) => AccessStatus::Error(err.to_string()),
+            };
+
+            Some(DeviceAccess {
+                kind,
+                serial: d.serial_number().map(str::to_string),
+                path: d.path().to_string_lossy().to_string(),
+                status,
+            })
+        })
+        .collect();
+
+    PermissionReport { devices }
+}
+
+/// Returns udev rules granting access to every supported device.
+///
+/// Save the output to `/etc/udev/rules.d/40-mirabox.rules` and reload the rules
+/// with `udevadm control --reload-rules && udevadm trigger`.
+pub fn udev_rules() -> String {
+    let mut rules = String::new();
+
+    for kind in Kind::ALL {
+        let ids = format!(
+            "ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\"",
+            kind.vendor_id(),
+            kind.product_id()
+        );
+        let access = "MODE=\"0660\", TAG+=\"uaccess\", GROUP=\"plugdev\"";
+
+        rules.push_str(&format!("SUBSYSTEM==\"usb\", {ids}, {access}\n"));
+        rules.push_str(&format!(
+            "KERNEL==\"hidraw*\", SUBSYSTEM==\"hidraw\", {ids}, {access}\n"
+        ));
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_udev_rules_cover_all_kinds() {
+        let rules = udev_rules();
+
+        for kind in Kind::ALL {
+            let ids = format!(
+                "ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\"",
+                kind.vendor_id(),
+                kind.product_id()
+            );
+            assert_eq!(rules.matches(&ids).count(), 2, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_permission_report() {
+        let device = |status| DeviceAccess {
+            kind: Kind::Akp153,
+            serial: None,
+            path: String::new(),
+            status,
+        };
+
+        let report = PermissionReport {
+            devices: vec![
+                device(AccessStatus::Ok),
+                device(AccessStatus::PermissionDenied),
+            ],
+        };
+        assert!(report.has_permission_issues());
+        assert!(!report.is_ok());
+
+        let report = PermissionReport {
+            devices: vec![device(AccessStatus::Ok)],
+        };
+        assert!(!report.has_permission_issues());
+        assert!(report.is_ok());
+    }
+}
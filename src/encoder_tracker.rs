@@ -0,0 +1,154 @@
+use crate::{Event, Kind};
+
+/// Bounds and step of the encoder position
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EncoderRange {
+    /// Lowest position
+    pub min: i32,
+    /// Highest position
+    pub max: i32,
+    /// How much position changes on every tick
+    pub step: i32,
+    /// Wrap around when going past the bounds instead of stopping at them
+    pub wrap: bool,
+}
+
+impl Default for EncoderRange {
+    fn default() -> Self {
+        Self {
+            min: 0,
+            max: 100,
+            step: 1,
+            wrap: false,
+        }
+    }
+}
+
+impl EncoderRange {
+    fn apply(&self, position: i32, ticks: i8) -> i32 {
+        let position = position.saturating_add(i32::from(ticks).saturating_mul(self.step));
+        if self.wrap {
+            let span = i64::from(self.max) - i64::from(self.min) + 1;
+            let offset = (i64::from(position) - i64::from(self.min)).rem_euclid(span);
+            (i64::from(self.min) + offset) as i32
+        } else {
+            self.clamp(position)
+        }
+    }
+
+    fn clamp(&self, position: i32) -> i32 {
+        position.clamp(self.min, self.max)
+    }
+}
+
+/// Integrates encoder twists into absolute positions, e.g. for volume or brightness
+#[derive(Clone, Debug)]
+pub struct EncoderTracker {
+    ranges: Vec<EncoderRange>,
+    positions: Vec<i32>,
+}
+
+impl EncoderTracker {
+    /// Creates tracker with the same range for every encoder of the device kind.
+    /// Encoders start at the lowest position
+    pub fn new(kind: Kind, range: EncoderRange) -> EncoderTracker {
+        let count = kind.encoder_count() as usize;
+        EncoderTracker {
+            ranges: vec![range; count],
+            positions: vec![range.min; count],
+        }
+    }
+
+    /// Changes range of the encoder, current position is clamped to it
+    pub fn set_range(&mut self, encoder: u8, range: EncoderRange) {
+        let index = encoder as usize;
+        if let Some(current) = self.ranges.get_mut(index) {
+            *current = range;
+            self.positions[index] = range.clamp(self.positions[index]);
+        }
+    }
+
+    /// Returns position of the encoder
+    pub fn position(&self, encoder: u8) -> Option<i32> {
+        self.positions.get(encoder as usize).copied()
+    }
+
+    /// Sets position of the encoder, clamped to its range
+    pub fn set_position(&mut self, encoder: u8, position: i32) {
+        let index = encoder as usize;
+        if let Some(range) = self.ranges.get(index) {
+            self.positions[index] = range.clamp(position);
+        }
+    }
+
+    /// Handles the event, returning [Event::EncoderPositionChanged] if the position changed
+    pub fn handle(&mut self, event: &Event) -> Option<Event> {
+        let Event::EncoderTwist(encoder, ticks) = *event else {
+            return None;
+        };
+
+        let index = encoder as usize;
+        let range = self.ranges.get(index)?;
+        let position = range.apply(self.positions[index], ticks);
+        if position == self.positions[index] {
+            return None;
+        }
+
+        self.positions[index] = position;
+        Some(Event::EncoderPositionChanged(encoder, position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_is_bounded() {
+        let mut tracker = EncoderTracker::new(
+            Kind::Akp03,
+            EncoderRange {
+                min: 0,
+                max: 10,
+                step: 4,
+                wrap: false,
+            },
+        );
+
+        assert!(tracker.handle(&Event::EncoderTwist(0, -1)).is_none());
+        assert_eq!(
+            tracker.handle(&Event::EncoderTwist(0, 2)),
+            Some(Event::EncoderPositionChanged(0, 8))
+        );
+        assert_eq!(
+            tracker.handle(&Event::EncoderTwist(0, 1)),
+            Some(Event::EncoderPositionChanged(0, 10))
+        );
+        assert!(tracker.handle(&Event::EncoderTwist(0, 1)).is_none());
+        assert_eq!(tracker.position(1), Some(0));
+        assert_eq!(tracker.position(3), None);
+    }
+
+    #[test]
+    fn test_position_wraps() {
+        let mut tracker = EncoderTracker::new(Kind::Akp03, EncoderRange::default());
+        tracker.set_range(
+            2,
+            EncoderRange {
+                min: 0,
+                max: 359,
+                step: 15,
+                wrap: true,
+            },
+        );
+
+        assert_eq!(
+            tracker.handle(&Event::EncoderTwist(2, -1)),
+            Some(Event::EncoderPositionChanged(2, 345))
+        );
+        assert_eq!(
+            tracker.handle(&Event::EncoderTwist(2, 2)),
+            Some(Event::EncoderPositionChanged(2, 15))
+        );
+    }
+}
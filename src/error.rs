@@ -0,0 +1,161 @@
+use std::str::Utf8Error;
+
+use hidapi::HidError;
+#[cfg(feature = "image")]
+use image::ImageError;
+use thiserror::Error;
+
+/// Errors that can occur while working with Ajazz devices
+#[derive(Debug, Error)]
+pub enum AjazzError {
+    /// HidApi error
+    #[error("HidApi error: {0}")]
+    HidError(#[from] HidError),
+
+    /// Failed to convert bytes into string
+    #[error("Failed to convert bytes into string: {0}")]
+    Utf8Error(#[from] Utf8Error),
+
+    /// Failed to encode image
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    #[error("Failed to encode image: {0}")]
+    ImageError(#[from] ImageError),
+
+    /// Tokio join error
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    #[error("Tokio join error: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+
+    /// Thread owning the device is no longer running
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    #[error("Device thread is no longer running")]
+    DeviceClosed,
+
+    /// Waiting was stopped by the [shutdown token](crate::AsyncAjazz::shutdown_token)
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    #[error("Waiting for the device was stopped by shutdown")]
+    ShutDown,
+
+    /// Line sent to the daemon isn't a valid command
+    #[cfg(feature = "daemon")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "daemon")))]
+    #[error("Invalid daemon command: {0:?}")]
+    InvalidCommand(String),
+
+    /// I/O error, e.g. of a daemon socket or a command log file
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    /// D-Bus error
+    #[cfg(feature = "dbus")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "dbus")))]
+    #[error("D-Bus error: {0}")]
+    DbusError(#[from] zbus::Error),
+
+    /// MQTT client error
+    #[cfg(feature = "mqtt")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "mqtt")))]
+    #[error("MQTT client error: {0}")]
+    MqttError(#[from] rumqttc::ClientError),
+
+    /// MIDI output error
+    #[cfg(feature = "midi")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "midi")))]
+    #[error("MIDI error: {0}")]
+    MidiError(String),
+
+    /// Font has no glyph for the character
+    #[cfg(feature = "text")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "text")))]
+    #[error("Font has no glyph for {0:?}")]
+    MissingGlyph(char),
+
+    /// Reader mutex was poisoned. Poisoned locks are now recovered, so it's no longer returned
+    #[error("Reader mutex was poisoned")]
+    PoisonError,
+
+    /// Key index is invalid
+    #[error("Key index is invalid: {0}")]
+    InvalidKeyIndex(u8),
+
+    /// Key has no display to show images on, e.g. it's a touch key
+    #[error("Key {0} has no screen")]
+    NoScreenOnKey(u8),
+
+    /// String doesn't name any device kind
+    #[error("Unknown device kind: {0}")]
+    UnknownKind(String),
+
+    /// No device with the serial is connected
+    #[error("No connected device with serial {0}")]
+    UnknownDevice(String),
+
+    /// Unrecognized Product ID
+    #[error("Unrecognized Product ID: {0}")]
+    UnrecognizedPID(u16),
+
+    /// The device doesn't support doing that
+    #[error("The device doesn't support doing that")]
+    UnsupportedOperation,
+
+    /// Device sent unexpected data
+    #[error("Device sent unexpected data")]
+    BadData,
+
+    /// Invalid image size
+    #[error("Invalid image size: {0}x{1}, expected {2}x{3}")]
+    InvalidImageSize(usize, usize, usize, usize),
+
+    /// Device didn't respond with ACK
+    #[error("Device didn't respond with ACK")]
+    NoAck,
+
+    /// Device didn't accept the initialization packet
+    #[error("Failed to initialize the device: {0}")]
+    InitializationFailed(HidError),
+
+    /// Device stopped responding to commands and has to be reconnected
+    #[error("Device stopped responding to commands, reconnect it")]
+    DeviceWedged,
+
+    /// Raw command doesn't fit into a single report
+    #[error("Command is too long: {0} bytes, at most {1} bytes fit into a report")]
+    CommandTooLong(usize, usize),
+
+    /// Device is visible, but the current user is not allowed to open it
+    #[error("Permission denied while opening the device, check udev rules")]
+    PermissionDenied,
+
+    /// Device is already open by another handle, holding PID of the owning process
+    /// or 0 if it's unknown
+    #[error("Device is already in use by process {0}")]
+    DeviceBusy(u32),
+
+    /// Image doesn't fit into the image cache limit of this many bytes,
+    /// see [Ajazz::set_image_cache_limit](crate::Ajazz::set_image_cache_limit)
+    #[error("Image cache is over its limit of {0} bytes, flush it first")]
+    ImageCacheFull(usize),
+
+    /// Device accepted only a part of the report, even after retries,
+    /// see [Ajazz::set_write_attempts](crate::Ajazz::set_write_attempts)
+    #[error("Device accepted {written} of {expected} bytes of the report")]
+    PartialWrite {
+        /// Length of the report
+        expected: usize,
+        /// Bytes accepted by the device
+        written: usize,
+    },
+
+    /// Flush failed after some images were sent, see [Ajazz::flush](crate::Ajazz::flush)
+    #[error("Flush failed after sending images of keys {applied:?}: {source}")]
+    PartialFlush {
+        /// Keys whose images were sent to the device before the failure
+        applied: Vec<u8>,
+        /// Error that stopped the flush
+        source: Box<AjazzError>,
+    },
+}
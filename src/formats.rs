@@ -0,0 +1,136 @@
+use crate::Kind;
+
+/// Image rotation
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ImageRotation {
+    /// No rotation
+    Rot0,
+    /// 90 degrees clockwise
+    Rot90,
+    /// 180 degrees
+    Rot180,
+    /// 90 degrees counter-clockwise
+    Rot270,
+}
+
+/// Image mirroring
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ImageMirroring {
+    /// No image mirroring
+    None,
+    /// Flip by X
+    X,
+    /// Flip by Y
+    Y,
+    /// Flip by both axes
+    Both,
+}
+
+/// Image format
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ImageMode {
+    /// No image
+    None,
+    /// Jpeg image
+    JPEG,
+    /// Uncompressed image, 3 bytes per pixel in blue, green, red order, rows from top to bottom
+    BGR,
+    /// Uncompressed image, 2 bytes per pixel as little-endian 5-6-5 bit red, green, blue,
+    /// rows from top to bottom
+    RGB565,
+}
+
+/// Image format used by the device
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ImageFormat {
+    /// Image format/mode
+    pub mode: ImageMode,
+    /// Image size
+    pub size: (usize, usize),
+    /// Image rotation
+    pub rotation: ImageRotation,
+    /// Image mirroring
+    pub mirror: ImageMirroring,
+}
+
+impl Default for ImageFormat {
+    fn default() -> Self {
+        Self {
+            mode: ImageMode::None,
+            size: (0, 0),
+            rotation: ImageRotation::Rot0,
+            mirror: ImageMirroring::None,
+        }
+    }
+}
+
+/// How the image is fitted into the boot logo, see [Ajazz::set_logo_image_with_scaling]
+///
+/// [Ajazz::set_logo_image_with_scaling]: crate::Ajazz::set_logo_image_with_scaling
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum LogoScaling {
+    /// Scales the image to cover the whole logo, cropping parts that don't fit
+    Cover,
+    /// Scales the image to fit into the logo, leaving black bars
+    Contain,
+    /// Stretches the image to the logo size, ignoring aspect ratio
+    Stretch,
+    /// Places the image in the middle without scaling
+    Center,
+}
+
+/// Dithering used when colors are reduced to the 16-bit depth of key panels
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum Dithering {
+    /// Colors are sent as is
+    #[default]
+    None,
+    /// Ordered dithering with 4x4 Bayer matrix, stable between frames of animations
+    Ordered,
+    /// Floyd–Steinberg error diffusion, smoothest for static images
+    FloydSteinberg,
+}
+
+/// Color correction of the panel, applied before images are encoded
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ColorProfile {
+    /// Exponent applied to normalized channel values, above 1.0 darkens mid-tones
+    /// of washed-out panels
+    pub gamma: f32,
+    /// Multipliers of red, green and blue channels, e.g. `[1.0, 1.0, 0.9]`
+    /// for panels rendering too blue
+    pub white_point: [f32; 3],
+}
+
+impl Default for ColorProfile {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            white_point: [1.0; 3],
+        }
+    }
+}
+
+/// Options of the image conversion
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ImageOptions {
+    /// Dithering of gradients, which otherwise show banding on small key panels
+    pub dithering: Dithering,
+    /// Color correction of the panel
+    pub color_profile: ColorProfile,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct WriteImageParameters {
+    pub image_report_length: usize,
+    pub image_report_payload_length: usize,
+}
+
+impl WriteImageParameters {
+    pub fn for_kind(kind: Kind) -> Self {
+        Self {
+            image_report_length: kind.image_report_length(),
+            image_report_payload_length: kind.max_image_payload(),
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+
+#[cfg(feature = "image")]
+use crate::burn_in::{BurnInProtection, Phase};
+use crate::device::Ajazz;
+use crate::locks::lock;
+use crate::schedule::BrightnessSchedule;
+use crate::AjazzError;
+
+enum Frame {
+    #[cfg(feature = "image")]
+    Image(DynamicImage),
+    Data(Arc<[u8]>),
+}
+
+#[derive(Default)]
+struct PumpState {
+    pending: BTreeMap<u8, Frame>,
+    dropped: u64,
+    #[cfg(feature = "image")]
+    protection: Option<ProtectionState>,
+    schedule: Option<ScheduleState>,
+}
+
+struct ScheduleState {
+    schedule: BrightnessSchedule,
+    /// Brightness set by the pump, `None` until it's set for the first time
+    applied: Option<u8>,
+}
+
+impl ScheduleState {
+    /// Returns brightness to set, if it differs from the one set by the pump
+    fn pending_brightness(&self) -> Option<u8> {
+        let brightness = self.schedule.current_brightness(SystemTime::now());
+        brightness.filter(|brightness| self.applied != Some(*brightness))
+    }
+}
+
+#[cfg(feature = "image")]
+struct ProtectionState {
+    settings: BurnInProtection,
+    started: Instant,
+    /// Phase images on the device were sent with
+    phase: Phase,
+    /// Original images shown on the device, re-sent when the phase changes
+    shown: BTreeMap<u8, DynamicImage>,
+}
+
+#[cfg(feature = "image")]
+impl ProtectionState {
+    fn current_phase(&self) -> Phase {
+        self.settings.phase(self.started.elapsed())
+    }
+
+    /// Adds shown images to the frames if the phase changed, and remembers images
+    /// of the frames to re-send them on the next change
+    fn track(&mut self, frames: &mut BTreeMap<u8, Frame>) -> (BurnInProtection, Phase) {
+        let phase = self.current_phase();
+        if phase != self.phase {
+            for (key, image) in self.shown.iter() {
+                frames
+                    .entry(*key)
+                    .or_insert_with(|| Frame::Image(image.clone()));
+            }
+            self.phase = phase;
+        }
+
+        for (key, frame) in frames.iter() {
+            match frame {
+                Frame::Image(image) => self.shown.insert(*key, image.clone()),
+                Frame::Data(_) => self.shown.remove(key),
+            };
+        }
+
+        (self.settings, phase)
+    }
+}
+
+impl PumpState {
+    /// Returns true if there are pending frames, or images must be re-sent for the new phase
+    fn is_ready(&self) -> bool {
+        #[cfg(not(feature = "image"))]
+        let phase_changed = false;
+        #[cfg(feature = "image")]
+        let phase_changed = self
+            .protection
+            .as_ref()
+            .is_some_and(|p| !p.shown.is_empty() && p.current_phase() != p.phase);
+        let brightness_changed = self
+            .schedule
+            .as_ref()
+            .is_some_and(|s| s.pending_brightness().is_some());
+        !self.pending.is_empty() || phase_changed || brightness_changed
+    }
+
+    /// Returns how long to wait before checking the phase and the schedule again
+    fn phase_timeout(&self) -> Option<Duration> {
+        #[cfg(not(feature = "image"))]
+        let phase = None;
+        #[cfg(feature = "image")]
+        let phase = self
+            .protection
+            .as_ref()
+            .filter(|p| !p.shown.is_empty())
+            .map(|p| p.settings.until_next_phase(p.started.elapsed()));
+        let schedule = self
+            .schedule
+            .as_ref()
+            .and_then(|s| s.schedule.until_next_change(SystemTime::now()));
+        phase.into_iter().chain(schedule).min()
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<PumpState>,
+    ready: Condvar,
+}
+
+/// Frame scheduler with latest-wins semantics.
+///
+/// Producers can push frames for any key at any rate, while the device thread calls
+/// [FramePump::pump] whenever the link is free. Only the most recent pending frame
+/// of every key is sent, older ones are dropped. Useful for live meters and timers,
+/// which produce frames faster than the device can show them.
+///
+/// Pump is cheap to clone, all clones share the same pending frames.
+///
+/// ```no_run
+/// # fn example(device: &ajazz_sdk::Ajazz, frames: Vec<Vec<u8>>) -> Result<(), ajazz_sdk::AjazzError> {
+/// let pump = ajazz_sdk::FramePump::new();
+///
+/// let producer = pump.clone();
+/// std::thread::spawn(move || {
+///     for frame in frames {
+///         producer.push_frame_data(0, frame);
+///     }
+/// });
+///
+/// loop {
+///     if pump.wait(None) {
+///         pump.pump(device)?;
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct FramePump {
+    shared: Arc<Shared>,
+}
+
+impl FramePump {
+    /// Creates an empty pump
+    pub fn new() -> FramePump {
+        FramePump::default()
+    }
+
+    /// Queues the image for the key, replacing frame that wasn't sent yet
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn push_frame(&self, key: u8, image: DynamicImage) {
+        self.push(key, Frame::Image(image));
+    }
+
+    /// Queues already encoded image data for the key, replacing frame that wasn't sent yet
+    pub fn push_frame_data(&self, key: u8, image_data: impl Into<Arc<[u8]>>) {
+        self.push(key, Frame::Data(image_data.into()));
+    }
+
+    /// Enables protection of OLED keys from burn-in, or disables it with `None`.
+    ///
+    /// Images pushed with [FramePump::push_frame] are shifted and dimmed over time,
+    /// [FramePump::wait] wakes up and [FramePump::pump] re-sends them whenever that happens.
+    /// Already encoded frames can't be altered and are sent as is
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn set_burn_in_protection(&self, protection: Option<BurnInProtection>) {
+        let mut state = self.lock();
+        state.protection = protection.map(|settings| ProtectionState {
+            settings,
+            started: Instant::now(),
+            phase: settings.phase(Duration::ZERO),
+            shown: BTreeMap::new(),
+        });
+        self.shared.ready.notify_all();
+    }
+
+    /// Makes the pump follow the brightness schedule, or stops following it with `None`.
+    ///
+    /// [FramePump::wait] wakes up and [FramePump::pump] sets the brightness whenever
+    /// the schedule changes it, so the application doesn't have to track the time
+    pub fn set_brightness_schedule(&self, schedule: Option<BrightnessSchedule>) {
+        let mut state = self.lock();
+        state.schedule = schedule.map(|schedule| ScheduleState {
+            schedule,
+            applied: None,
+        });
+        self.shared.ready.notify_all();
+    }
+
+    /// Returns amount of keys with pending frames
+    pub fn pending(&self) -> usize {
+        self.lock().pending.len()
+    }
+
+    /// Returns amount of frames that were replaced by newer ones before being sent
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    /// Blocks until there is a pending frame or the timeout passes.
+    /// Returns true if there are frames to send
+    pub fn wait(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.lock();
+
+        while !state.is_ready() {
+            let now = Instant::now();
+            let left = deadline.map(|deadline| deadline.saturating_duration_since(now));
+            if left.is_some_and(|left| left.is_zero()) {
+                return false;
+            }
+
+            // Burn-in protection changes phase on its own, without anyone notifying
+            state = match left.into_iter().chain(state.phase_timeout()).min() {
+                Some(timeout) => {
+                    self.shared
+                        .ready
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self
+                    .shared
+                    .ready
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+
+        true
+    }
+
+    /// Sends the latest pending frame of every key to the device, followed by a single flush.
+    /// Sets brightness too, if the schedule changed it. Returns amount of frames sent
+    pub fn pump(&self, device: &Ajazz) -> Result<usize, AjazzError> {
+        let brightness = self
+            .lock()
+            .schedule
+            .as_ref()
+            .and_then(ScheduleState::pending_brightness);
+        if let Some(brightness) = brightness {
+            device.set_brightness(brightness)?;
+            if let Some(schedule) = self.lock().schedule.as_mut() {
+                schedule.applied = Some(brightness);
+            }
+        }
+
+        #[cfg(feature = "image")]
+        let (frames, protection) = {
+            let mut state = self.lock();
+            let mut frames = std::mem::take(&mut state.pending);
+            let protection = state.protection.as_mut().map(|p| p.track(&mut frames));
+            (frames, protection)
+        };
+        #[cfg(not(feature = "image"))]
+        let frames = std::mem::take(&mut self.lock().pending);
+
+        if frames.is_empty() {
+            return Ok(0);
+        }
+
+        device.transaction(|tx| {
+            for (key, frame) in frames.iter() {
+                match frame {
+                    #[cfg(feature = "image")]
+                    Frame::Image(image) => match protection {
+                        Some((settings, phase)) => {
+                            tx.set_button_image(*key, settings.apply(image, phase))?
+                        }
+                        None => tx.set_button_image(*key, image.clone())?,
+                    },
+                    Frame::Data(image_data) => {
+                        tx.set_button_image_data(*key, image_data.clone())?
+                    }
+                }
+            }
+            Ok(frames.len())
+        })
+    }
+
+    fn push(&self, key: u8, frame: Frame) {
+        let mut state = self.lock();
+        if state.pending.insert(key, frame).is_some() {
+            state.dropped += 1;
+        }
+        self.shared.ready.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PumpState> {
+        lock(&self.shared.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayTransport;
+    use crate::schedule::BrightnessChange;
+    use crate::Kind;
+
+    #[test]
+    fn test_pump_sends_latest_frame_per_key() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        let pump = FramePump::new();
+
+        pump.push_frame_data(0, [0xAA; 10]);
+        pump.push_frame_data(0, [0xBB; 10]);
+        pump.push_frame_data(1, [0xCC; 10]);
+        assert_eq!(pump.pending(), 2);
+        assert_eq!(pump.dropped(), 1);
+
+        assert_eq!(pump.pump(&device).unwrap(), 2);
+        assert_eq!(pump.pending(), 0);
+
+        let written = replay.written();
+        assert!(written.iter().any(|p| p[1..11] == [0xBB; 10]));
+        assert!(!written.iter().any(|p| p[1..11] == [0xAA; 10]));
+        assert_eq!(&written.last().unwrap()[6..9], b"STP");
+
+        assert_eq!(pump.pump(&device).unwrap(), 0);
+        assert_eq!(replay.written().len(), written.len());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_burn_in_protection_resends_images() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        let pump = FramePump::new();
+        pump.set_burn_in_protection(Some(BurnInProtection {
+            shift_interval: Duration::from_millis(20),
+            ..Default::default()
+        }));
+
+        pump.push_frame(0, DynamicImage::new_rgb8(60, 60));
+        assert_eq!(pump.pump(&device).unwrap(), 1);
+        assert_eq!(pump.pump(&device).unwrap(), 0);
+
+        assert!(pump.wait(Some(Duration::from_secs(1))));
+        assert_eq!(pump.pump(&device).unwrap(), 1);
+
+        pump.set_burn_in_protection(None);
+        assert!(!pump.wait(Some(Duration::from_millis(30))));
+    }
+
+    #[test]
+    fn test_pump_follows_brightness_schedule() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        let pump = FramePump::new();
+        pump.set_brightness_schedule(Some(BrightnessSchedule {
+            changes: vec![BrightnessChange {
+                hour: 0,
+                minute: 0,
+                percent: 40,
+            }],
+            utc_offset_minutes: 0,
+        }));
+
+        assert!(pump.wait(Some(Duration::from_millis(1))));
+        assert_eq!(pump.pump(&device).unwrap(), 0);
+        assert_eq!(device.get_brightness(), Some(40));
+        let written = replay.written().len();
+
+        // Brightness is set once, until the schedule changes it
+        assert!(!pump.wait(Some(Duration::from_millis(1))));
+        assert_eq!(pump.pump(&device).unwrap(), 0);
+        assert_eq!(replay.written().len(), written);
+    }
+
+    #[test]
+    fn test_wait_returns_on_timeout() {
+        let pump = FramePump::new();
+        assert!(!pump.wait(Some(Duration::from_millis(1))));
+
+        pump.push_frame_data(0, [0xAA; 10]);
+        assert!(pump.wait(Some(Duration::from_millis(1))));
+    }
+}
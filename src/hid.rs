@@ -1,6 +1,8 @@
 use std::collections::HashSet;
-use hidapi::{HidApi, HidResult};
+use std::io::ErrorKind;
+use hidapi::{HidApi, HidError, HidResult};
 use crate::info::{is_mirabox_vendor, Kind};
+use crate::AjazzError;
 
 /// Creates an instance of the HidApi
 ///
@@ -18,6 +20,22 @@ pub fn refresh_device_list(hidapi: &mut HidApi) -> HidResult<()> {
 ///
 /// **WARNING:** To refresh the list, use [refresh_device_list]
 pub fn list_devices(hidapi: &HidApi) -> Vec<(Kind, String)> {
+    list_devices_by(hidapi, Kind::from_vid_pid)
+}
+
+/// Same as [list_devices], but also returns devices with known Vendor ID and unknown
+/// Product ID as [Kind::UnknownV1] or [Kind::UnknownV2].
+/// Such devices can be opened with [Ajazz::connect_unknown](crate::Ajazz::connect_unknown)
+///
+/// **WARNING:** To refresh the list, use [refresh_device_list]
+pub fn list_devices_with_unknown(hidapi: &HidApi) -> Vec<(Kind, String)> {
+    list_devices_by(hidapi, Kind::from_vid_pid_or_unknown)
+}
+
+fn list_devices_by(
+    hidapi: &HidApi,
+    resolve: impl Fn(u16, u16) -> Option<Kind>,
+) -> Vec<(Kind, String)> {
     hidapi
         .device_list()
         .filter_map(|d| {
@@ -26,12 +44,27 @@ pub fn list_devices(hidapi: &HidApi) -> Vec<(Kind, String)> {
             }
 
             let serial = d.serial_number()?;
-            Some((
-                Kind::from_vid_pid(d.vendor_id(), d.product_id())?,
-                serial.to_string(),
-            ))
+            Some((resolve(d.vendor_id(), d.product_id())?, serial.to_string()))
         })
         .collect::<HashSet<_>>()
         .into_iter()
         .collect()
 }
+
+/// Returns true if HidApi failed to open the device because of missing permissions
+pub(crate) fn is_permission_denied(err: &HidError) -> bool {
+    match err {
+        HidError::HidApiError { message } => message.contains("Permission denied"),
+        HidError::IoError { error } => error.kind() == ErrorKind::PermissionDenied,
+        _ => false,
+    }
+}
+
+/// Converts error returned while opening the device into [AjazzError]
+pub(crate) fn open_error(err: HidError) -> AjazzError {
+    if is_permission_denied(&err) {
+        AjazzError::PermissionDenied
+    } else {
+        AjazzError::HidError(err)
+    }
+}
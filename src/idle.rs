@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use crate::Event;
+
+/// Inactivity tracking of the reader, reported as [Event::Idle] and [Event::Active]
+pub(crate) struct IdleTracker {
+    timeout: Duration,
+    last_activity: Instant,
+    idle: bool,
+}
+
+impl IdleTracker {
+    pub(crate) fn new(timeout: Duration, now: Instant) -> IdleTracker {
+        IdleTracker {
+            timeout,
+            last_activity: now,
+            idle: false,
+        }
+    }
+
+    /// Returns time left until the deck becomes idle, or `None` if it already is
+    pub(crate) fn until_idle(&self, now: Instant) -> Option<Duration> {
+        if self.idle {
+            return None;
+        }
+
+        Some((self.last_activity + self.timeout).saturating_duration_since(now))
+    }
+
+    /// Puts [Event::Active] before the first input after being idle,
+    /// and adds [Event::Idle] once nothing happened for the timeout
+    pub(crate) fn handle(&mut self, updates: &mut Vec<Event>, now: Instant) {
+        if updates
+            .iter()
+            .any(|update| !matches!(update, Event::Tick(_)))
+        {
+            self.last_activity = now;
+            if self.idle {
+                self.idle = false;
+                updates.insert(0, Event::Active);
+            }
+            return;
+        }
+
+        let inactive = now.saturating_duration_since(self.last_activity);
+        if !self.idle && inactive >= self.timeout {
+            self.idle = true;
+            updates.push(Event::Idle(inactive));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_and_active_transitions() {
+        let start = Instant::now();
+        let mut idle = IdleTracker::new(Duration::from_secs(10), start);
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut updates = vec![];
+        idle.handle(&mut updates, at(5));
+        assert!(updates.is_empty());
+        assert_eq!(idle.until_idle(at(5)), Some(Duration::from_secs(5)));
+
+        idle.handle(&mut updates, at(11));
+        assert_eq!(updates, [Event::Idle(Duration::from_secs(11))]);
+        assert_eq!(idle.until_idle(at(11)), None);
+
+        // Idle is reported once
+        let mut updates = vec![Event::Tick(0)];
+        idle.handle(&mut updates, at(30));
+        assert_eq!(updates, [Event::Tick(0)]);
+
+        let mut updates = vec![Event::ButtonDown(2)];
+        idle.handle(&mut updates, at(31));
+        assert_eq!(updates, [Event::Active, Event::ButtonDown(2)]);
+        assert_eq!(idle.until_idle(at(31)), Some(Duration::from_secs(10)));
+    }
+}
@@ -1,64 +1,118 @@
 use image::{ColorType, DynamicImage, GenericImageView, ImageError};
 use image::codecs::jpeg::JpegEncoder;
-use image::imageops::FilterType;
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
 
+use crate::formats::{
+    ColorProfile, Dithering, ImageFormat, ImageMirroring, ImageMode, ImageOptions,
+    ImageRotation, LogoScaling,
+};
 use crate::{Kind, AjazzError};
 
-/// Image rotation
-#[derive(Copy, Clone, Debug, Hash)]
-pub enum ImageRotation {
-    /// No rotation
-    Rot0,
-    /// 90 degrees clockwise
-    Rot90,
-    /// 180 degrees
-    Rot180,
-    /// 90 degrees counter-clockwise
-    Rot270,
-}
-
-/// Image mirroring
-#[derive(Copy, Clone, Debug, Hash)]
-pub enum ImageMirroring {
-    /// No image mirroring
-    None,
-    /// Flip by X
-    X,
-    /// Flip by Y
-    Y,
-    /// Flip by both axes
-    Both,
-}
-
-/// Image format
-#[derive(Copy, Clone, Debug, Hash)]
-pub enum ImageMode {
-    /// No image
-    None,
-    /// Jpeg image
-    JPEG,
-}
-
-/// Image format used by the device
-#[derive(Copy, Clone, Debug, Hash)]
-pub struct ImageFormat {
-    /// Image format/mode
-    pub mode: ImageMode,
-    /// Image size
-    pub size: (usize, usize),
-    /// Image rotation
-    pub rotation: ImageRotation,
-    /// Image mirroring
-    pub mirror: ImageMirroring,
-}
-
-impl Default for ImageFormat {
-    fn default() -> Self {
-        Self {
-            mode: ImageMode::None,
-            size: (0, 0),
-            rotation: ImageRotation::Rot0,
-            mirror: ImageMirroring::None,
+/// Fits the image into the size according to the scaling
+pub(crate) fn scale_image(
+    image: DynamicImage,
+    size: (usize, usize),
+    scaling: LogoScaling,
+) -> DynamicImage {
+    let (w, h) = (size.0 as u32, size.1 as u32);
+    let image = match scaling {
+        LogoScaling::Cover => return image.resize_to_fill(w, h, FilterType::Triangle),
+        LogoScaling::Stretch => return image.resize_exact(w, h, FilterType::Triangle),
+        LogoScaling::Contain => image.resize(w, h, FilterType::Triangle),
+        LogoScaling::Center => image,
+    };
+
+    let mut canvas = RgbImage::from_pixel(w, h, Rgb([0, 0, 0]));
+    let x = (i64::from(w) - i64::from(image.width())) / 2;
+    let y = (i64::from(h) - i64::from(image.height())) / 2;
+    imageops::overlay(&mut canvas, &image.into_rgb8(), x, y);
+
+    DynamicImage::ImageRgb8(canvas)
+}
+
+impl ColorProfile {
+    /// Applies the correction to every pixel of the image
+    fn apply(&self, image: &mut RgbImage) {
+        if *self == ColorProfile::default() {
+            return;
+        }
+
+        let table: Vec<[u8; 256]> = self
+            .white_point
+            .iter()
+            .map(|multiplier| {
+                std::array::from_fn(|value| {
+                    let value = (value as f32 / 255.0).powf(self.gamma) * multiplier;
+                    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+                })
+            })
+            .collect();
+
+        for pixel in image.pixels_mut() {
+            for (channel, table) in pixel.0.iter_mut().zip(&table) {
+                *channel = table[usize::from(*channel)];
+            }
+        }
+    }
+}
+
+/// Thresholds of the ordered dithering
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Bits of red, green and blue channels of 16-bit panels
+const PANEL_BITS: [u32; 3] = [5, 6, 5];
+
+/// Rounds value to the closest level of channel with the depth
+fn quantize(value: f32, bits: u32) -> u8 {
+    let levels = ((1 << bits) - 1) as f32;
+    let level = (value.clamp(0.0, 255.0) / 255.0 * levels).round();
+    (level / levels * 255.0).round() as u8
+}
+
+/// Reduces colors of the image to the panel depth, spreading the error as the dithering says
+fn dither(image: &mut RgbImage, dithering: Dithering) {
+    match dithering {
+        Dithering::None => {}
+        Dithering::Ordered => {
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                let threshold =
+                    (f32::from(BAYER_4X4[y as usize % 4][x as usize % 4]) + 0.5) / 16.0 - 0.5;
+                for (channel, bits) in pixel.0.iter_mut().zip(PANEL_BITS) {
+                    let step = 255.0 / ((1 << bits) - 1) as f32;
+                    *channel = quantize(f32::from(*channel) + threshold * step, bits);
+                }
+            }
+        }
+        Dithering::FloydSteinberg => {
+            let (w, h) = (image.width() as usize, image.height() as usize);
+            let mut errors = vec![[0.0f32; 3]; w * h];
+            for y in 0..h {
+                for x in 0..w {
+                    let pixel = image.get_pixel_mut(x as u32, y as u32);
+                    let mut error = [0.0; 3];
+                    for (i, bits) in PANEL_BITS.into_iter().enumerate() {
+                        let value = f32::from(pixel.0[i]) + errors[y * w + x][i];
+                        pixel.0[i] = quantize(value, bits);
+                        error[i] = value - f32::from(pixel.0[i]);
+                    }
+
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let nx = x as isize + dx;
+                        if nx < 0 || nx as usize >= w || y + dy >= h {
+                            return;
+                        }
+                        let target = &mut errors[(y + dy) * w + nx as usize];
+                        for i in 0..3 {
+                            target[i] += error[i] * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                }
+            }
         }
     }
 }
@@ -68,11 +122,12 @@ pub fn convert_image(kind: Kind, image: DynamicImage) -> Result<Vec<u8>, ImageEr
     convert_image_with_format(kind.key_image_format(), image)
 }
 
-/// Converts image into image data depending on provided image format
-pub fn convert_image_with_format(
+/// Applies size, orientation and color options of the format, leaving image not encoded
+fn prepare_image(
     image_format: ImageFormat,
     image: DynamicImage,
-) -> Result<Vec<u8>, ImageError> {
+    options: ImageOptions,
+) -> RgbImage {
     // Ensuring size of the image
     let (ws, hs) = image_format.size;
 
@@ -94,18 +149,104 @@ pub fn convert_image_with_format(
         ImageMirroring::Both => image.fliph().flipv(),
     };
 
-    let image_data = image.into_rgb8().to_vec();
+    let mut image = image.into_rgb8();
+    options.color_profile.apply(&mut image);
+    dither(&mut image, options.dithering);
+    image
+}
+
+/// Returns the image as the device kind will show it on a key: scaled to the key size,
+/// upright, without compression. Useful for previews in configurators
+pub fn preview_key_image(kind: Kind, image: DynamicImage) -> DynamicImage {
+    preview_image_with_options(kind.key_image_format(), image, ImageOptions::default())
+}
+
+/// Returns the image as a panel with the format and options will show it,
+/// see [preview_key_image]
+pub fn preview_image_with_options(
+    image_format: ImageFormat,
+    image: DynamicImage,
+    options: ImageOptions,
+) -> DynamicImage {
+    let image = DynamicImage::ImageRgb8(prepare_image(image_format, image, options));
+    revert_orientation(image_format, image)
+}
+
+/// Reverts rotation and mirroring of the format, which only compensate how the panel
+/// is mounted, returning the image upright as seen on the device
+pub(crate) fn revert_orientation(
+    image_format: ImageFormat,
+    image: DynamicImage,
+) -> DynamicImage {
+    let image = match image_format.mirror {
+        ImageMirroring::None => image,
+        ImageMirroring::X => image.fliph(),
+        ImageMirroring::Y => image.flipv(),
+        ImageMirroring::Both => image.fliph().flipv(),
+    };
+    match image_format.rotation {
+        ImageRotation::Rot0 => image,
+        ImageRotation::Rot90 => image.rotate270(),
+        ImageRotation::Rot180 => image.rotate180(),
+        ImageRotation::Rot270 => image.rotate90(),
+    }
+}
+
+/// Converts image into image data depending on provided image format
+pub fn convert_image_with_format(
+    image_format: ImageFormat,
+    image: DynamicImage,
+) -> Result<Vec<u8>, ImageError> {
+    convert_image_with_options(image_format, image, ImageOptions::default())
+}
+
+/// Converts image into image data depending on provided image format and options
+pub fn convert_image_with_options(
+    image_format: ImageFormat,
+    image: DynamicImage,
+    options: ImageOptions,
+) -> Result<Vec<u8>, ImageError> {
+    let mut buf = Vec::new();
+    encode_image_into(image_format, image, options, &mut buf)?;
+    Ok(buf)
+}
+
+/// Converts image into image data, replacing content of the buffer.
+/// Lets callers reuse a single buffer instead of allocating one for every image
+pub(crate) fn encode_image_into(
+    image_format: ImageFormat,
+    image: DynamicImage,
+    options: ImageOptions,
+    buf: &mut Vec<u8>,
+) -> Result<(), ImageError> {
+    let (ws, hs) = image_format.size;
+    let image = prepare_image(image_format, image, options);
+    let image_data = image.into_raw();
+    buf.clear();
 
     // Encoding image
     match image_format.mode {
-        ImageMode::None => Ok(vec![]),
+        ImageMode::None => {}
         ImageMode::JPEG => {
-            let mut buf = Vec::new();
-            let mut encoder = JpegEncoder::new_with_quality(&mut buf, 90);
+            let mut encoder = JpegEncoder::new_with_quality(&mut *buf, 90);
             encoder.encode(&image_data, ws as u32, hs as u32, ColorType::Rgb8.into())?;
-            Ok(buf)
         }
+        ImageMode::BGR => buf.extend(
+            image_data
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]]),
+        ),
+        ImageMode::RGB565 => buf.extend(image_data.chunks_exact(3).flat_map(|pixel| {
+            let (r, g, b) = (
+                u16::from(pixel[0]),
+                u16::from(pixel[1]),
+                u16::from(pixel[2]),
+            );
+            (((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3)).to_le_bytes()
+        })),
     }
+
+    Ok(())
 }
 
 /// Converts image into image data depending on provided kind of device, can be safely ran inside [multi_thread](tokio::runtime::Builder::new_multi_thread) runtime
@@ -130,6 +271,7 @@ pub fn convert_image_with_format_async(
 }
 
 /// Rect to be used when trying to send image to lcd screen
+#[derive(Clone)]
 pub struct ImageRect {
     /// Width of the image
     pub w: u16,
@@ -167,26 +309,121 @@ impl ImageRect {
     }
 }
 
-#[derive(Clone, Copy)]
-pub(crate) struct WriteImageParameters {
-    pub image_report_length: usize,
-    pub image_report_payload_length: usize,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl WriteImageParameters {
-    pub fn for_kind(kind: Kind) -> Self {
-        let image_report_length = match kind {
-            kind if kind.is_v1_api() => 513,
-            kind if kind.is_v2_api() => 1025,
-            _ => 1024,
+    fn format(mode: ImageMode) -> ImageFormat {
+        ImageFormat {
+            mode,
+            size: (2, 1),
+            rotation: ImageRotation::Rot0,
+            mirror: ImageMirroring::None,
+        }
+    }
+
+    #[test]
+    fn test_raw_modes_round_trip() {
+        let mut image = RgbImage::new(2, 1);
+        image.put_pixel(0, 0, Rgb([0xF8, 0x04, 0x10]));
+        image.put_pixel(1, 0, Rgb([0x08, 0xFC, 0xF8]));
+        let image = DynamicImage::ImageRgb8(image);
+
+        let bgr = convert_image_with_format(format(ImageMode::BGR), image.clone()).unwrap();
+        assert_eq!(bgr, [0x10, 0x04, 0xF8, 0xF8, 0xFC, 0x08]);
+        let decoded: Vec<u8> = bgr.chunks(3).flat_map(|p| [p[2], p[1], p[0]]).collect();
+        assert_eq!(decoded, image.to_rgb8().into_raw());
+
+        let rgb565 =
+            convert_image_with_format(format(ImageMode::RGB565), image.clone()).unwrap();
+        assert_eq!(rgb565.len(), 4);
+        let decoded: Vec<u8> = rgb565
+            .chunks(2)
+            .flat_map(|p| {
+                let value = u16::from_le_bytes([p[0], p[1]]);
+                [
+                    ((value >> 11) << 3) as u8,
+                    (((value >> 5) & 0x3F) << 2) as u8,
+                    ((value & 0x1F) << 3) as u8,
+                ]
+            })
+            .collect();
+        assert_eq!(decoded, image.to_rgb8().into_raw());
+    }
+
+    #[test]
+    fn test_dithering_keeps_average_color() {
+        for dithering in [Dithering::Ordered, Dithering::FloydSteinberg] {
+            let mut image = RgbImage::from_pixel(16, 16, Rgb([134, 134, 134]));
+            dither(&mut image, dithering);
+
+            let reds: Vec<u32> = image.pixels().map(|p| u32::from(p.0[0])).collect();
+            assert!(reds.iter().all(|r| *r == 132 || *r == 140), "{dithering:?}");
+            let average = reds.iter().sum::<u32>() / reds.len() as u32;
+            assert!((133..=135).contains(&average), "{dithering:?}: {average}");
+        }
+
+        let mut image = RgbImage::from_pixel(4, 4, Rgb([134, 134, 134]));
+        dither(&mut image, Dithering::None);
+        assert_eq!(image.get_pixel(0, 0), &Rgb([134, 134, 134]));
+    }
+
+    #[test]
+    fn test_color_profile() {
+        let mut image = RgbImage::from_pixel(1, 1, Rgb([128, 255, 255]));
+        ColorProfile::default().apply(&mut image);
+        assert_eq!(image.get_pixel(0, 0), &Rgb([128, 255, 255]));
+
+        let profile = ColorProfile {
+            gamma: 2.0,
+            white_point: [1.0, 1.0, 0.5],
         };
+        profile.apply(&mut image);
+        assert_eq!(image.get_pixel(0, 0), &Rgb([64, 255, 128]));
+    }
+
+    #[test]
+    fn test_preview_is_upright() {
+        let mut image = RgbImage::from_pixel(20, 20, Rgb([0, 0, 0]));
+        image.put_pixel(0, 0, Rgb([255, 255, 255]));
+        let image = DynamicImage::ImageRgb8(image);
+
+        for kind in [Kind::Akp153, Kind::Akp815, Kind::Akp03] {
+            let preview = preview_key_image(kind, image.clone());
+            let (w, h) = kind.key_image_format().size;
+            assert_eq!(preview.dimensions(), (w as u32, h as u32), "{kind:?}");
+
+            let preview = preview.into_rgb8();
+            let corner = preview.get_pixel(0, 0).0[0];
+            let opposite = preview.get_pixel(w as u32 - 1, h as u32 - 1).0[0];
+            assert!(corner > opposite, "{kind:?}");
+        }
+    }
 
-        let image_report_header_length = 1;
-        let image_report_payload_length = image_report_length - image_report_header_length;
+    #[test]
+    fn test_scale_image_modes() {
+        let image =
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(200, 100, Rgb([255, 255, 255])));
 
-        Self {
-            image_report_length,
-            image_report_payload_length,
+        for scaling in [
+            LogoScaling::Cover,
+            LogoScaling::Contain,
+            LogoScaling::Stretch,
+        ] {
+            let scaled = scale_image(image.clone(), (100, 100), scaling);
+            assert_eq!(scaled.dimensions(), (100, 100));
         }
+
+        let contained =
+            scale_image(image.clone(), (100, 100), LogoScaling::Contain).into_rgb8();
+        assert_eq!(contained.get_pixel(50, 10), &Rgb([0, 0, 0]));
+        assert_eq!(contained.get_pixel(50, 50), &Rgb([255, 255, 255]));
+
+        let covered = scale_image(image.clone(), (100, 100), LogoScaling::Cover).into_rgb8();
+        assert_eq!(covered.get_pixel(50, 10), &Rgb([255, 255, 255]));
+
+        let centered = scale_image(image, (300, 300), LogoScaling::Center).into_rgb8();
+        assert_eq!(centered.get_pixel(10, 150), &Rgb([0, 0, 0]));
+        assert_eq!(centered.get_pixel(150, 150), &Rgb([255, 255, 255]));
     }
 }
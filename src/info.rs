@@ -1,6 +1,11 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::str::FromStr;
+
 use crate::{
-    protocol::codes,
-    images::{ImageFormat, ImageMirroring, ImageMode, ImageRotation},
+    protocol::{codes, AjazzProtocolParser},
+    formats::{ImageFormat, ImageMirroring, ImageMode, ImageRotation},
+    AjazzError,
 };
 
 /// Returns true for vendors IDs that are handled by the library
@@ -11,8 +16,9 @@ pub const fn is_mirabox_vendor(vendor: u16) -> bool {
     )
 }
 
-/// Enum describing kinds of Ajazz devices
+/// Enum describing kinds of Ajazz devices. New devices may be added in minor releases
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum Kind {
     /// Ajazz AKP153
     Akp153,
@@ -30,12 +36,48 @@ pub enum Kind {
     Akp03R,
     /// Ajazz AKP03R rev 2
     Akp03RRev2,
+    /// Unknown device with Mirabox v1 vendor ID, uses AKP153 geometry without key remapping
+    UnknownV1 {
+        /// Product ID of the device
+        pid: u16,
+    },
+    /// Unknown device with Mirabox v2 vendor ID, uses AKP03 geometry
+    UnknownV2 {
+        /// Product ID of the device
+        pid: u16,
+    },
+}
+
+/// Family of devices sharing layout and protocol details
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum Family {
+    /// AKP153, AKP153E and AKP153R
+    Akp153,
+    /// AKP815
+    Akp815,
+    /// AKP03, AKP03E, AKP03R and AKP03R rev 2
+    Akp03,
+    /// Devices not recognized by the library
+    Unknown,
 }
 
 impl Kind {
-    /// Creates [Kind] variant from Vendor ID and Product ID
+    /// All device kinds known to the library, including ones disabled by cargo features
+    pub const ALL: [Kind; 8] = [
+        Kind::Akp153,
+        Kind::Akp153E,
+        Kind::Akp153R,
+        Kind::Akp815,
+        Kind::Akp03,
+        Kind::Akp03E,
+        Kind::Akp03R,
+        Kind::Akp03RRev2,
+    ];
+
+    /// Creates [Kind] variant from Vendor ID and Product ID.
+    /// Kinds of device families disabled by cargo features are not recognized
     pub const fn from_vid_pid(vid: u16, pid: u16) -> Option<Kind> {
-        match vid {
+        let kind = match vid {
             codes::VENDOR_ID_MIRABOX_V1 => match pid {
                 codes::PID_AJAZZ_AKP153 => Some(Kind::Akp153),
                 codes::PID_AJAZZ_AKP815 => Some(Kind::Akp815),
@@ -52,10 +94,109 @@ impl Kind {
                 _ => None,
             },
 
+            _ => None,
+        };
+
+        match kind {
+            Some(kind) if kind.is_enabled() => Some(kind),
             _ => None,
         }
     }
 
+    /// Returns true if support of the device family is enabled by cargo features.
+    /// Unknown devices need support of the family they borrow protocol from
+    pub const fn is_enabled(&self) -> bool {
+        match self {
+            Kind::UnknownV1 { .. } => cfg!(any(feature = "akp153", feature = "akp815")),
+            Kind::UnknownV2 { .. } => cfg!(feature = "akp03"),
+            _ => match self.family() {
+                Family::Akp153 => cfg!(feature = "akp153"),
+                Family::Akp815 => cfg!(feature = "akp815"),
+                Family::Akp03 => cfg!(feature = "akp03"),
+                Family::Unknown => false,
+            },
+        }
+    }
+
+    /// Creates [Kind] variant from Vendor ID and Product ID, falling back to
+    /// [Kind::UnknownV1] or [Kind::UnknownV2] if only the vendor is recognized.
+    ///
+    /// Unknown devices work in degraded mode with conservative defaults,
+    /// their real geometry may differ
+    pub const fn from_vid_pid_or_unknown(vid: u16, pid: u16) -> Option<Kind> {
+        if let Some(kind) = Kind::from_vid_pid(vid, pid) {
+            return Some(kind);
+        }
+
+        let kind = match vid {
+            codes::VENDOR_ID_MIRABOX_V1 => Kind::UnknownV1 { pid },
+            codes::VENDOR_ID_MIRABOX_V2 => Kind::UnknownV2 { pid },
+            _ => return None,
+        };
+
+        if kind.is_enabled() {
+            Some(kind)
+        } else {
+            None
+        }
+    }
+
+    /// Human readable name of the device kind
+    pub const fn display_name(&self) -> &'static str {
+        match self {
+            Kind::Akp153 => "Ajazz AKP153",
+            Kind::Akp153E => "Ajazz AKP153E",
+            Kind::Akp153R => "Ajazz AKP153R",
+            Kind::Akp815 => "Ajazz AKP815",
+            Kind::Akp03 => "Ajazz AKP03",
+            Kind::Akp03E => "Ajazz AKP03E",
+            Kind::Akp03R => "Ajazz AKP03R",
+            Kind::Akp03RRev2 => "Ajazz AKP03R (rev 2)",
+            Kind::UnknownV1 { .. } | Kind::UnknownV2 { .. } => "Unknown Mirabox device",
+        }
+    }
+
+    /// Stable identifier of the device kind, used by [Display] and [FromStr].
+    /// Unknown kinds are identified by the vendor generation and product ID, e.g. `unknown-v1:1234`
+    pub const fn identifier(&self) -> &'static str {
+        match self {
+            Kind::Akp153 => "akp153",
+            Kind::Akp153E => "akp153e",
+            Kind::Akp153R => "akp153r",
+            Kind::Akp815 => "akp815",
+            Kind::Akp03 => "akp03",
+            Kind::Akp03E => "akp03e",
+            Kind::Akp03R => "akp03r",
+            Kind::Akp03RRev2 => "akp03r-rev2",
+            Kind::UnknownV1 { .. } => "unknown-v1",
+            Kind::UnknownV2 { .. } => "unknown-v2",
+        }
+    }
+
+    /// Names under which the same hardware is sold by other brands
+    pub const fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            Kind::Akp153 => &["Mirabox HSV293S"],
+            Kind::Akp03 => &["Mirabox N3"],
+            _ => &[],
+        }
+    }
+
+    /// Family of the device kind
+    pub const fn family(&self) -> Family {
+        match self {
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => Family::Akp153,
+            Kind::Akp815 => Family::Akp815,
+            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::Akp03RRev2 => Family::Akp03,
+            Kind::UnknownV1 { .. } | Kind::UnknownV2 { .. } => Family::Unknown,
+        }
+    }
+
+    /// Returns true for devices that are not recognized by the library
+    pub const fn is_unknown(&self) -> bool {
+        matches!(self, Kind::UnknownV1 { .. } | Kind::UnknownV2 { .. })
+    }
+
     /// Retrieves Product ID of the device
     pub const fn product_id(&self) -> u16 {
         match self {
@@ -67,6 +208,7 @@ impl Kind {
             Kind::Akp03E => codes::PID_AJAZZ_AKP03E,
             Kind::Akp03R => codes::PID_AJAZZ_AKP03R,
             Kind::Akp03RRev2 => codes::PID_AJAZZ_AKP03R_REV2,
+            Kind::UnknownV1 { pid } | Kind::UnknownV2 { pid } => *pid,
         }
     }
 
@@ -81,48 +223,127 @@ impl Kind {
             Kind::Akp03E => codes::VENDOR_ID_MIRABOX_V2,
             Kind::Akp03R => codes::VENDOR_ID_MIRABOX_V2,
             Kind::Akp03RRev2 => codes::VENDOR_ID_MIRABOX_V2,
+            Kind::UnknownV1 { .. } => codes::VENDOR_ID_MIRABOX_V1,
+            Kind::UnknownV2 { .. } => codes::VENDOR_ID_MIRABOX_V2,
         }
     }
 
     /// Amount of keys the device has
     pub const fn key_count(&self) -> u8 {
         match self {
-            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 15 + 3,
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R | Kind::UnknownV1 { .. } => 15 + 3,
             Kind::Akp815 => 15,
-            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::Akp03RRev2 => 6 + 3,
+            Kind::Akp03
+            | Kind::Akp03E
+            | Kind::Akp03R
+            | Kind::Akp03RRev2
+            | Kind::UnknownV2 { .. } => 6 + 3,
         }
     }
 
     /// Amount of display keys the device has
     pub const fn display_key_count(&self) -> u8 {
         match self {
-            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::Akp03RRev2 => 6,
+            Kind::Akp03
+            | Kind::Akp03E
+            | Kind::Akp03R
+            | Kind::Akp03RRev2
+            | Kind::UnknownV2 { .. } => 6,
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 15,
             _ => self.key_count(),
         }
     }
 
+    /// Amount of touch keys the device has, see [Kind::touch_keys]
+    pub const fn touch_key_count(&self) -> u8 {
+        match self {
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 3,
+            _ => 0,
+        }
+    }
+
+    /// Indices of keys with display, images can only be shown on them
+    pub fn display_keys(&self) -> Range<u8> {
+        0..self.display_key_count()
+    }
+
+    /// Indices of touch keys, keys without display next to the LCD strip. They come right
+    /// after display keys and are reported as [Event::TouchKeyDown](crate::Event::TouchKeyDown)
+    /// and [Event::TouchKeyUp](crate::Event::TouchKeyUp)
+    pub fn touch_keys(&self) -> Range<u8> {
+        let first = self.display_key_count();
+        first..first + self.touch_key_count()
+    }
+
+    /// Converts physical key index, the one the hardware uses in its reports, to
+    /// logical key index used by the library, see [key order](crate#key-order).
+    /// Returns `None` if the device has no such key
+    pub fn physical_to_logical(&self, index: u8) -> Option<u8> {
+        if index >= self.key_count() {
+            return None;
+        }
+
+        if self.is_v1_api() {
+            self.index_from_native_v1(index)
+        } else {
+            Some(index)
+        }
+    }
+
+    /// Fails unless the key has a display to show images on. Display key count is passed
+    /// in, as [overridden layout](crate::Ajazz::override_layout) may change it
+    pub(crate) fn check_display_key(
+        &self,
+        key: u8,
+        display_key_count: u8,
+    ) -> Result<(), AjazzError> {
+        let extra_keys = self.key_count() - self.display_key_count();
+        if key >= display_key_count + extra_keys {
+            return Err(AjazzError::InvalidKeyIndex(key));
+        }
+
+        if key >= display_key_count {
+            return Err(AjazzError::NoScreenOnKey(key));
+        }
+
+        Ok(())
+    }
+
     /// Amount of button rows the device has
     pub const fn row_count(&self) -> u8 {
         match self {
-            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 3,
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R | Kind::UnknownV1 { .. } => 3,
             Kind::Akp815 => 5,
-            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::Akp03RRev2 => 2,
+            Kind::Akp03
+            | Kind::Akp03E
+            | Kind::Akp03R
+            | Kind::Akp03RRev2
+            | Kind::UnknownV2 { .. } => 2,
         }
     }
 
     /// Amount of button columns the device has
     pub const fn column_count(&self) -> u8 {
         match self {
-            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 6,
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => 5,
+            Kind::UnknownV1 { .. } => 6,
             Kind::Akp815 => 3,
-            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::Akp03RRev2 => 3,
+            Kind::Akp03
+            | Kind::Akp03E
+            | Kind::Akp03R
+            | Kind::Akp03RRev2
+            | Kind::UnknownV2 { .. } => 3,
         }
     }
 
     /// Amount of encoders/knobs the device has
     pub const fn encoder_count(&self) -> u8 {
         match self {
-            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::Akp03RRev2 => 3,
+            Kind::Akp03
+            | Kind::Akp03E
+            | Kind::Akp03R
+            | Kind::Akp03RRev2
+            | Kind::UnknownV2 { .. } => 3,
             _ => 0,
         }
     }
@@ -136,10 +357,20 @@ impl Kind {
         }
     }
 
+    /// Returns true if keys and the LCD strip of the device have separate backlights,
+    /// see [Ajazz::set_brightness_zones](crate::Ajazz::set_brightness_zones).
+    ///
+    /// Command dimming a single zone hasn't been captured on any device yet,
+    /// so no device has zones for now
+    pub const fn has_brightness_zones(&self) -> bool {
+        false
+    }
+
     /// Size of the boot logo on the device
     pub const fn boot_logo_size(&self) -> Option<(usize, usize)> {
         match self {
             Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::Akp03RRev2 => Some((320, 240)),
+            Kind::UnknownV1 { .. } | Kind::UnknownV2 { .. } => None,
             _ => self.lcd_strip_size(),
         }
     }
@@ -152,19 +383,25 @@ impl Kind {
     /// Image format used by the device kind
     pub const fn logo_image_format(&self) -> ImageFormat {
         match self {
-            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::Akp03RRev2 => ImageFormat {
+            Kind::Akp03
+            | Kind::Akp03E
+            | Kind::Akp03R
+            | Kind::Akp03RRev2
+            | Kind::UnknownV2 { .. } => ImageFormat {
                 mode: ImageMode::JPEG,
                 size: (240, 320),
                 rotation: ImageRotation::Rot90,
                 mirror: ImageMirroring::None,
             },
 
-            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => ImageFormat {
-                mode: ImageMode::JPEG,
-                size: (854, 480),
-                rotation: ImageRotation::Rot0,
-                mirror: ImageMirroring::None,
-            },
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R | Kind::UnknownV1 { .. } => {
+                ImageFormat {
+                    mode: ImageMode::JPEG,
+                    size: (854, 480),
+                    rotation: ImageRotation::Rot0,
+                    mirror: ImageMirroring::None,
+                }
+            }
 
             Kind::Akp815 => ImageFormat {
                 mode: ImageMode::JPEG,
@@ -178,12 +415,14 @@ impl Kind {
     /// Image format used by the device kind
     pub const fn key_image_format(&self) -> ImageFormat {
         match self {
-            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => ImageFormat {
-                mode: ImageMode::JPEG,
-                size: (85, 85),
-                rotation: ImageRotation::Rot90,
-                mirror: ImageMirroring::Both,
-            },
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R | Kind::UnknownV1 { .. } => {
+                ImageFormat {
+                    mode: ImageMode::JPEG,
+                    size: (85, 85),
+                    rotation: ImageRotation::Rot90,
+                    mirror: ImageMirroring::Both,
+                }
+            }
 
             Kind::Akp815 => ImageFormat {
                 mode: ImageMode::JPEG,
@@ -192,12 +431,14 @@ impl Kind {
                 mirror: ImageMirroring::None,
             },
 
-            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R => ImageFormat {
-                mode: ImageMode::JPEG,
-                size: (60, 60),
-                rotation: ImageRotation::Rot0,
-                mirror: ImageMirroring::None,
-            },
+            Kind::Akp03 | Kind::Akp03E | Kind::Akp03R | Kind::UnknownV2 { .. } => {
+                ImageFormat {
+                    mode: ImageMode::JPEG,
+                    size: (60, 60),
+                    rotation: ImageRotation::Rot0,
+                    mirror: ImageMirroring::None,
+                }
+            }
 
             Kind::Akp03RRev2 => ImageFormat {
                 mode: ImageMode::JPEG,
@@ -208,17 +449,46 @@ impl Kind {
         }
     }
 
+    /// Length of the reports carrying image data, including the report ID
+    pub const fn image_report_length(&self) -> usize {
+        // Listed by protocol version, so a new kind has to pick its length
+        match self {
+            Kind::Akp153
+            | Kind::Akp153E
+            | Kind::Akp153R
+            | Kind::Akp815
+            | Kind::UnknownV1 { .. } => 513,
+            Kind::Akp03
+            | Kind::Akp03E
+            | Kind::Akp03R
+            | Kind::Akp03RRev2
+            | Kind::UnknownV2 { .. } => 1025,
+        }
+    }
+
+    /// Amount of image data carried by one report. Encoded images are split into
+    /// reports of this size, the last one padded with zeros, so images of a multiple
+    /// of it are sent without padding
+    pub const fn max_image_payload(&self) -> usize {
+        // Report ID is the only header
+        self.image_report_length() - 1
+    }
+
     /// Returns true for devices with 512 byte packet length
     pub const fn is_v1_api(&self) -> bool {
         matches!(
             self,
-            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R | Kind::Akp815
+            Kind::Akp153
+                | Kind::Akp153E
+                | Kind::Akp153R
+                | Kind::Akp815
+                | Kind::UnknownV1 { .. }
         )
     }
 
     /// Returns true for devices with 1024 byte packet length
     pub const fn is_v2_api(&self) -> bool {
-        self.is_akp03()
+        self.is_akp03() || matches!(self, Kind::UnknownV2 { .. })
     }
 
     /// Returns true for devices is Ajazz AKP03
@@ -229,3 +499,133 @@ impl Kind {
         )
     }
 }
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::UnknownV1 { pid } | Kind::UnknownV2 { pid } => {
+                write!(f, "{}:{pid:04x}", self.identifier())
+            }
+            _ => f.write_str(self.identifier()),
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = AjazzError;
+
+    /// Parses identifier returned by [Kind::identifier], ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || AjazzError::UnknownKind(s.to_string());
+        let identifier = s.trim().to_ascii_lowercase();
+
+        if let Some((generation, pid)) = identifier.split_once(':') {
+            let pid = u16::from_str_radix(pid, 16).map_err(|_| unknown())?;
+            return match generation {
+                "unknown-v1" => Ok(Kind::UnknownV1 { pid }),
+                "unknown-v2" => Ok(Kind::UnknownV2 { pid }),
+                _ => Err(unknown()),
+            };
+        }
+
+        Kind::ALL
+            .into_iter()
+            .find(|kind| kind.identifier() == identifier)
+            .ok_or_else(unknown)
+    }
+}
+
+/// What the device reported during the handshake, see [Ajazz::handshake]
+///
+/// [Ajazz::handshake]: crate::Ajazz::handshake
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandshakeInfo {
+    /// Kind of the device
+    pub kind: Kind,
+    /// Firmware version reported by the device
+    pub firmware_version: String,
+    /// Serial number of the device
+    pub serial_number: String,
+}
+
+/// Row of the [supported_devices] table
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SupportedDevice {
+    /// Kind of the device
+    pub kind: Kind,
+    /// Human readable name of the device
+    pub name: &'static str,
+    /// Vendor ID of the device
+    pub vendor_id: u16,
+    /// Product ID of the device
+    pub product_id: u16,
+    /// Key layout of the device as (rows, columns)
+    pub layout: (u8, u8),
+}
+
+/// Returns table of devices supported with enabled cargo features,
+/// e.g. for rendering device pickers
+pub fn supported_devices() -> Vec<SupportedDevice> {
+    Kind::ALL
+        .into_iter()
+        .filter(Kind::is_enabled)
+        .map(|kind| SupportedDevice {
+            kind,
+            name: kind.display_name(),
+            vendor_id: kind.vendor_id(),
+            product_id: kind.product_id(),
+            layout: kind.key_layout(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_report_sizes() {
+        assert_eq!(Kind::Akp153.image_report_length(), 513);
+        assert_eq!(Kind::Akp815.max_image_payload(), 512);
+        assert_eq!(Kind::Akp03R.image_report_length(), 1025);
+        assert_eq!(Kind::UnknownV2 { pid: 0x1234 }.max_image_payload(), 1024);
+    }
+
+    #[test]
+    fn test_kind_string_round_trip() {
+        let unknown = [
+            Kind::UnknownV1 { pid: 0x1234 },
+            Kind::UnknownV2 { pid: 0x00ab },
+        ];
+        for kind in Kind::ALL.into_iter().chain(unknown) {
+            assert_eq!(kind.to_string().parse::<Kind>().unwrap(), kind);
+            assert_eq!(
+                kind.to_string().to_uppercase().parse::<Kind>().unwrap(),
+                kind
+            );
+        }
+
+        // Identifiers are stored in config files, renaming them breaks users
+        let identifiers: Vec<String> = Kind::ALL.iter().map(Kind::to_string).collect();
+        assert_eq!(
+            identifiers,
+            [
+                "akp153",
+                "akp153e",
+                "akp153r",
+                "akp815",
+                "akp03",
+                "akp03e",
+                "akp03r",
+                "akp03r-rev2"
+            ]
+        );
+        assert_eq!(unknown[1].to_string(), "unknown-v2:00ab");
+        assert!(matches!(
+            "akp999".parse::<Kind>(),
+            Err(AjazzError::UnknownKind(_))
+        ));
+        assert!("unknown-v1:xyz".parse::<Kind>().is_err());
+        assert!("unknown-v1".parse::<Kind>().is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+
+use crate::Event;
+
+impl Event {
+    /// Returns the event as a single line of JSON, e.g. `{"ButtonDown":3}`
+    pub fn to_json(&self) -> String {
+        // Events are plain data, serializing them can't fail
+        serde_json::to_string(self).expect("event is serializable")
+    }
+}
+
+/// Writes events as JSON Lines, one event per line, and flushes the writer,
+/// so events reach the other end of a pipe right away, e.g. for filtering with `jq`
+pub fn write_json_lines<W: Write>(writer: &mut W, events: &[Event]) -> io::Result<()> {
+    for event in events {
+        writeln!(writer, "{}", event.to_json())?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_lines() {
+        let mut output = vec![];
+        let events = [Event::ButtonDown(3), Event::EncoderTwist(1, -2)];
+
+        write_json_lines(&mut output, &events).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"ButtonDown\":3}\n{\"EncoderTwist\":[1,-2]}\n"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use image::{imageops, DynamicImage, RgbImage};
+
+use crate::device::Ajazz;
+use crate::images::convert_image_with_format;
+use crate::locks::lock;
+use crate::{AjazzError, ImageRect};
+
+/// LCD strip writes.
+///
+/// The protocol has no command for writing a part of the strip, so the whole strip
+/// is sent with the boot logo command. Areas written with [Ajazz::write_lcd] are drawn
+/// onto the strip content kept by this instance, which starts black
+impl Ajazz {
+    /// Fills the whole LCD strip with the image, which must match [Kind::lcd_strip_size]
+    ///
+    /// [Kind::lcd_strip_size]: crate::Kind::lcd_strip_size
+    pub fn write_lcd_fill(&self, rect: &ImageRect) -> Result<(), AjazzError> {
+        let (w, h) = self.lcd_size()?;
+        if (u32::from(rect.w), u32::from(rect.h)) != (w, h) {
+            return Err(AjazzError::InvalidImageSize(
+                rect.w.into(),
+                rect.h.into(),
+                w as usize,
+                h as usize,
+            ));
+        }
+
+        let image = decode_rect(rect)?;
+        self.write_lcd_frame(image)
+    }
+
+    /// Draws the image onto the LCD strip with top left corner at (x, y).
+    /// The image must fit into the strip
+    pub fn write_lcd(&self, x: u16, y: u16, rect: &ImageRect) -> Result<(), AjazzError> {
+        let (w, h) = self.lcd_size()?;
+        let (right, bottom) = (
+            u32::from(x) + u32::from(rect.w),
+            u32::from(y) + u32::from(rect.h),
+        );
+        if right > w || bottom > h {
+            return Err(AjazzError::InvalidImageSize(
+                rect.w.into(),
+                rect.h.into(),
+                w.saturating_sub(x.into()) as usize,
+                h.saturating_sub(y.into()) as usize,
+            ));
+        }
+
+        let image = decode_rect(rect)?;
+        let mut frame = lock(&self.lcd_frame)
+            .clone()
+            .unwrap_or_else(|| RgbImage::new(w, h));
+        imageops::replace(&mut frame, &image, x.into(), y.into());
+
+        self.write_lcd_frame(frame)
+    }
+
+    fn lcd_size(&self) -> Result<(u32, u32), AjazzError> {
+        let (w, h) = self
+            .kind()
+            .lcd_strip_size()
+            .ok_or(AjazzError::UnsupportedOperation)?;
+
+        Ok((w as u32, h as u32))
+    }
+
+    fn write_lcd_frame(&self, frame: RgbImage) -> Result<(), AjazzError> {
+        let image_data = convert_image_with_format(
+            self.kind().logo_image_format(),
+            DynamicImage::ImageRgb8(frame.clone()),
+        )?;
+        self.set_logo_image_data(&image_data)?;
+        *lock(&self.lcd_frame) = Some(frame);
+
+        Ok(())
+    }
+}
+
+fn decode_rect(rect: &ImageRect) -> Result<RgbImage, AjazzError> {
+    let image = image::load_from_memory_with_format(&rect.data, image::ImageFormat::Jpeg)?;
+    Ok(image.into_rgb8())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+    use crate::protocol::codes;
+    use crate::replay::ReplayTransport;
+    use crate::Kind;
+
+    fn rect(w: u32, h: u32, color: Rgb<u8>) -> ImageRect {
+        ImageRect::from_image(DynamicImage::ImageRgb8(RgbImage::from_pixel(w, h, color)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_write_lcd_keeps_strip_content() {
+        let replay = ReplayTransport::default();
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        replay.push_read(codes::RESPONSE_ACK_OK);
+        let device = Ajazz::from_transport(Kind::Akp153, replay.clone());
+
+        let white = Rgb([255, 255, 255]);
+        device.write_lcd_fill(&rect(854, 480, white)).unwrap();
+        device
+            .write_lcd(100, 100, &rect(50, 50, Rgb([0, 0, 0])))
+            .unwrap();
+
+        let frame = device.lcd_frame.lock().unwrap().clone().unwrap();
+        assert_eq!(frame.get_pixel(10, 10), &white);
+        assert!(frame.get_pixel(125, 125).0.iter().all(|c| *c < 16));
+        assert_eq!(
+            replay
+                .written()
+                .iter()
+                .filter(|p| &p[6..9] == b"LOG")
+                .count(),
+            2
+        );
+
+        assert!(matches!(
+            device.write_lcd(830, 0, &rect(50, 50, white)),
+            Err(AjazzError::InvalidImageSize(50, 50, 24, 480))
+        ));
+        assert!(matches!(
+            device.write_lcd_fill(&rect(50, 50, white)),
+            Err(AjazzError::InvalidImageSize(50, 50, 854, 480))
+        ));
+
+        let device = Ajazz::from_transport(Kind::Akp03, ReplayTransport::default());
+        assert!(matches!(
+            device.write_lcd_fill(&rect(50, 50, white)),
+            Err(AjazzError::UnsupportedOperation)
+        ));
+    }
+}
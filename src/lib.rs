@@ -1,29 +1,141 @@
 //! Ajazz library
 //!
 //! Library for interacting with Ajazz devices through [hidapi](https://crates.io/crates/hidapi).
+//!
+//! # Key order
+//!
+//! Keys are numbered the same way on every device: display keys first, left to right
+//! and top to bottom as the device stands on the desk, starting at 0. Keys without
+//! display follow them in the same order, e.g. touch keys of the AKP153 are 15 - 17.
+//! Indices taken and reported by the library, in [Event], [AjazzInput] and every key
+//! argument, are in this order no matter how the hardware numbers its keys, see
+//! [Kind::physical_to_logical].
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![warn(missing_docs)]
 
-use std::str::Utf8Error;
-
-use hidapi::HidError;
-use image::ImageError;
-use thiserror::Error;
+#[cfg(not(any(feature = "akp153", feature = "akp815", feature = "akp03")))]
+compile_error!("At least one device family feature must be enabled: akp153, akp815 or akp03");
 
 mod info;
+mod formats;
+#[cfg(feature = "image")]
 mod images;
 mod device;
-mod protocol;
+mod device_lock;
+mod commands;
+mod error;
+mod reader;
 mod hid;
-
-pub use info::Kind;
-pub use device::{Ajazz, DeviceStateReader};
+mod options;
+mod locks;
+mod self_test;
+mod transport;
+mod transaction;
+mod frame_pump;
+#[cfg(feature = "image")]
+mod burn_in;
+mod state;
+#[cfg(feature = "image")]
+mod assets;
+#[cfg(feature = "image")]
+mod press_feedback;
+mod bindings;
+mod timers;
+#[cfg(feature = "image")]
+mod lcd;
+#[cfg(feature = "image")]
+mod video;
+#[cfg(feature = "image")]
+mod overlays;
+#[cfg(feature = "image")]
+mod deck_preview;
+#[cfg(feature = "image")]
+mod virtual_deck;
+mod recording;
+mod idle;
+mod schedule;
+mod encoder_tracker;
+mod metrics;
+#[cfg(feature = "json")]
+mod json_lines;
+#[cfg(feature = "midi")]
+mod midi;
+
+pub use info::{supported_devices, Family, HandshakeInfo, Kind, SupportedDevice};
+pub use protocol::{flush_packets, image_data_reports, key_image_packets, parse_input};
+pub use device::{Ajazz, FlushProgress};
+pub use commands::BrightnessZones;
+pub use error::AjazzError;
+pub use reader::{AjazzInput, DeviceStateReader, Event};
+pub use options::{ConnectOptions, DropPolicy};
+pub use formats::{
+    ColorProfile, Dithering, ImageFormat, ImageMode, ImageMirroring, ImageOptions,
+    ImageRotation, LogoScaling,
+};
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
 pub use images::{
-    convert_image, convert_image_with_format, ImageFormat, ImageMode, ImageMirroring,
-    ImageRect, ImageRotation,
+    convert_image, convert_image_with_format, convert_image_with_options,
+    preview_image_with_options, preview_key_image, ImageRect,
 };
-pub use hid::{new_hidapi, refresh_device_list, list_devices};
+pub use hid::{new_hidapi, refresh_device_list, list_devices, list_devices_with_unknown};
+/// hidapi version used by the crate, so applications don't have to depend on it
+pub use hidapi;
+pub use self_test::{SelfTestReport, SelfTestStep, StepStatus};
+pub use transport::Transport;
+pub use transaction::Transaction;
+pub use frame_pump::FramePump;
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub use burn_in::BurnInProtection;
+pub use schedule::{BrightnessChange, BrightnessSchedule};
+pub use state::DeckState;
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub use assets::{Asset, AssetLibrary};
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub use press_feedback::PressFeedback;
+pub use bindings::Bindings;
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub use overlays::Overlay;
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub use virtual_deck::VirtualAjazz;
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub use video::{KeyVideoSink, LcdVideoSink};
+pub use recording::{InputLog, RecordedEvent};
+pub use encoder_tracker::{EncoderRange, EncoderTracker};
+pub use metrics::Metrics;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use json_lines::write_json_lines;
+#[cfg(feature = "midi")]
+#[cfg_attr(docsrs, doc(cfg(feature = "midi")))]
+pub use midi::{EncoderMode, MidiController, MidiMapper, MidiMapping};
+
+/// Protocol codes, pure packet builders and input parsers, for tools working with raw reports
+pub mod protocol;
+
+pub mod replay;
+
+pub mod diagnostics;
+
+/// Device interface for single-threaded use, without locks
+pub mod sync;
+
+/// Widgets rendered on the LCD strip
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub mod widgets;
+
+/// Rendering of text and icon fonts into key images
+#[cfg(feature = "text")]
+#[cfg_attr(docsrs, doc(cfg(feature = "text")))]
+pub mod text;
 
 /// Async Ajazz
 #[cfg(feature = "async")]
@@ -31,105 +143,16 @@ pub use hid::{new_hidapi, refresh_device_list, list_devices};
 pub mod asynchronous;
 #[cfg(feature = "async")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
-pub use asynchronous::AsyncAjazz;
+pub use asynchronous::{AsyncAjazz, DeviceWatcher, HotplugEvent, Priority};
+/// tokio-util's token stopping async reads, so applications don't have to depend on it
 #[cfg(feature = "async")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use tokio_util::sync::CancellationToken;
+
+/// Building blocks of a daemon sharing devices with unprivileged applications
+#[cfg(feature = "daemon")]
+#[cfg_attr(docsrs, doc(cfg(feature = "daemon")))]
+pub mod daemon;
+#[cfg(all(feature = "async", feature = "image"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "async", feature = "image"))))]
 pub use images::{convert_image_async, convert_image_with_format_async};
-
-/// Errors that can occur while working with Ajazz devices
-#[derive(Debug, Error)]
-pub enum AjazzError {
-    /// HidApi error
-    #[error("HidApi error: {0}")]
-    HidError(#[from] HidError),
-
-    /// Failed to convert bytes into string
-    #[error("Failed to convert bytes into string: {0}")]
-    Utf8Error(#[from] Utf8Error),
-
-    /// Failed to encode image
-    #[error("Failed to encode image: {0}")]
-    ImageError(#[from] ImageError),
-
-    /// Tokio join error
-    #[cfg(feature = "async")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
-    #[error("Tokio join error: {0}")]
-    JoinError(#[from] tokio::task::JoinError),
-
-    /// Reader mutex was poisoned
-    #[error("Reader mutex was poisoned")]
-    PoisonError,
-
-    /// Key index is invalid
-    #[error("Key index is invalid: {0}")]
-    InvalidKeyIndex(u8),
-
-    /// Unrecognized Product ID
-    #[error("Unrecognized Product ID: {0}")]
-    UnrecognizedPID(u16),
-
-    /// The device doesn't support doing that
-    #[error("The device doesn't support doing that")]
-    UnsupportedOperation,
-
-    /// Device sent unexpected data
-    #[error("Device sent unexpected data")]
-    BadData,
-
-    /// Invalid image size
-    #[error("Invalid image size: {0}x{1}, expected {2}x{3}")]
-    InvalidImageSize(usize, usize, usize, usize),
-
-    /// Device didn't respond with ACK
-    #[error("Device didn't respond with ACK")]
-    NoAck,
-}
-
-/// Type of input that the device produced
-#[derive(Clone, Debug)]
-pub enum AjazzInput {
-    /// No data was passed from the device
-    NoData,
-
-    /// Button was pressed
-    ButtonStateChange(Vec<bool>),
-
-    /// Encoder/Knob was pressed
-    EncoderStateChange(Vec<bool>),
-
-    /// Encoder/Knob was twisted/turned
-    EncoderTwist(Vec<i8>),
-}
-
-impl AjazzInput {
-    /// Checks if there's data received or not
-    pub fn is_empty(&self) -> bool {
-        matches!(self, AjazzInput::NoData)
-    }
-}
-
-/// Tells what changed in button states
-#[derive(Copy, Clone, Debug, Hash)]
-pub enum Event {
-    /// Button got pressed down
-    ButtonDown(u8),
-
-    /// Button got released
-    ButtonUp(u8),
-
-    /// Encoder got pressed down
-    EncoderDown(u8),
-
-    /// Encoder was released from being pressed down
-    EncoderUp(u8),
-
-    /// Encoder was twisted
-    EncoderTwist(u8, i8),
-}
-
-#[derive(Default)]
-struct DeviceState {
-    pub buttons: Vec<bool>,
-    pub encoders: Vec<bool>,
-}
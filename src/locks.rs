@@ -0,0 +1,22 @@
+//! Lock helpers that recover from poisoning.
+//!
+//! A panic in one thread while holding a lock shouldn't make the device handle unusable
+//! for the rest of the application. Data behind the locks is always left in a valid state,
+//! so the guard is taken from the poisoned lock as is.
+
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Locks the mutex, ignoring poisoning
+pub(crate) fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Locks the lock for reading, ignoring poisoning
+pub(crate) fn read<T: ?Sized>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Locks the lock for writing, ignoring poisoning
+pub(crate) fn write<T: ?Sized>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
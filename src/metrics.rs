@@ -0,0 +1,31 @@
+use std::fmt;
+use std::time::Duration;
+
+use hidapi::HidError;
+
+/// Receiver of device connection counters, e.g. for exporting them to Prometheus
+/// from a long-running daemon.
+///
+/// Every method does nothing by default, so implementations override only the counters
+/// they need. Methods are called on the thread talking to the device, so they should
+/// return quickly
+pub trait Metrics: Send + Sync {
+    /// Report of `_bytes` length was written to the device
+    fn packet_written(&self, _bytes: usize) {}
+
+    /// Flush sending images to the device took `_duration`
+    fn flushed(&self, _duration: Duration) {}
+
+    /// Reading from the device failed
+    fn read_error(&self, _error: &HidError) {}
+
+    /// Device was opened. Connections made with the same options are counted together,
+    /// so every call after the first one is a reconnect
+    fn connected(&self) {}
+}
+
+impl fmt::Debug for dyn Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
@@ -0,0 +1,212 @@
+use midir::{MidiOutput, MidiOutputConnection};
+
+use crate::{AjazzError, Event};
+
+/// Name of the MIDI client, shown to other applications
+const CLIENT_NAME: &str = "Ajazz";
+
+/// How twists of the encoders are sent as control changes
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EncoderMode {
+    /// Value is tracked in 0 - 127 starting at 64, twists move it and the value is sent
+    #[default]
+    Absolute,
+    /// Twist is sent as offset from 64, e.g. 63 for one tick left, the "binary offset"
+    /// relative mode most DAWs understand
+    Relative,
+}
+
+/// Which notes and control changes the keys and encoders send
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MidiMapping {
+    /// Channel of every message, 0 - 15
+    pub channel: u8,
+    /// Note of every key by key index, keys without a note send nothing
+    pub key_notes: Vec<u8>,
+    /// Note sent when the encoder is pressed, by encoder index
+    pub encoder_notes: Vec<u8>,
+    /// Control change number of the encoder twists, by encoder index
+    pub encoder_ccs: Vec<u8>,
+    /// Velocity of Note On messages
+    pub velocity: u8,
+    /// How encoder twists are sent
+    pub encoder_mode: EncoderMode,
+}
+
+impl Default for MidiMapping {
+    /// Keys start at C1 like drum pads, encoders send CC 20 and up
+    fn default() -> Self {
+        Self {
+            channel: 0,
+            key_notes: (36..52).collect(),
+            encoder_notes: (52..56).collect(),
+            encoder_ccs: (20..24).collect(),
+            velocity: 127,
+            encoder_mode: EncoderMode::Absolute,
+        }
+    }
+}
+
+/// Turns device events into MIDI messages according to the mapping
+#[derive(Clone, Debug)]
+pub struct MidiMapper {
+    mapping: MidiMapping,
+    /// Current values of the encoders in [EncoderMode::Absolute]
+    values: Vec<u8>,
+}
+
+impl MidiMapper {
+    /// Creates mapper, encoders start in the middle of the range
+    pub fn new(mapping: MidiMapping) -> MidiMapper {
+        let values = vec![64; mapping.encoder_ccs.len()];
+        MidiMapper { mapping, values }
+    }
+
+    /// Returns the mapping
+    pub fn mapping(&self) -> &MidiMapping {
+        &self.mapping
+    }
+
+    /// Returns MIDI message of the event, `None` for unmapped keys and other events
+    pub fn message(&mut self, event: &Event) -> Option<[u8; 3]> {
+        let channel = self.mapping.channel & 0x0F;
+        let note_on = |note: &u8| [0x90 | channel, *note & 0x7F, self.mapping.velocity & 0x7F];
+        let note_off = |note: &u8| [0x80 | channel, *note & 0x7F, 0];
+
+        match *event {
+            Event::ButtonDown(key) => self.mapping.key_notes.get(key as usize).map(note_on),
+            Event::ButtonUp(key) => self.mapping.key_notes.get(key as usize).map(note_off),
+            Event::EncoderDown(encoder) => self
+                .mapping
+                .encoder_notes
+                .get(encoder as usize)
+                .map(note_on),
+            Event::EncoderUp(encoder) => self
+                .mapping
+                .encoder_notes
+                .get(encoder as usize)
+                .map(note_off),
+            Event::EncoderTwist(encoder, ticks) => {
+                let cc = *self.mapping.encoder_ccs.get(encoder as usize)? & 0x7F;
+                let value = match self.mapping.encoder_mode {
+                    EncoderMode::Absolute => {
+                        let value = &mut self.values[encoder as usize];
+                        *value = (i16::from(*value) + i16::from(ticks)).clamp(0, 127) as u8;
+                        *value
+                    }
+                    EncoderMode::Relative => (64 + i16::from(ticks)).clamp(0, 127) as u8,
+                };
+                Some([0xB0 | channel, cc, value])
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Sends events of the device to a MIDI output, so DAWs can use keys and encoders
+/// as a MIDI controller
+pub struct MidiController {
+    mapper: MidiMapper,
+    connection: MidiOutputConnection,
+}
+
+impl MidiController {
+    /// Connects to the first output port whose name contains `port`
+    pub fn connect(port: &str, mapping: MidiMapping) -> Result<MidiController, AjazzError> {
+        let output = MidiOutput::new(CLIENT_NAME).map_err(midi_error)?;
+        let found = output.ports().into_iter().find(|candidate| {
+            output
+                .port_name(candidate)
+                .is_ok_and(|name| name.contains(port))
+        });
+        let found = found.ok_or_else(|| AjazzError::MidiError(format!("No port {port:?}")))?;
+        let connection = output.connect(&found, CLIENT_NAME).map_err(midi_error)?;
+
+        Ok(MidiController {
+            mapper: MidiMapper::new(mapping),
+            connection,
+        })
+    }
+
+    /// Creates virtual output port with the name, which DAWs see as a MIDI device
+    #[cfg(unix)]
+    pub fn create_virtual(
+        name: &str,
+        mapping: MidiMapping,
+    ) -> Result<MidiController, AjazzError> {
+        use midir::os::unix::VirtualOutput;
+
+        let output = MidiOutput::new(CLIENT_NAME).map_err(midi_error)?;
+        let connection = output.create_virtual(name).map_err(midi_error)?;
+
+        Ok(MidiController {
+            mapper: MidiMapper::new(mapping),
+            connection,
+        })
+    }
+
+    /// Sends messages of the events, e.g. the ones returned by a reader
+    pub fn send(&mut self, events: &[Event]) -> Result<(), AjazzError> {
+        for event in events {
+            if let Some(message) = self.mapper.message(event) {
+                self.connection.send(&message).map_err(midi_error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts any of MIDI errors, they have no common type
+fn midi_error(error: impl std::fmt::Display) -> AjazzError {
+    AjazzError::MidiError(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_send_notes() {
+        let mut mapper = MidiMapper::new(MidiMapping {
+            channel: 2,
+            ..Default::default()
+        });
+
+        assert_eq!(mapper.message(&Event::ButtonDown(1)), Some([0x92, 37, 127]));
+        assert_eq!(mapper.message(&Event::ButtonUp(1)), Some([0x82, 37, 0]));
+        assert_eq!(
+            mapper.message(&Event::EncoderDown(0)),
+            Some([0x92, 52, 127])
+        );
+        assert_eq!(mapper.message(&Event::ButtonDown(200)), None);
+        assert_eq!(mapper.message(&Event::Tick(0)), None);
+    }
+
+    #[test]
+    fn test_encoder_modes() {
+        let mut absolute = MidiMapper::new(MidiMapping::default());
+        assert_eq!(
+            absolute.message(&Event::EncoderTwist(1, 3)),
+            Some([0xB0, 21, 67])
+        );
+        assert_eq!(
+            absolute.message(&Event::EncoderTwist(1, -100)),
+            Some([0xB0, 21, 0])
+        );
+
+        let mut relative = MidiMapper::new(MidiMapping {
+            encoder_mode: EncoderMode::Relative,
+            ..Default::default()
+        });
+        assert_eq!(
+            relative.message(&Event::EncoderTwist(0, -1)),
+            Some([0xB0, 20, 63])
+        );
+        assert_eq!(
+            relative.message(&Event::EncoderTwist(0, -1)),
+            Some([0xB0, 20, 63])
+        );
+        assert_eq!(relative.message(&Event::EncoderTwist(9, 1)), None);
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+
+use crate::Metrics;
+
+/// What to do with the device when its [Ajazz](crate::Ajazz) is dropped, so applications
+/// exiting without [shutdown](crate::Ajazz::shutdown) don't leave stale images on the keys
+#[derive(Clone, Debug, Default)]
+pub enum DropPolicy {
+    /// Leave the device as is
+    #[default]
+    Nothing,
+    /// Clear every key image
+    ClearScreen,
+    /// Show the image on every display key
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    StandbyImage(DynamicImage),
+    /// Shutdown the device
+    Shutdown,
+}
+
+/// Options used when connecting to the device
+#[derive(Clone, Debug)]
+pub struct ConnectOptions {
+    /// How many times to try opening the device before giving up
+    pub attempts: u8,
+    /// Delay between connection attempts
+    pub retry_delay: Duration,
+    /// Blocking mode set once after connecting. Only affects reads without timeout:
+    /// in blocking mode they wait for data, otherwise they return immediately
+    pub blocking: bool,
+    /// What to do with the device when it's dropped
+    pub on_drop: DropPolicy,
+    /// Read and check the device response after brightness, clear and flush commands,
+    /// see [Ajazz::set_ack_verification](crate::Ajazz::set_ack_verification)
+    pub verify_acks: bool,
+    /// How long to wait for the device response to a command,
+    /// see [Ajazz::set_command_timeout](crate::Ajazz::set_command_timeout)
+    pub command_timeout: Duration,
+    /// Receiver of connection counters, see [Ajazz::set_metrics](crate::Ajazz::set_metrics)
+    pub metrics: Option<Arc<dyn Metrics>>,
+    /// Take the cross-process lock of the device, so another application opening it
+    /// gets [AjazzError::DeviceBusy](crate::AjazzError::DeviceBusy) instead of mixing
+    /// its image stream with ours
+    pub lock_device: bool,
+    /// Limit of bytes waiting in the image cache,
+    /// see [Ajazz::set_image_cache_limit](crate::Ajazz::set_image_cache_limit)
+    pub image_cache_limit: Option<usize>,
+    /// Delay between image data reports, for hosts and hubs dropping reports written
+    /// back to back, see [Ajazz::set_report_pacing](crate::Ajazz::set_report_pacing)
+    pub report_pacing: Option<Duration>,
+    /// How many times to write a report the device accepted only partially,
+    /// see [Ajazz::set_write_attempts](crate::Ajazz::set_write_attempts)
+    pub write_attempts: u8,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            retry_delay: Duration::from_millis(100),
+            blocking: false,
+            on_drop: DropPolicy::Nothing,
+            verify_acks: false,
+            command_timeout: Duration::from_millis(1000),
+            metrics: None,
+            lock_device: true,
+            image_cache_limit: None,
+            report_pacing: None,
+            write_attempts: 1,
+        }
+    }
+}
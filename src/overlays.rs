@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::widgets::{draw_digit, Rect};
+
+/// Indicator drawn over a key image, e.g. while an action runs or after it failed.
+///
+/// Overlays are drawn over a base image, usually the one the key shows, see
+/// [Ajazz::key_image]. Animated overlays are sent through the [FramePump]
+///
+/// ```no_run
+/// # fn example(device: &ajazz_sdk::Ajazz, pump: &ajazz_sdk::FramePump) -> Result<(), ajazz_sdk::AjazzError> {
+/// use ajazz_sdk::Overlay;
+///
+/// let base = device.key_image(0)?.unwrap();
+/// let started = std::time::Instant::now();
+/// while started.elapsed().as_secs() < 3 {
+///     pump.push_frame(0, Overlay::spinner(started.elapsed()).render(&base));
+///     pump.pump(device)?;
+/// }
+/// pump.push_frame(0, base);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [Ajazz::key_image]: crate::Ajazz::key_image
+/// [FramePump]: crate::FramePump
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Overlay {
+    /// Frame of the busy spinner, from 0 to [Overlay::SPINNER_FRAMES]
+    Spinner(u32),
+    /// Warning triangle, for failed actions
+    Warning,
+    /// Number in the top right corner, numbers above 99 are shown as 99
+    Badge(u32),
+}
+
+/// Color of the warning triangle
+const WARNING_COLOR: Rgb<u8> = Rgb([255, 200, 0]);
+
+/// Color of the badge circle
+const BADGE_COLOR: Rgb<u8> = Rgb([220, 30, 30]);
+
+impl Overlay {
+    /// Amount of frames in the spinner animation
+    pub const SPINNER_FRAMES: u32 = 8;
+
+    /// How long every spinner frame is shown by [Overlay::spinner]
+    pub const SPINNER_FRAME_DURATION: Duration = Duration::from_millis(100);
+
+    /// Returns spinner frame for the time since the animation started
+    pub fn spinner(elapsed: Duration) -> Overlay {
+        let frame = elapsed.as_millis() / Self::SPINNER_FRAME_DURATION.as_millis();
+        Overlay::Spinner((frame % u128::from(Self::SPINNER_FRAMES)) as u32)
+    }
+
+    /// Returns the base image with the overlay drawn over it
+    pub fn render(&self, base: &DynamicImage) -> DynamicImage {
+        let mut image = base.to_rgb8();
+        let (w, h) = image.dimensions();
+        let size = w.min(h) as f32;
+        let center = (w as f32 / 2.0, h as f32 / 2.0);
+
+        match *self {
+            Overlay::Spinner(frame) => {
+                dim(&mut image);
+                let frames = Self::SPINNER_FRAMES;
+                for dot in 0..frames {
+                    let angle = std::f32::consts::TAU * dot as f32 / frames as f32;
+                    let position = (
+                        center.0 + angle.sin() * size * 0.3,
+                        center.1 - angle.cos() * size * 0.3,
+                    );
+                    // Dots fade out behind the leading one
+                    let behind = (frame % frames + frames - dot) % frames;
+                    let level = (255 - behind * 255 / frames) as u8;
+                    fill_circle(&mut image, position, size * 0.07, Rgb([level; 3]));
+                }
+            }
+            Overlay::Warning => {
+                dim(&mut image);
+                let half = size * 0.35;
+                let (top, bottom) = (center.1 - half, center.1 + half * 0.8);
+                for y in 0..h {
+                    let y_center = y as f32 + 0.5;
+                    if y_center < top || y_center > bottom {
+                        continue;
+                    }
+
+                    let half_width = (y_center - top) / (bottom - top) * half;
+                    for x in 0..w {
+                        if (x as f32 + 0.5 - center.0).abs() <= half_width {
+                            image.put_pixel(x, y, WARNING_COLOR);
+                        }
+                    }
+                }
+
+                let black = Rgb([0, 0, 0]);
+                let stroke = (size * 0.08).max(1.0);
+                let x = center.0 - stroke / 2.0;
+                fill(
+                    &mut image,
+                    (x, top + half * 0.5),
+                    (stroke, half * 0.65),
+                    black,
+                );
+                fill(
+                    &mut image,
+                    (x, bottom - stroke * 1.6),
+                    (stroke, stroke),
+                    black,
+                );
+            }
+            Overlay::Badge(number) => {
+                let radius = size * 0.2;
+                let position = (w as f32 - radius - 1.0, radius + 1.0);
+                fill_circle(&mut image, position, radius, BADGE_COLOR);
+
+                let number = number.min(99);
+                let digits: Vec<u8> = if number >= 10 {
+                    vec![(number / 10) as u8, (number % 10) as u8]
+                } else {
+                    vec![number as u8]
+                };
+                let digit_h = (radius * 1.1) as u32;
+                let digit_w = (digit_h / 2).max(1);
+                let gap = (digit_w / 3).max(1);
+                let total = digits.len() as u32 * (digit_w + gap) - gap;
+                let mut x = (position.0 - total as f32 / 2.0).max(0.0) as u32;
+                let y = (position.1 - digit_h as f32 / 2.0).max(0.0) as u32;
+                for digit in digits {
+                    let white = Rgb([255, 255, 255]);
+                    draw_digit(&mut image, Rect::new(x, y, digit_w, digit_h), digit, white);
+                    x += digit_w + gap;
+                }
+            }
+        }
+
+        DynamicImage::ImageRgb8(image)
+    }
+}
+
+/// Halves brightness of the image, so the overlay stands out
+fn dim(image: &mut RgbImage) {
+    for pixel in image.pixels_mut() {
+        for channel in pixel.0.iter_mut() {
+            *channel /= 2;
+        }
+    }
+}
+
+fn fill_circle(image: &mut RgbImage, center: (f32, f32), radius: f32, color: Rgb<u8>) {
+    let (w, h) = image.dimensions();
+    for y in 0..h {
+        for x in 0..w {
+            let (dx, dy) = (x as f32 + 0.5 - center.0, y as f32 + 0.5 - center.1);
+            if dx * dx + dy * dy <= radius * radius {
+                image.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+fn fill(image: &mut RgbImage, origin: (f32, f32), size: (f32, f32), color: Rgb<u8>) {
+    let (w, h) = image.dimensions();
+    let (left, top) = (origin.0.max(0.0) as u32, origin.1.max(0.0) as u32);
+    let (right, bottom) = ((origin.0 + size.0) as u32, (origin.1 + size.1) as u32);
+    for y in top..bottom.min(h) {
+        for x in left..right.min(w) {
+            image.put_pixel(x, y, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(60, 60, Rgb([100, 100, 100])))
+    }
+
+    #[test]
+    fn test_spinner_frames() {
+        assert_eq!(Overlay::spinner(Duration::ZERO), Overlay::Spinner(0));
+        assert_eq!(
+            Overlay::spinner(Duration::from_millis(350)),
+            Overlay::Spinner(3)
+        );
+        assert_eq!(
+            Overlay::spinner(Duration::from_millis(850)),
+            Overlay::Spinner(0)
+        );
+
+        let first = Overlay::Spinner(0).render(&base()).into_rgb8();
+        let second = Overlay::Spinner(1).render(&base()).into_rgb8();
+        assert_ne!(first, second);
+        // Leading dot at the top, background dimmed
+        assert_eq!(first.get_pixel(30, 12), &Rgb([255, 255, 255]));
+        assert_eq!(first.get_pixel(1, 1), &Rgb([50, 50, 50]));
+    }
+
+    #[test]
+    fn test_warning_and_badge() {
+        let warning = Overlay::Warning.render(&base()).into_rgb8();
+        assert_eq!(warning.get_pixel(20, 45), &WARNING_COLOR);
+        assert_eq!(warning.get_pixel(1, 1), &Rgb([50, 50, 50]));
+
+        let badge = Overlay::Badge(7).render(&base()).into_rgb8();
+        assert_eq!(badge.get_pixel(1, 58), &Rgb([100, 100, 100]));
+        assert_eq!(badge.get_pixel(58, 13), &BADGE_COLOR);
+        assert!(badge.pixels().any(|p| *p == Rgb([255, 255, 255])));
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use image::{DynamicImage, ImageError};
+
+use crate::device::Ajazz;
+use crate::formats::{ImageFormat, ImageMirroring, ImageMode, ImageRotation};
+use crate::images::convert_image_with_format;
+use crate::protocol::AjazzRequestBuilder;
+use crate::{AjazzError, Event};
+
+/// How key images change while the key is held, see [DeviceStateReader::set_press_feedback].
+///
+/// [DeviceStateReader::set_press_feedback]: crate::DeviceStateReader::set_press_feedback
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PressFeedback {
+    /// Darkens the image by the amount, from 0.0 to 1.0
+    Darken(f32),
+    /// Brightens the image towards white by the amount, from 0.0 to 1.0
+    Highlight(f32),
+}
+
+impl Default for PressFeedback {
+    fn default() -> Self {
+        PressFeedback::Darken(0.5)
+    }
+}
+
+impl PressFeedback {
+    /// Returns the image altered according to the feedback
+    pub(crate) fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let mut image = image.to_rgb8();
+        for pixel in image.pixels_mut() {
+            for channel in pixel.0.iter_mut() {
+                let value = f32::from(*channel);
+                *channel = match *self {
+                    PressFeedback::Darken(amount) => value * (1.0 - amount.clamp(0.0, 1.0)),
+                    PressFeedback::Highlight(amount) => {
+                        value + (255.0 - value) * amount.clamp(0.0, 1.0)
+                    }
+                } as u8;
+            }
+        }
+
+        DynamicImage::ImageRgb8(image)
+    }
+}
+
+/// Altered image of the key, reused on next presses while the key shows the same image
+struct AlteredImage {
+    original: Arc<[u8]>,
+    altered: Arc<[u8]>,
+}
+
+/// Press feedback of the reader, with images of held keys
+pub(crate) struct PressFeedbackState {
+    feedback: PressFeedback,
+    images: BTreeMap<u8, AlteredImage>,
+    /// Keys showing altered image
+    held: Vec<u8>,
+}
+
+impl PressFeedbackState {
+    pub(crate) fn new(feedback: PressFeedback) -> PressFeedbackState {
+        PressFeedbackState {
+            feedback,
+            images: BTreeMap::new(),
+            held: vec![],
+        }
+    }
+
+    /// Shows altered images of pressed keys and restores images of released ones
+    pub(crate) fn handle(
+        &mut self,
+        device: &Ajazz,
+        updates: &[Event],
+    ) -> Result<(), AjazzError> {
+        let mut changed = false;
+        for update in updates {
+            changed |= match *update {
+                Event::ButtonDown(key) => self.press(device, key)?,
+                Event::ButtonUp(key) => self.release(device, key)?,
+                _ => false,
+            };
+        }
+
+        if changed {
+            device.write_packet(&device.kind().flush_packet())?;
+        }
+
+        Ok(())
+    }
+
+    fn press(&mut self, device: &Ajazz, key: u8) -> Result<bool, AjazzError> {
+        let Some(original) = device.shown_key_image(key) else {
+            return Ok(false);
+        };
+
+        let altered = match self.images.get(&key) {
+            Some(image) if Arc::ptr_eq(&image.original, &original) => image.altered.clone(),
+            _ => {
+                let altered: Arc<[u8]> =
+                    self.alter(device.key_image_format(), &original)?.into();
+                let image = AlteredImage {
+                    original: original.clone(),
+                    altered: altered.clone(),
+                };
+                self.images.insert(key, image);
+                altered
+            }
+        };
+
+        device.write_key_image(key, &altered)?;
+        // The altered image is temporary, exported state keeps the original
+        device.record_key_image(key, &original);
+        self.held.push(key);
+        Ok(true)
+    }
+
+    fn release(&mut self, device: &Ajazz, key: u8) -> Result<bool, AjazzError> {
+        let Some(position) = self.held.iter().position(|held| *held == key) else {
+            return Ok(false);
+        };
+        self.held.remove(position);
+
+        // Image could be replaced or cleared while the key was held
+        let original = self.images.get(&key).map(|image| &image.original);
+        match (original, device.shown_key_image(key)) {
+            (Some(original), Some(shown)) if Arc::ptr_eq(original, &shown) => {
+                device.write_key_image(key, &shown)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn alter(&self, format: ImageFormat, image_data: &[u8]) -> Result<Vec<u8>, ImageError> {
+        if format.mode != ImageMode::JPEG {
+            return Ok(image_data.to_vec());
+        }
+
+        // Stored images are already rotated and mirrored, only pixels are changed
+        let image = image::load_from_memory_with_format(image_data, image::ImageFormat::Jpeg)?;
+        let format = ImageFormat {
+            size: (image.width() as usize, image.height() as usize),
+            rotation: ImageRotation::Rot0,
+            mirror: ImageMirroring::None,
+            ..format
+        };
+        convert_image_with_format(format, self.feedback.apply(&image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feedback_changes_pixels() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            2,
+            2,
+            image::Rgb([200, 100, 0]),
+        ));
+
+        let darkened = PressFeedback::Darken(0.5).apply(&image).to_rgb8();
+        assert_eq!(darkened.get_pixel(0, 0).0, [100, 50, 0]);
+
+        let highlighted = PressFeedback::Highlight(1.0).apply(&image).to_rgb8();
+        assert_eq!(highlighted.get_pixel(1, 1).0, [255, 255, 255]);
+    }
+}
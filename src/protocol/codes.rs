@@ -1,3 +1,8 @@
+//! Vendor and product IDs, input report offsets and action codes, and command mnemonics
+//! used by the devices.
+//!
+//! Every request starts with [REQUEST_HEADER] (`CRT`) followed by the command mnemonic
+
 /// Feature report ID for firmware version
 pub const FEATURE_REPORT_ID_VERSION: u8 = 0x01;
 
@@ -23,7 +28,7 @@ pub const PID_AJAZZ_AKP03R: u16 = 0x1003;
 /// Product ID of Ajazz AKP03R rev 2
 pub const PID_AJAZZ_AKP03R_REV2: u16 = 0x3003;
 
-/// Offset of the button index in the input data
+/// Offset of the action code in the input data
 pub const OFFSET_ACTION_CODE: usize = 9;
 /// Offset of the data length in the input data
 pub const OFFSET_DATA_LENGTH: usize = 0;
@@ -60,33 +65,35 @@ pub const ACTION_CODE_ENCODER_1_PRESS: u8 = 0x35;
 /// Action code for encoder 2 press
 pub const ACTION_CODE_ENCODER_2_PRESS: u8 = 0x34;
 
-/// Header of the request packet
+/// Header of the request packet, `CRT`
 pub const REQUEST_HEADER: &[u8] = &[0x00, 0x43, 0x52, 0x54, 0x00, 0x00];
 
-/// Request for flush command
+/// Key index clearing every key with [REQUEST_CMD_CLEAR_BUTTON_IMAGE]
 pub const CMD_CLEAR_ALL: u8 = 0xFF;
 
-/// Request for initialize command
+/// Request for initialize command, `DIS`
 pub const REQUEST_CMD_DIS: &[u8] = &[0x44, 0x49, 0x53, 0x00, 0x00];
-/// Request for brightness command
+/// Request for brightness command, `LIG`
 pub const REQUEST_CMD_LIG: &[u8] = &[0x4c, 0x49, 0x47, 0x00, 0x00];
-/// Request for keep alive command
+/// Request for keep alive command, `CONNECT`
 pub const REQUEST_CMD_KEEP_ALIVE: &[u8] = &[0x43, 0x4F, 0x4E, 0x4E, 0x45, 0x43, 0x54];
-/// Request for shutdown command
+/// Request for shutdown command, `CLE` with `DC` argument
 pub const REQUEST_CMD_SHUTDOWN: &[u8] = &[0x43, 0x4C, 0x45, 0x00, 0x00, 0x44, 0x43];
-/// Request for sleep command
+/// Request for sleep command, `HAN`
 pub const REQUEST_CMD_SLEEP: &[u8] = &[0x48, 0x41, 0x4E];
-/// Request for clear button image command
+/// Request for clear button image command, `CLE`
 pub const REQUEST_CMD_CLEAR_BUTTON_IMAGE: &[u8] = &[0x43, 0x4c, 0x45, 0x00, 0x00, 0x00];
-/// Request for flush command
+/// Request for flush command, `STP`
 pub const REQUEST_CMD_FLUSH: &[u8] = &[0x53, 0x54, 0x50];
-/// Request for image packet.
+/// Request for image packet, `BAT`.
 /// This packet should be sent before sending image data.
 pub const REQUEST_CMD_IMAGE_ANNOUNCE: &[u8] = &[0x42, 0x41, 0x54, 0x00, 0x00];
-/// Request for logo image command
+/// Request for logo image command of "v1" devices, `LOG` with the image size
 pub const REQUEST_CMD_LOGO_IMAGE_V1: &[u8] = &[0x4c, 0x4f, 0x47, 0x00, 0x12, 0xc3, 0xc0, 0x01];
-/// Request for logo image command
+/// Request for logo image command of "v2" devices, `LOG`
 pub const REQUEST_CMD_LOGO_IMAGE_V2: &[u8] = &[0x4c, 0x4f, 0x47, 0x00, 0x00];
 
-/// Response for ACK packet
+/// Start of every response to a command, `ACK`
+pub const RESPONSE_ACK: &[u8] = &[0x41, 0x43, 0x4b];
+/// Response for ACK packet, `ACK` followed by `OK`
 pub const RESPONSE_ACK_OK: &[u8] = &[0x41, 0x43, 0x4b, 0x00, 0x00, 0x4f, 0x4b];
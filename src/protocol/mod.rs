@@ -1,6 +1,10 @@
-pub(crate) mod codes;
-pub(crate) mod parser;
+pub mod codes;
+pub(crate) mod packets;
+pub mod parser;
 pub(crate) mod request;
 
-pub(crate) use parser::{extract_string, AjazzProtocolParser};
+pub(crate) use parser::{extract_string, is_command_response, AjazzProtocolParser};
+pub use parser::parse_input;
+pub use packets::{flush_packets, image_data_reports, key_image_packets};
+pub(crate) use packets::fill_image_report;
 pub(crate) use request::AjazzRequestBuilder;
@@ -0,0 +1,90 @@
+use crate::formats::WriteImageParameters;
+use crate::info::Kind;
+use crate::AjazzError;
+
+use super::AjazzRequestBuilder;
+
+/// Fills report buffer with the chunk of image data, zeroing the rest of the report
+pub(crate) fn fill_image_report(buf: &mut [u8], chunk: &[u8]) {
+    let (header, payload) = buf.split_at_mut(1);
+    header[0] = 0x00;
+    payload[..chunk.len()].copy_from_slice(chunk);
+    payload[chunk.len()..].fill(0x00);
+}
+
+/// Splits image data into reports sent after the image announce packet
+pub fn image_data_reports(
+    kind: Kind,
+    image_data: &[u8],
+) -> impl Iterator<Item = Vec<u8>> + '_ {
+    let parameters = WriteImageParameters::for_kind(kind);
+
+    image_data
+        .chunks(parameters.image_report_payload_length)
+        .map(move |chunk| {
+            let mut buf = vec![0x00; parameters.image_report_length];
+            fill_image_report(&mut buf, chunk);
+            buf
+        })
+}
+
+/// Builds every packet needed to send the key image: announce followed by data reports
+pub fn key_image_packets(
+    kind: Kind,
+    key: u8,
+    image_data: &[u8],
+) -> Result<Vec<Vec<u8>>, AjazzError> {
+    kind.check_display_key(key, kind.display_key_count())?;
+
+    let mut packets = vec![kind.key_image_announce_packet(key, image_data)];
+    packets.extend(image_data_reports(kind, image_data));
+    Ok(packets)
+}
+
+/// Builds every packet sent by [Ajazz::flush](crate::Ajazz::flush) for the key images
+pub fn flush_packets(kind: Kind, images: &[(u8, &[u8])]) -> Result<Vec<Vec<u8>>, AjazzError> {
+    let mut packets = vec![];
+    for (key, image_data) in images {
+        packets.extend(key_image_packets(kind, *key, image_data)?);
+    }
+
+    if !packets.is_empty() {
+        packets.push(kind.flush_packet());
+    }
+
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_data_reports() {
+        let kind = Kind::Akp03;
+        let reports: Vec<Vec<u8>> = image_data_reports(kind, &[0xAA; 1500]).collect();
+
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|r| r.len() == 1025));
+        assert_eq!(reports[1][1..477], [0xAA; 476]);
+        assert!(reports[1][477..].iter().all(|b| *b == 0x00));
+    }
+
+    #[test]
+    fn test_flush_packets() {
+        let kind = Kind::Akp153;
+        let packets = flush_packets(kind, &[(0, &[0xAA; 10]), (1, &[0xBB; 600])]).unwrap();
+
+        let commands: Vec<&[u8]> = packets.iter().map(|p| &p[6..9]).collect();
+        assert_eq!(packets.len(), 6);
+        assert_eq!(commands[0], b"BAT");
+        assert_eq!(commands[2], b"BAT");
+        assert_eq!(commands[5], b"STP");
+
+        assert!(flush_packets(kind, &[]).unwrap().is_empty());
+        assert!(matches!(
+            flush_packets(kind, &[(18, &[0xAA])]),
+            Err(AjazzError::InvalidKeyIndex(18))
+        ));
+    }
+}
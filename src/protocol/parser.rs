@@ -1,3 +1,10 @@
+//! Parsers of input reports, for reports read through transports other than hidapi,
+//! e.g. a Bluetooth bridge or a network proxy.
+//!
+//! Parsers take the whole report and the offset of the field they read, so reports
+//! wrapped in another framing can be parsed without copying. Reports read from the
+//! device itself keep the action code at [OFFSET_ACTION_CODE](codes::OFFSET_ACTION_CODE)
+
 use std::str::{from_utf8, Utf8Error};
 
 use crate::info::Kind;
@@ -5,7 +12,6 @@ use crate::protocol::codes;
 use crate::{AjazzError, AjazzInput};
 
 pub(crate) trait AjazzProtocolParser {
-    fn parse_input(&self, data: &[u8]) -> Result<AjazzInput, AjazzError>;
     fn index_from_native_v1(&self, i: u8) -> Option<u8>;
     fn index_to_native_v1(&self, key: u8) -> Option<u8>;
     fn is_ack_ok(&self, data: &[u8]) -> bool;
@@ -16,45 +22,76 @@ pub(crate) fn extract_string(bytes: &[u8]) -> Result<String, Utf8Error> {
     Ok(from_utf8(bytes)?.replace('\0', "").to_string())
 }
 
-impl AjazzProtocolParser for Kind {
-    fn parse_input(&self, data: &[u8]) -> Result<AjazzInput, AjazzError> {
-        if data[codes::OFFSET_DATA_LENGTH] == 0 {
-            return Ok(AjazzInput::NoData);
-        }
+/// Returns true if the report is a response to a command rather than input.
+/// Both arrive the same way, so responses show up between inputs, e.g. during a flush.
+///
+/// Input reports start with the same `ACK` header as responses, followed by the action
+/// code, so only reports without an action code are taken for responses. Such reports
+/// carry no input on any device: code 0 releases no keys on "v1" devices and is not
+/// an input code on "v2" ones, so no input is lost if a report is taken wrongly
+pub(crate) fn is_command_response(data: &[u8]) -> bool {
+    data.starts_with(codes::RESPONSE_ACK)
+        && data
+            .get(codes::OFFSET_ACTION_CODE)
+            .is_none_or(|code| *code == codes::ACTION_CODE_NOP)
+}
 
-        let action_code = data[codes::OFFSET_ACTION_CODE];
+/// Input report with bounds checked access to its fields
+struct InputReport<'a>(&'a [u8]);
 
-        match self {
-            kind if kind.is_v1_api() => {
-                let mut states = vec![false; self.key_count() as usize];
-                if action_code != codes::ACTION_CODE_NOP {
-                    let raw_index = action_code - 1;
-                    let Some(index) = kind.index_from_native_v1(raw_index) else {
-                        return Err(AjazzError::BadData);
-                    };
-                    states[index as usize] = true;
-                }
-
-                Ok(AjazzInput::ButtonStateChange(states))
-            }
+impl InputReport<'_> {
+    /// Returns byte at the offset, or [AjazzError::BadData] if report is too short
+    fn byte(&self, offset: usize) -> Result<u8, AjazzError> {
+        self.0.get(offset).copied().ok_or(AjazzError::BadData)
+    }
 
-            kind if kind.is_v2_api() => {
-                if is_akp03_button_press(action_code) {
-                    parse_akp03_button_press(action_code)
-                } else if is_akp03_encoder_value(action_code) {
-                    parse_akp03_encoder_value(action_code)
-                } else if is_akp03_encoder_press(action_code) {
-                    parse_akp03_encoder_press(action_code)
-                } else {
-                    println!("Bad data: {:?}", data);
-                    Err(AjazzError::BadData)
-                }
+    /// Returns true if report carries no data. Empty slice is returned on read timeout
+    fn is_empty(&self) -> bool {
+        self.0
+            .get(codes::OFFSET_DATA_LENGTH)
+            .is_none_or(|len| *len == 0)
+    }
+}
+
+/// Parses input report read from the device.
+///
+/// Function is pure and doesn't need a connected device, so reports coming from any source
+/// can be parsed with it. Empty reports produce [AjazzInput::NoData],
+/// reports that are too short to contain an action produce [AjazzError::BadData].
+pub fn parse_input(kind: Kind, data: &[u8]) -> Result<AjazzInput, AjazzError> {
+    let report = InputReport(data);
+    if report.is_empty() {
+        return Ok(AjazzInput::NoData);
+    }
+
+    if !kind.is_enabled() {
+        return Err(AjazzError::UnsupportedOperation);
+    }
+
+    match kind {
+        #[cfg(any(feature = "akp153", feature = "akp815"))]
+        kind if kind.is_v1_api() => {
+            let action_code = report.byte(codes::OFFSET_ACTION_CODE)?;
+            let mut states = vec![false; kind.key_count() as usize];
+            if action_code != codes::ACTION_CODE_NOP {
+                let raw_index = action_code - 1;
+                let Some(index) = kind.index_from_native_v1(raw_index) else {
+                    return Err(AjazzError::BadData);
+                };
+                states[index as usize] = true;
             }
 
-            _ => Err(AjazzError::UnsupportedOperation),
+            Ok(AjazzInput::ButtonStateChange(states))
         }
+
+        #[cfg(feature = "akp03")]
+        kind if kind.is_v2_api() => parse_akp03_input(data, codes::OFFSET_ACTION_CODE),
+
+        _ => Err(AjazzError::UnsupportedOperation),
     }
+}
 
+impl AjazzProtocolParser for Kind {
     /// Converts Ajazz native key index to normalized key index
     fn index_from_native_v1(&self, i: u8) -> Option<u8> {
         if i >= self.key_count() || !self.is_v1_api() {
@@ -62,40 +99,34 @@ impl AjazzProtocolParser for Kind {
         }
 
         match self {
-            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => {
-                if i < self.key_count() {
-                    Some(
-                        [4, 10, 16, 3, 9, 15, 2, 8, 14, 1, 7, 13, 0, 6, 12, 5, 11, 17]
-                            [i as usize],
-                    )
-                } else {
-                    Some(i)
-                }
-            }
-            Kind::Akp815 => {
-                if i < self.key_count() {
-                    Some(self.key_count() - 1 - i)
-                } else {
-                    Some(i)
-                }
-            }
+            // Touch keys come after the 3x5 grid of display keys
+            #[cfg(feature = "akp153")]
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => Some(
+                [4, 9, 14, 3, 8, 13, 2, 7, 12, 1, 6, 11, 0, 5, 10, 15, 16, 17][i as usize],
+            ),
+            #[cfg(feature = "akp815")]
+            Kind::Akp815 => Some(self.key_count() - 1 - i),
+            Kind::UnknownV1 { .. } => Some(i),
             _ => None,
         }
     }
 
-    /// Converts normalized key index to Ajazz native key index
+    /// Converts normalized key index to Ajazz native key index,
+    /// the exact inverse of [index_from_native_v1](AjazzProtocolParser::index_from_native_v1)
     fn index_to_native_v1(&self, key: u8) -> Option<u8> {
-        if self.is_v1_api() {
-            if key < self.key_count() {
-                Some(
-                    [12, 9, 6, 3, 0, 15, 13, 10, 7, 4, 1, 16, 14, 11, 8, 5, 2, 17]
-                        [key as usize],
-                )
-            } else {
-                Some(key)
-            }
-        } else {
-            None
+        if key >= self.key_count() || !self.is_v1_api() {
+            return None;
+        }
+
+        match self {
+            #[cfg(feature = "akp153")]
+            Kind::Akp153 | Kind::Akp153E | Kind::Akp153R => Some(
+                [12, 9, 6, 3, 0, 13, 10, 7, 4, 1, 14, 11, 8, 5, 2, 15, 16, 17][key as usize],
+            ),
+            #[cfg(feature = "akp815")]
+            Kind::Akp815 => Some(self.key_count() - 1 - key),
+            Kind::UnknownV1 { .. } => Some(key),
+            _ => None,
         }
     }
 
@@ -104,7 +135,33 @@ impl AjazzProtocolParser for Kind {
     }
 }
 
-fn parse_akp03_button_press(input: u8) -> Result<AjazzInput, AjazzError> {
+/// Parses input of the AKP03 family from the action code at the offset of the report.
+///
+/// Returns [AjazzError::BadData] if the report is too short or the action code is unknown
+#[cfg(feature = "akp03")]
+#[cfg_attr(docsrs, doc(cfg(feature = "akp03")))]
+pub fn parse_akp03_input(report: &[u8], offset: usize) -> Result<AjazzInput, AjazzError> {
+    let action_code = InputReport(report).byte(offset)?;
+    if is_akp03_button_press(action_code) {
+        parse_akp03_button_press(report, offset)
+    } else if is_akp03_encoder_value(action_code) {
+        parse_akp03_encoder_value(report, offset)
+    } else if is_akp03_encoder_press(action_code) {
+        parse_akp03_encoder_press(report, offset)
+    } else {
+        Err(AjazzError::BadData)
+    }
+}
+
+/// Parses key press of the AKP03 family from the action code at the offset of the report.
+/// Code 0 releases every key
+#[cfg(feature = "akp03")]
+#[cfg_attr(docsrs, doc(cfg(feature = "akp03")))]
+pub fn parse_akp03_button_press(
+    report: &[u8],
+    offset: usize,
+) -> Result<AjazzInput, AjazzError> {
+    let input = InputReport(report).byte(offset)?;
     let mut button_states = vec![false; Kind::Akp03.key_count() as usize];
     if input == 0 {
         return Ok(AjazzInput::ButtonStateChange(button_states));
@@ -124,7 +181,14 @@ fn parse_akp03_button_press(input: u8) -> Result<AjazzInput, AjazzError> {
     Ok(AjazzInput::ButtonStateChange(button_states))
 }
 
-fn parse_akp03_encoder_value(input: u8) -> Result<AjazzInput, AjazzError> {
+/// Parses encoder twist of the AKP03 family from the action code at the offset of the report
+#[cfg(feature = "akp03")]
+#[cfg_attr(docsrs, doc(cfg(feature = "akp03")))]
+pub fn parse_akp03_encoder_value(
+    report: &[u8],
+    offset: usize,
+) -> Result<AjazzInput, AjazzError> {
+    let input = InputReport(report).byte(offset)?;
     let mut encoder_values = vec![0i8; Kind::Akp03.encoder_count() as usize];
 
     let (encoder, value): (usize, i8) = match input {
@@ -144,7 +208,14 @@ fn parse_akp03_encoder_value(input: u8) -> Result<AjazzInput, AjazzError> {
     Ok(AjazzInput::EncoderTwist(encoder_values))
 }
 
-fn parse_akp03_encoder_press(input: u8) -> Result<AjazzInput, AjazzError> {
+/// Parses encoder press of the AKP03 family from the action code at the offset of the report
+#[cfg(feature = "akp03")]
+#[cfg_attr(docsrs, doc(cfg(feature = "akp03")))]
+pub fn parse_akp03_encoder_press(
+    report: &[u8],
+    offset: usize,
+) -> Result<AjazzInput, AjazzError> {
+    let input = InputReport(report).byte(offset)?;
     let mut encoder_states = vec![false; Kind::Akp03.encoder_count() as usize];
 
     let encoder: usize = match input {
@@ -158,6 +229,7 @@ fn parse_akp03_encoder_press(input: u8) -> Result<AjazzInput, AjazzError> {
     Ok(AjazzInput::EncoderStateChange(encoder_states))
 }
 
+#[cfg(feature = "akp03")]
 fn is_akp03_encoder_value(input: u8) -> bool {
     matches!(
         input,
@@ -170,6 +242,7 @@ fn is_akp03_encoder_value(input: u8) -> bool {
     )
 }
 
+#[cfg(feature = "akp03")]
 fn is_akp03_encoder_press(input: u8) -> bool {
     matches!(
         input,
@@ -179,6 +252,7 @@ fn is_akp03_encoder_press(input: u8) -> bool {
     )
 }
 
+#[cfg(feature = "akp03")]
 fn is_akp03_button_press(input: u8) -> bool {
     matches!(
         input,
@@ -190,3 +264,205 @@ fn is_akp03_button_press(input: u8) -> bool {
             | codes::ACTION_CODE_BUTTON_9
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(action_code: u8) -> Vec<u8> {
+        let mut data = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        data[codes::OFFSET_DATA_LENGTH] = 0x41;
+        data[codes::OFFSET_ACTION_CODE] = action_code;
+        data
+    }
+
+    #[test]
+    fn test_parse_every_action_code() {
+        for kind in Kind::ALL {
+            for action_code in 0..=u8::MAX {
+                let _ = parse_input(kind, &report(action_code));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_unknown_action_code() {
+        for kind in Kind::ALL {
+            if !kind.is_enabled() {
+                continue;
+            }
+
+            assert!(
+                matches!(parse_input(kind, &report(0xFF)), Err(AjazzError::BadData)),
+                "{kind:?}"
+            );
+            for action_code in 0..=u8::MAX {
+                let input = parse_input(kind, &report(action_code));
+                assert!(
+                    matches!(input, Ok(_) | Err(AjazzError::BadData)),
+                    "{kind:?} with {action_code:#04x}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_empty_report() {
+        let data = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        assert!(matches!(
+            parse_input(Kind::Akp153, &data),
+            Ok(AjazzInput::NoData)
+        ));
+        assert!(matches!(
+            parse_input(Kind::Akp03, &data),
+            Ok(AjazzInput::NoData)
+        ));
+    }
+
+    #[test]
+    fn test_parse_truncated_report() {
+        for kind in Kind::ALL {
+            if !kind.is_enabled() {
+                continue;
+            }
+
+            assert!(matches!(parse_input(kind, &[]), Ok(AjazzInput::NoData)));
+            assert!(matches!(parse_input(kind, &[0x00]), Ok(AjazzInput::NoData)));
+
+            let data = report(0x01);
+            for length in 1..=codes::OFFSET_ACTION_CODE {
+                assert!(
+                    matches!(parse_input(kind, &data[..length]), Err(AjazzError::BadData)),
+                    "{kind:?} with {length} bytes"
+                );
+            }
+            assert!(parse_input(kind, &data[..=codes::OFFSET_ACTION_CODE]).is_ok());
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "akp153", feature = "akp815"))]
+    fn test_parse_v1_button() {
+        let input = parse_input(Kind::Akp815, &report(0x01)).unwrap();
+        let AjazzInput::ButtonStateChange(states) = input else {
+            panic!("unexpected input: {input:?}");
+        };
+        assert_eq!(states.len(), 15);
+        assert!(states[14]);
+
+        assert!(matches!(
+            parse_input(Kind::Akp815, &report(0x10)),
+            Err(AjazzError::BadData)
+        ));
+        assert!(matches!(
+            parse_input(Kind::Akp153, &report(0x40)),
+            Err(AjazzError::BadData)
+        ));
+    }
+
+    #[test]
+    fn test_native_index_round_trip() {
+        let unknown = Kind::UnknownV1 { pid: 0x1234 };
+        for kind in Kind::ALL.into_iter().chain([unknown]) {
+            if !kind.is_enabled() || !kind.is_v1_api() {
+                continue;
+            }
+
+            let mut logical = vec![];
+            for native in 0..kind.key_count() {
+                let key = kind.index_from_native_v1(native).unwrap();
+                assert_eq!(kind.index_to_native_v1(key), Some(native), "{kind:?}");
+                logical.push(key);
+            }
+            logical.sort();
+            assert!(logical.into_iter().eq(0..kind.key_count()), "{kind:?}");
+            assert_eq!(kind.index_from_native_v1(kind.key_count()), None);
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "akp153", feature = "akp815", feature = "akp03"))]
+    fn test_physical_to_logical() {
+        for kind in Kind::ALL {
+            if !kind.is_enabled() {
+                continue;
+            }
+
+            for index in 0..kind.key_count() {
+                let key = kind.physical_to_logical(index).unwrap();
+                let native = kind.index_to_native_v1(key).unwrap_or(key);
+                assert_eq!(native, index, "{kind:?}");
+            }
+            assert_eq!(kind.physical_to_logical(kind.key_count()), None);
+        }
+
+        // Top right key of the AKP153 is the first one the hardware reports
+        assert_eq!(Kind::Akp153.physical_to_logical(0), Some(4));
+        assert_eq!(Kind::Akp815.physical_to_logical(0), Some(14));
+        assert_eq!(Kind::Akp153.physical_to_logical(16), Some(16));
+        assert_eq!(Kind::Akp03.physical_to_logical(7), Some(7));
+    }
+
+    #[test]
+    #[cfg(all(any(feature = "akp153", feature = "akp815"), feature = "akp03"))]
+    fn test_parse_unknown_kind() {
+        let kind = Kind::from_vid_pid_or_unknown(codes::VENDOR_ID_MIRABOX_V1, 0x1234).unwrap();
+        assert_eq!(kind, Kind::UnknownV1 { pid: 0x1234 });
+        assert!(kind.is_unknown() && kind.is_v1_api());
+        assert_eq!(kind.index_to_native_v1(4), Some(4));
+
+        let input = parse_input(kind, &report(0x05)).unwrap();
+        let AjazzInput::ButtonStateChange(states) = input else {
+            panic!("unexpected input: {input:?}");
+        };
+        assert!(states[4]);
+
+        let kind = Kind::from_vid_pid_or_unknown(codes::VENDOR_ID_MIRABOX_V2, 0x1234).unwrap();
+        assert!(kind.is_v2_api());
+        assert_eq!(Kind::from_vid_pid_or_unknown(0x1234, 0x1234), None);
+    }
+
+    #[test]
+    #[cfg(feature = "akp03")]
+    fn test_parse_akp03_inputs() {
+        let kind = Kind::Akp03E;
+
+        let input = parse_input(kind, &report(codes::ACTION_CODE_BUTTON_9)).unwrap();
+        assert!(matches!(input, AjazzInput::ButtonStateChange(s) if s[8]));
+
+        let input = parse_input(kind, &report(codes::ACTION_CODE_ENCODER_0_CCW)).unwrap();
+        assert!(matches!(input, AjazzInput::EncoderTwist(t) if t == [-1, 0, 0]));
+
+        let input = parse_input(kind, &report(codes::ACTION_CODE_ENCODER_1_PRESS)).unwrap();
+        assert!(
+            matches!(input, AjazzInput::EncoderStateChange(s) if s == [false, true, false])
+        );
+
+        assert!(matches!(
+            parse_input(kind, &report(0x07)),
+            Err(AjazzError::BadData)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "akp03")]
+    fn test_parse_akp03_with_offset() {
+        // Report behind a 2 byte header of some other framing
+        let framed = [0xAA, 0x55, codes::ACTION_CODE_ENCODER_2_CW];
+
+        let input = parse_akp03_input(&framed, 2).unwrap();
+        assert!(matches!(input, AjazzInput::EncoderTwist(t) if t == [0, 0, 1]));
+
+        let input = parse_akp03_button_press(&[0x00, 0x03], 1).unwrap();
+        assert!(matches!(input, AjazzInput::ButtonStateChange(s) if s[2]));
+
+        assert!(matches!(
+            parse_akp03_encoder_press(&framed, 2),
+            Err(AjazzError::BadData)
+        ));
+        assert!(matches!(
+            parse_akp03_input(&framed, 3),
+            Err(AjazzError::BadData)
+        ));
+    }
+}
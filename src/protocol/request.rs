@@ -68,6 +68,8 @@ pub(crate) trait AjazzRequestBuilder {
     fn key_image_announce_packet(&self, key: u8, image_data: &[u8]) -> Vec<u8>;
 
     fn logo_image_packet(&self, image_data: &[u8]) -> Vec<u8>;
+
+    fn raw_command_packet(&self, command: &[u8]) -> Option<Vec<u8>>;
 }
 
 impl Kind {
@@ -79,6 +81,11 @@ impl Kind {
         }
     }
 
+    /// Longest raw command that fits into a report after the request header
+    pub(crate) fn raw_command_limit(&self) -> usize {
+        self.packet_length() + 1 - codes::REQUEST_HEADER.len()
+    }
+
     /// Extends buffer up to required packet length
     pub fn pad_packet(&self, buf: &mut Vec<u8>) {
         let length = self.packet_length() + 1;
@@ -121,7 +128,7 @@ impl AjazzRequestBuilder for Kind {
     }
 
     fn clear_button_image_packet(&self, key: u8) -> Vec<u8> {
-        let key = self.index_from_native_v1(key).unwrap_or(key);
+        let key = self.index_to_native_v1(key).unwrap_or(key);
         let key = if key == 0xff { 0xff } else { key + 1 };
 
         let mut buf = REQUEST_CLEAR_BUTTON_IMAGE.clone();
@@ -162,6 +169,16 @@ impl AjazzRequestBuilder for Kind {
         self.pad_packet(&mut buf);
         buf
     }
+
+    fn raw_command_packet(&self, command: &[u8]) -> Option<Vec<u8>> {
+        if command.len() > self.raw_command_limit() {
+            return None;
+        }
+
+        let mut buf = format_request(command);
+        self.pad_packet(&mut buf);
+        Some(buf)
+    }
 }
 
 #[cfg(test)]
@@ -242,6 +259,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "akp153")]
     fn test_clear_button_image_packet() {
         let kind = Kind::Akp153;
 
@@ -255,14 +273,14 @@ mod tests {
             kind,
             0,
             vec![
-                0x00, 0x43, 0x52, 0x54, 0x00, 0x00, 0x43, 0x4c, 0x45, 0x00, 0x00, 0x00, 0x05,
+                0x00, 0x43, 0x52, 0x54, 0x00, 0x00, 0x43, 0x4c, 0x45, 0x00, 0x00, 0x00, 0x0D,
             ],
         );
         assert_clear_packet(
             kind,
             1,
             vec![
-                0x00, 0x43, 0x52, 0x54, 0x00, 0x00, 0x43, 0x4c, 0x45, 0x00, 0x00, 0x00, 0x0B,
+                0x00, 0x43, 0x52, 0x54, 0x00, 0x00, 0x43, 0x4c, 0x45, 0x00, 0x00, 0x00, 0x0A,
             ],
         );
         assert_clear_packet(
@@ -272,6 +290,12 @@ mod tests {
                 0x00, 0x43, 0x52, 0x54, 0x00, 0x00, 0x43, 0x4c, 0x45, 0x00, 0x00, 0x00, 0xff,
             ],
         );
+
+        // Key is numbered the same way as in the image announce
+        for key in kind.display_keys() {
+            let announce = kind.key_image_announce_packet(key, &[]);
+            assert_eq!(kind.clear_button_image_packet(key)[12], announce[13]);
+        }
     }
 
     #[test]
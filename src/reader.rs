@@ -0,0 +1,648 @@
+use std::ops::Range;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::device::Ajazz;
+use crate::locks::lock;
+use crate::idle::IdleTracker;
+#[cfg(feature = "image")]
+use crate::press_feedback::PressFeedbackState;
+use crate::recording::{InputLog, Recorder};
+use crate::timers::{shortest_timeout, Timers};
+use crate::{AjazzError, EncoderTracker};
+#[cfg(feature = "image")]
+use crate::PressFeedback;
+
+/// Type of input that the device produced
+#[derive(Clone, Debug)]
+pub enum AjazzInput {
+    /// No data was passed from the device
+    NoData,
+
+    /// Button was pressed
+    ButtonStateChange(Vec<bool>),
+
+    /// Encoder/Knob was pressed
+    EncoderStateChange(Vec<bool>),
+
+    /// Encoder/Knob was twisted/turned
+    EncoderTwist(Vec<i8>),
+}
+
+impl AjazzInput {
+    /// Checks if there's data received or not
+    pub fn is_empty(&self) -> bool {
+        matches!(self, AjazzInput::NoData)
+    }
+}
+
+/// Tells what changed in button states
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// Button got pressed down
+    ButtonDown(u8),
+
+    /// Button got released
+    ButtonUp(u8),
+
+    /// [Touch key](crate::Kind::touch_keys) got pressed down, e.g. 15 - 17 on the AKP153
+    TouchKeyDown(u8),
+
+    /// [Touch key](crate::Kind::touch_keys) got released
+    TouchKeyUp(u8),
+
+    /// Encoder got pressed down
+    EncoderDown(u8),
+
+    /// Encoder was released from being pressed down
+    EncoderUp(u8),
+
+    /// Encoder was twisted
+    EncoderTwist(u8, i8),
+
+    /// Absolute position of the encoder changed, if encoder tracking is enabled on the reader
+    EncoderPositionChanged(u8, i32),
+
+    /// Timer registered on the reader fired
+    Tick(u32),
+
+    /// Nothing was pressed for the idle timeout set on the reader, with time since
+    /// the last input
+    Idle(Duration),
+
+    /// Input arrived after the reader reported [Event::Idle]
+    Active,
+}
+
+/// Predicate deciding which events are returned by readers
+type EventFilter = Box<dyn Fn(&Event) -> bool + Send + Sync>;
+
+#[derive(Default)]
+pub(crate) struct DeviceState {
+    pub buttons: Vec<bool>,
+    pub encoders: Vec<bool>,
+    /// Keys reported as [Event::TouchKeyDown] and [Event::TouchKeyUp]
+    pub touch_key_range: Range<u8>,
+    pub encoder_tracker: Option<EncoderTracker>,
+    pub debounce: Vec<Duration>,
+    pub last_button_change: Vec<Option<Instant>>,
+    pub filter: Option<EventFilter>,
+    #[cfg(feature = "image")]
+    pub press_feedback: Option<PressFeedbackState>,
+    pub timers: Timers,
+    pub recorder: Option<Recorder>,
+    pub idle: Option<IdleTracker>,
+    pub wake_on_press: bool,
+}
+
+impl Ajazz {
+    /// Returns device state reader for this device
+    pub fn get_reader(self: &Arc<Self>) -> Arc<DeviceStateReader> {
+        #[allow(clippy::arc_with_non_send_sync)]
+        Arc::new(DeviceStateReader {
+            device: self.clone(),
+            states: Mutex::new(DeviceState {
+                buttons: vec![false; self.key_count() as usize],
+                encoders: vec![false; self.kind().encoder_count() as usize],
+                touch_key_range: self.touch_keys(),
+                ..Default::default()
+            }),
+            subscribers: Mutex::new(vec![]),
+        })
+    }
+}
+
+/// Button reader that keeps state of the Ajazz and returns events instead of full states
+pub struct DeviceStateReader {
+    device: Arc<Ajazz>,
+    states: Mutex<DeviceState>,
+    subscribers: Mutex<Vec<Sender<Event>>>,
+}
+
+pub(crate) fn handle_input_state_change(
+    input: AjazzInput,
+    current_state: &mut DeviceState,
+) -> Result<Vec<Event>, AjazzError> {
+    let now = Instant::now();
+    let mut updates = vec![];
+    match input {
+        AjazzInput::ButtonStateChange(buttons) => {
+            for (index, is_changed) in buttons.iter().enumerate() {
+                if !is_changed {
+                    continue;
+                }
+
+                // State follows the device even when the change isn't reported,
+                // so the next change after a bounce is reported the right way
+                current_state.buttons[index] = !current_state.buttons[index];
+                if current_state.is_bouncing(index, now) {
+                    continue;
+                }
+
+                if let Some(last_change) = current_state.last_button_change.get_mut(index) {
+                    *last_change = Some(now);
+                }
+                let key = index as u8;
+                let touch_keys = &current_state.touch_key_range;
+                updates.push(
+                    match (touch_keys.contains(&key), current_state.buttons[index]) {
+                        (true, true) => Event::TouchKeyDown(key),
+                        (true, false) => Event::TouchKeyUp(key),
+                        (false, true) => Event::ButtonDown(key),
+                        (false, false) => Event::ButtonUp(key),
+                    },
+                );
+            }
+        }
+
+        AjazzInput::EncoderStateChange(encoders) => {
+            for (index, is_changed) in encoders.iter().enumerate() {
+                if !is_changed {
+                    continue;
+                }
+
+                current_state.encoders[index] = !current_state.encoders[index];
+                if current_state.encoders[index] {
+                    updates.push(Event::EncoderDown(index as u8));
+                } else {
+                    updates.push(Event::EncoderUp(index as u8));
+                }
+            }
+        }
+
+        AjazzInput::EncoderTwist(twist) => {
+            for (index, change) in twist.iter().enumerate() {
+                if *change != 0 {
+                    updates.push(Event::EncoderTwist(index as u8, *change));
+                }
+            }
+        }
+
+        _ => {}
+    }
+
+    if let Some(tracker) = current_state.encoder_tracker.as_mut() {
+        let positions: Vec<Event> = updates.iter().filter_map(|u| tracker.handle(u)).collect();
+        updates.extend(positions);
+    }
+
+    if let Some(idle) = current_state.idle.as_mut() {
+        idle.handle(&mut updates, now);
+    }
+
+    updates.extend(current_state.timers.poll(now));
+
+    if let Some(filter) = current_state.filter.as_ref() {
+        updates.retain(|update| filter(update));
+    }
+
+    Ok(updates)
+}
+
+/// Returns true if the event comes from pressing something on the device
+pub(crate) fn is_press(update: &Event) -> bool {
+    matches!(
+        update,
+        Event::ButtonDown(_)
+            | Event::TouchKeyDown(_)
+            | Event::EncoderDown(_)
+            | Event::EncoderTwist(..)
+    )
+}
+
+impl DeviceState {
+    /// Sets debounce of the key, or of every key if `key` is None
+    pub(crate) fn set_debounce(
+        &mut self,
+        key: Option<u8>,
+        debounce: Duration,
+    ) -> Result<(), AjazzError> {
+        let count = self.buttons.len();
+        self.debounce.resize(count, Duration::ZERO);
+        self.last_button_change.resize(count, None);
+
+        match key {
+            Some(key) => {
+                let Some(current) = self.debounce.get_mut(key as usize) else {
+                    return Err(AjazzError::InvalidKeyIndex(key));
+                };
+                *current = debounce;
+            }
+            None => self.debounce.fill(debounce),
+        }
+
+        Ok(())
+    }
+
+    /// Returns time left until the reader has to report ticks or idleness by itself
+    pub(crate) fn until_next_event(&self, now: Instant) -> Option<Duration> {
+        let until_idle = self.idle.as_ref().and_then(|idle| idle.until_idle(now));
+        shortest_timeout(self.timers.until_next(now), until_idle)
+    }
+
+    /// Returns true if the button changed state too recently to change it again
+    fn is_bouncing(&self, index: usize, now: Instant) -> bool {
+        let debounce = self.debounce.get(index).copied().unwrap_or_default();
+        match self.last_button_change.get(index).copied().flatten() {
+            Some(last_change) => now - last_change < debounce,
+            None => false,
+        }
+    }
+}
+
+impl DeviceStateReader {
+    /// Reads states and returns updates
+    pub fn read(&self, timeout: Option<Duration>) -> Result<Vec<Event>, AjazzError> {
+        let until_tick = lock(&self.states).until_next_event(Instant::now());
+        let input = self
+            .device
+            .read_input(shortest_timeout(timeout, until_tick))?;
+        let mut current_state = lock(&self.states);
+
+        let updates = handle_input_state_change(input, &mut current_state)?;
+        if current_state.wake_on_press
+            && self.device.is_asleep()
+            && updates.iter().any(is_press)
+        {
+            self.device.wake_and_restore()?;
+        }
+        #[cfg(feature = "image")]
+        if let Some(feedback) = current_state.press_feedback.as_mut() {
+            feedback.handle(&self.device, &updates)?;
+        }
+        if let Some(recorder) = current_state.recorder.as_mut() {
+            recorder.push(&updates, Instant::now());
+        }
+
+        let mut subscribers = lock(&self.subscribers);
+        subscribers.retain(|subscriber| updates.iter().all(|u| subscriber.send(*u).is_ok()));
+
+        Ok(updates)
+    }
+
+    /// Reads until an event matching the predicate appears and returns it.
+    /// Returns `None` if the timeout passes first, waits forever if timeout is `None`.
+    /// Events that don't match are still delivered to subscribers
+    pub fn wait_for(
+        &self,
+        mut predicate: impl FnMut(&Event) -> bool,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Event>, AjazzError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let left =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if left.is_some_and(|left| left.is_zero()) {
+                return Ok(None);
+            }
+
+            let updates = self.read(left)?;
+            if let Some(update) = updates.into_iter().find(|update| predicate(update)) {
+                return Ok(Some(update));
+            }
+        }
+    }
+
+    /// Waits until any button is pressed and returns its index, see [DeviceStateReader::wait_for]
+    pub fn wait_for_button(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<u8>, AjazzError> {
+        let update =
+            self.wait_for(|update| matches!(update, Event::ButtonDown(_)), timeout)?;
+        Ok(update.and_then(|update| match update {
+            Event::ButtonDown(key) => Some(key),
+            _ => None,
+        }))
+    }
+
+    /// Waits until any encoder is pressed or twisted and returns the event,
+    /// see [DeviceStateReader::wait_for]
+    pub fn wait_for_encoder(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Event>, AjazzError> {
+        self.wait_for(
+            |update| matches!(update, Event::EncoderDown(_) | Event::EncoderTwist(..)),
+            timeout,
+        )
+    }
+
+    /// Returns receiver that gets every event read by this reader, no matter who calls
+    /// [DeviceStateReader::read]. Lets several parts of the application observe input
+    /// while a single loop reads it. Receivers can be moved to other threads
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = channel();
+        lock(&self.subscribers).push(sender);
+        receiver
+    }
+
+    /// Enables tracking of absolute encoder positions,
+    /// which are reported as [Event::EncoderPositionChanged]
+    pub fn enable_encoder_tracking(&self, tracker: EncoderTracker) {
+        let mut current_state = lock(&self.states);
+        current_state.encoder_tracker = Some(tracker);
+    }
+
+    /// Returns absolute position of the encoder, if encoder tracking is enabled
+    pub fn encoder_position(&self, encoder: u8) -> Option<i32> {
+        let current_state = lock(&self.states);
+        current_state
+            .encoder_tracker
+            .as_ref()
+            .and_then(|tracker| tracker.position(encoder))
+    }
+
+    /// Doesn't report button state changes happening sooner than `debounce` after
+    /// the reported one, e.g. a release right after the press is dropped and the next
+    /// press is reported as [Event::ButtonDown] again.
+    /// Applies to every key, zero duration disables debouncing
+    pub fn set_debounce(&self, debounce: Duration) {
+        let mut current_state = lock(&self.states);
+        // Setting debounce of every key can't fail
+        let _ = current_state.set_debounce(None, debounce);
+    }
+
+    /// Sets debounce of the single key, see [DeviceStateReader::set_debounce]
+    pub fn set_key_debounce(&self, key: u8, debounce: Duration) -> Result<(), AjazzError> {
+        let mut current_state = lock(&self.states);
+        current_state.set_debounce(Some(key), debounce)
+    }
+
+    /// Registers timer reported as [Event::Tick] with the returned id every interval,
+    /// so clocks and animations can be driven from the same loop as input.
+    /// Reads return early when a timer is due
+    pub fn every(&self, interval: Duration) -> u32 {
+        let mut current_state = lock(&self.states);
+        current_state.timers.add(interval, Instant::now())
+    }
+
+    /// Removes timer registered with [DeviceStateReader::every].
+    /// Returns false if there was no such timer
+    pub fn cancel_timer(&self, id: u32) -> bool {
+        let mut current_state = lock(&self.states);
+        current_state.timers.remove(id)
+    }
+
+    /// Sets predicate deciding which events are returned, other events are dropped
+    pub fn set_filter(&self, filter: impl Fn(&Event) -> bool + Send + Sync + 'static) {
+        let mut current_state = lock(&self.states);
+        current_state.filter = Some(Box::new(filter));
+    }
+
+    /// Makes held keys show darkened or highlighted version of their image, or disables it
+    /// with `None`. Works with images set through this device instance, which are restored
+    /// when keys are released
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn set_press_feedback(&self, feedback: Option<PressFeedback>) {
+        let mut current_state = lock(&self.states);
+        current_state.press_feedback = feedback.map(PressFeedbackState::new);
+    }
+
+    /// Reports [Event::Idle] after nothing was pressed for the timeout, and [Event::Active]
+    /// on the next input. `None` disables idle detection
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        let now = Instant::now();
+        lock(&self.states).idle = timeout.map(|timeout| IdleTracker::new(timeout, now));
+    }
+
+    /// Wakes the device up and restores its images when a key or an encoder is pressed
+    /// after [Ajazz::sleep]. The press is still reported
+    pub fn set_wake_on_press(&self, enabled: bool) {
+        lock(&self.states).wake_on_press = enabled;
+    }
+
+    /// Starts recording events read by this reader, dropping the previous recording.
+    /// Recorded events can be taken with [DeviceStateReader::stop_recording]
+    pub fn record(&self) {
+        lock(&self.states).recorder = Some(Recorder::new());
+    }
+
+    /// Stops recording and returns the recorded events, or `None` if nothing was recorded
+    pub fn stop_recording(&self) -> Option<InputLog> {
+        lock(&self.states).recorder.take().map(Recorder::finish)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "akp03")]
+    use crate::protocol::codes;
+    use crate::replay::ReplayTransport;
+    use crate::Kind;
+
+    #[test]
+    fn test_touch_key_events() {
+        #[allow(clippy::arc_with_non_send_sync)]
+        let device = Arc::new(Ajazz::from_transport(
+            Kind::Akp153,
+            ReplayTransport::default(),
+        ));
+        let reader = device.get_reader();
+        let mut state = lock(&reader.states);
+        let mut press = |key: usize| {
+            let mut buttons = vec![false; 18];
+            buttons[key] = true;
+            handle_input_state_change(AjazzInput::ButtonStateChange(buttons), &mut state)
+                .unwrap()
+        };
+
+        assert_eq!(press(14), [Event::ButtonDown(14)]);
+        assert_eq!(press(16), [Event::TouchKeyDown(16)]);
+        assert_eq!(press(16), [Event::TouchKeyUp(16)]);
+        assert_eq!(device.touch_keys(), 15..18);
+        assert!(matches!(
+            device.set_button_image_data(15, [0xAA; 10]),
+            Err(AjazzError::NoScreenOnKey(15))
+        ));
+    }
+
+    #[test]
+    fn test_debounce_and_filter() {
+        let mut state = DeviceState {
+            buttons: vec![false; 3],
+            ..Default::default()
+        };
+        let press = || AjazzInput::ButtonStateChange(vec![true, false, false]);
+
+        state.set_debounce(None, Duration::from_secs(60)).unwrap();
+        state.set_debounce(Some(2), Duration::ZERO).unwrap();
+        assert!(matches!(
+            state.set_debounce(Some(3), Duration::ZERO),
+            Err(AjazzError::InvalidKeyIndex(3))
+        ));
+
+        let updates = handle_input_state_change(press(), &mut state).unwrap();
+        assert_eq!(updates, [Event::ButtonDown(0)]);
+        let updates = handle_input_state_change(press(), &mut state).unwrap();
+        assert!(updates.is_empty());
+
+        state.filter = Some(Box::new(|event| !matches!(event, Event::ButtonUp(_))));
+        let release = || AjazzInput::ButtonStateChange(vec![false, false, true]);
+        let updates = handle_input_state_change(release(), &mut state).unwrap();
+        assert_eq!(updates, [Event::ButtonDown(2)]);
+        let updates = handle_input_state_change(release(), &mut state).unwrap();
+        assert!(updates.is_empty());
+    }
+
+    #[test]
+    fn test_debounce_follows_device_state() {
+        let mut state = DeviceState {
+            buttons: vec![false; 1],
+            ..Default::default()
+        };
+        let toggle = || AjazzInput::ButtonStateChange(vec![true]);
+        state.set_debounce(None, Duration::from_millis(50)).unwrap();
+
+        let updates = handle_input_state_change(toggle(), &mut state).unwrap();
+        assert_eq!(updates, [Event::ButtonDown(0)]);
+
+        // Release inside the window isn't reported
+        let updates = handle_input_state_change(toggle(), &mut state).unwrap();
+        assert!(updates.is_empty());
+
+        std::thread::sleep(Duration::from_millis(60));
+        let updates = handle_input_state_change(toggle(), &mut state).unwrap();
+        assert_eq!(updates, [Event::ButtonDown(0)]);
+        let updates = handle_input_state_change(toggle(), &mut state).unwrap();
+        assert!(updates.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "akp03")]
+    fn test_subscribers_get_every_event() {
+        let replay = ReplayTransport::default();
+        let mut report = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        report[codes::OFFSET_DATA_LENGTH] = 0x41;
+        report[codes::OFFSET_ACTION_CODE] = 0x01;
+        replay.push_read(&report);
+
+        #[allow(clippy::arc_with_non_send_sync)]
+        let device = Arc::new(Ajazz::from_transport(Kind::Akp03, replay));
+        let reader = device.get_reader();
+        let first = reader.subscribe();
+        let second = reader.subscribe();
+        drop(reader.subscribe());
+
+        let updates = reader.read(Some(Duration::from_millis(1))).unwrap();
+        assert_eq!(updates, [Event::ButtonDown(0)]);
+        assert_eq!(first.try_recv(), Ok(Event::ButtonDown(0)));
+        assert_eq!(second.try_recv(), Ok(Event::ButtonDown(0)));
+        assert!(first.try_recv().is_err());
+        assert_eq!(reader.subscribers.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "akp03")]
+    fn test_wake_on_press_restores_images() {
+        let replay = ReplayTransport::default();
+        let mut report = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        report[codes::OFFSET_DATA_LENGTH] = 0x41;
+        report[codes::OFFSET_ACTION_CODE] = 0x01;
+        replay.push_read(&report);
+
+        #[allow(clippy::arc_with_non_send_sync)]
+        let device = Arc::new(Ajazz::from_transport(Kind::Akp03, replay.clone()));
+        device.set_button_image_data(1, [0xAB; 10]).unwrap();
+        device.flush().unwrap();
+        device.sleep().unwrap();
+        assert!(device.is_asleep());
+
+        let reader = device.get_reader();
+        reader.set_wake_on_press(true);
+        let written = replay.written().len();
+        let updates = reader.read(Some(Duration::from_millis(1))).unwrap();
+        assert_eq!(updates, [Event::ButtonDown(0)]);
+        assert!(!device.is_asleep());
+
+        let resent = &replay.written()[written..];
+        assert!(resent.iter().any(|p| p[1..11] == [0xAB; 10]));
+        assert_eq!(&resent.last().unwrap()[6..9], b"STP");
+    }
+
+    #[test]
+    #[cfg(feature = "akp03")]
+    fn test_wait_for_matching_event() {
+        let replay = ReplayTransport::default();
+        let mut report = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        report[codes::OFFSET_DATA_LENGTH] = 0x41;
+        report[codes::OFFSET_ACTION_CODE] = 0x01;
+        replay.push_read(&report);
+        replay.push_read(&report);
+
+        #[allow(clippy::arc_with_non_send_sync)]
+        let device = Arc::new(Ajazz::from_transport(Kind::Akp03, replay));
+        let reader = device.get_reader();
+        let events = reader.subscribe();
+
+        let timeout = Some(Duration::from_millis(1));
+        let update = reader.wait_for(|u| matches!(u, Event::ButtonUp(_)), timeout);
+        assert_eq!(update.unwrap(), Some(Event::ButtonUp(0)));
+        assert_eq!(events.try_recv(), Ok(Event::ButtonDown(0)));
+
+        assert_eq!(reader.wait_for_button(timeout).unwrap(), None);
+        assert_eq!(reader.wait_for_encoder(timeout).unwrap(), None);
+    }
+
+    #[test]
+    #[cfg(all(feature = "image", feature = "akp03"))]
+    fn test_press_feedback_restores_image() {
+        let replay = ReplayTransport::default();
+        let mut report = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        report[codes::OFFSET_DATA_LENGTH] = 0x41;
+        report[codes::OFFSET_ACTION_CODE] = 0x01;
+        replay.push_read(&report);
+        replay.push_read(&report);
+
+        #[allow(clippy::arc_with_non_send_sync)]
+        let device = Arc::new(Ajazz::from_transport(Kind::Akp03, replay.clone()));
+        device
+            .set_button_image(0, image::DynamicImage::new_rgb8(60, 60))
+            .unwrap();
+        device.flush().unwrap();
+        let state = device.export_state();
+
+        let reader = device.get_reader();
+        reader.set_press_feedback(Some(PressFeedback::default()));
+
+        let timeout = Some(Duration::from_millis(1));
+        let before = replay.written().len();
+        assert_eq!(reader.read(timeout).unwrap(), [Event::ButtonDown(0)]);
+        assert_eq!(device.export_state(), state);
+        let pressed = replay.written().len();
+        assert!(pressed > before);
+        assert_eq!(&replay.written().last().unwrap()[6..9], b"STP");
+
+        assert_eq!(reader.read(timeout).unwrap(), [Event::ButtonUp(0)]);
+        let written = replay.written();
+        assert!(written.len() > pressed);
+        assert_eq!(&written.last().unwrap()[6..9], b"STP");
+        assert_eq!(device.export_state(), state);
+    }
+
+    #[test]
+    fn test_ticks_interleave_with_input() {
+        #[allow(clippy::arc_with_non_send_sync)]
+        let device = Arc::new(Ajazz::from_transport(
+            Kind::Akp03,
+            ReplayTransport::default(),
+        ));
+        let reader = device.get_reader();
+        let timer = reader.every(Duration::from_millis(5));
+
+        let update = reader.wait_for(|_| true, Some(Duration::from_secs(1)));
+        assert_eq!(update.unwrap(), Some(Event::Tick(timer)));
+
+        assert!(reader.cancel_timer(timer));
+        assert_eq!(
+            reader
+                .wait_for(|_| true, Some(Duration::from_millis(20)))
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Bindings, Event};
+
+/// Event read by the reader, with time passed since the recording started
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecordedEvent {
+    /// Time since the recording started
+    pub at: Duration,
+    /// The event
+    pub event: Event,
+}
+
+/// Timestamped log of events, see [DeviceStateReader::record].
+///
+/// Logs can be saved with the `serde` feature and played back later,
+/// e.g. for demo modes or tests of the deck application
+///
+/// [DeviceStateReader::record]: crate::DeviceStateReader::record
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InputLog {
+    /// Events in the order they were read
+    pub events: Vec<RecordedEvent>,
+}
+
+impl InputLog {
+    /// Dispatches events to the bindings, keeping the time between them as recorded.
+    /// Returns amount of called handlers
+    pub fn play_back(&self, bindings: &mut Bindings) -> usize {
+        let started = Instant::now();
+        let mut called = 0;
+        for recorded in &self.events {
+            sleep(recorded.at.saturating_sub(started.elapsed()));
+            called += bindings.dispatch(&recorded.event);
+        }
+        called
+    }
+
+    /// Dispatches events to the bindings right away, ignoring the timestamps.
+    /// Returns amount of called handlers
+    pub fn dispatch_all(&self, bindings: &mut Bindings) -> usize {
+        self.events
+            .iter()
+            .map(|recorded| bindings.dispatch(&recorded.event))
+            .sum()
+    }
+}
+
+/// Recording in progress on the reader
+pub(crate) struct Recorder {
+    started: Instant,
+    log: InputLog,
+}
+
+impl Recorder {
+    pub(crate) fn new() -> Recorder {
+        Recorder {
+            started: Instant::now(),
+            log: InputLog::default(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, events: &[Event], now: Instant) {
+        let at = now.saturating_duration_since(self.started);
+        self.log.events.extend(
+            events
+                .iter()
+                .map(|event| RecordedEvent { at, event: *event }),
+        );
+    }
+
+    pub(crate) fn finish(self) -> InputLog {
+        self.log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn test_play_back_keeps_timing() {
+        let mut recorder = Recorder::new();
+        let started = recorder.started;
+        recorder.push(&[Event::ButtonDown(1)], started);
+        recorder.push(
+            &[Event::ButtonUp(1), Event::EncoderTwist(0, -2)],
+            started + Duration::from_millis(30),
+        );
+        let log = recorder.finish();
+        assert_eq!(log.events.len(), 3);
+        assert_eq!(log.events[2].at, Duration::from_millis(30));
+
+        let pressed = Rc::new(RefCell::new(vec![]));
+        let mut bindings = Bindings::new();
+        let on_press = pressed.clone();
+        bindings.on_button_down(1, move || on_press.borrow_mut().push(1));
+        let on_twist = pressed.clone();
+        bindings.on_encoder_twist(0, move |delta| on_twist.borrow_mut().push(delta));
+
+        let start = Instant::now();
+        assert_eq!(log.play_back(&mut bindings), 2);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(*pressed.borrow(), [1, -2]);
+
+        assert_eq!(log.dispatch_all(&mut bindings), 2);
+    }
+}
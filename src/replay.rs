@@ -0,0 +1,761 @@
+//! Capture and replay of device sessions.
+//!
+//! [Recorder] wraps a real [Transport] and logs every report that goes through it.
+//! The resulting [Capture] can be saved to a file and later fed into [ReplayTransport],
+//! which plays recorded device responses back, allowing protocol regression tests
+//! without hardware.
+//!
+//! Captures are stored as text, one report per line: direction (`W` for written reports,
+//! `R` for read reports, `F` for feature reports), report length and report data in hex.
+//! Trailing zeros of the data are omitted. Lines starting with `#` are comments.
+//!
+//! ```text
+//! # AKP153, set brightness to 50%
+//! W 513 00435254000044495300
+//! W 513 0043525400004c4947000032
+//! ```
+//!
+//! [CommandLog] adds timestamps to the same format, so logs attached to bug reports
+//! show when every report happened.
+
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Error as IoError, ErrorKind, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hidapi::{HidError, HidResult};
+use thiserror::Error;
+
+use crate::locks::lock;
+use crate::protocol::codes;
+use crate::transport::Transport;
+
+/// Direction of the captured report
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Report written to the device
+    Write,
+    /// Input report read from the device
+    Read,
+    /// Feature report read from the device
+    FeatureReport,
+}
+
+impl Direction {
+    fn tag(self) -> char {
+        match self {
+            Direction::Write => 'W',
+            Direction::Read => 'R',
+            Direction::FeatureReport => 'F',
+        }
+    }
+}
+
+/// Single captured report
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    /// Direction of the report
+    pub direction: Direction,
+    /// Report data, including report ID
+    pub data: Vec<u8>,
+}
+
+/// Ordered list of reports exchanged with the device
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capture {
+    /// Captured reports in order of occurrence
+    pub packets: Vec<Packet>,
+}
+
+impl Capture {
+    /// Loads capture from the file
+    pub fn load(path: impl AsRef<Path>) -> Result<Capture, IoError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Saves capture to the file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), IoError> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Adds report to the end of the capture
+    pub fn push(&mut self, direction: Direction, data: &[u8]) {
+        self.packets.push(Packet {
+            direction,
+            data: data.to_vec(),
+        });
+    }
+
+    /// Returns reports of the given direction
+    pub fn filter(&self, direction: Direction) -> impl Iterator<Item = &[u8]> {
+        self.packets
+            .iter()
+            .filter(move |p| p.direction == direction)
+            .map(|p| p.data.as_slice())
+    }
+}
+
+impl Display for Capture {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for packet in &self.packets {
+            writeln!(f, "{}", format_packet(packet))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Capture {
+    type Err = IoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let packets = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(parse_packet)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Capture { packets })
+    }
+}
+
+fn format_packet(packet: &Packet) -> String {
+    let used = packet
+        .data
+        .iter()
+        .rposition(|b| *b != 0)
+        .map_or(0, |i| i + 1);
+    let hex: String = packet.data[..used]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+
+    format!("{} {} {}", packet.direction.tag(), packet.data.len(), hex)
+        .trim_end()
+        .to_string()
+}
+
+fn parse_packet(line: &str) -> Result<Packet, IoError> {
+    let invalid = || {
+        IoError::new(
+            ErrorKind::InvalidData,
+            format!("Invalid capture line: {line}"),
+        )
+    };
+
+    let mut parts = line.split_whitespace();
+    let direction = match parts.next() {
+        Some("W") => Direction::Write,
+        Some("R") => Direction::Read,
+        Some("F") => Direction::FeatureReport,
+        _ => return Err(invalid()),
+    };
+    let length: usize = parts
+        .next()
+        .and_then(|l| l.parse().ok())
+        .ok_or_else(invalid)?;
+    let hex = parts.next().unwrap_or("");
+
+    if !hex.len().is_multiple_of(2) || hex.len() / 2 > length || parts.next().is_some() {
+        return Err(invalid());
+    }
+
+    let mut data = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect::<Result<Vec<u8>, _>>()?;
+    data.resize(length, 0);
+
+    Ok(Packet { direction, data })
+}
+
+#[derive(Default)]
+struct RecorderState {
+    capture: Capture,
+    file: Option<BufWriter<File>>,
+}
+
+impl RecorderState {
+    fn record(&mut self, direction: Direction, data: &[u8]) -> HidResult<()> {
+        self.capture.push(direction, data);
+
+        if let Some(file) = &mut self.file {
+            let packet = self.capture.packets.last().expect("packet was just pushed");
+            writeln!(file, "{}", format_packet(packet))
+                .and_then(|()| file.flush())
+                .map_err(|error| HidError::IoError { error })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Records every report going through wrapped transports.
+///
+/// Recorder is a cheap handle, clones share the same capture.
+///
+/// ```no_run
+/// use ajazz_sdk::{new_hidapi, Ajazz, Kind};
+/// use ajazz_sdk::replay::Recorder;
+///
+/// let hidapi = new_hidapi().unwrap();
+/// let hid = hidapi.open(Kind::Akp153.vendor_id(), Kind::Akp153.product_id()).unwrap();
+///
+/// let recorder = Recorder::to_file("session.capture").unwrap();
+/// let device = Ajazz::from_transport(Kind::Akp153, recorder.wrap(hid));
+/// device.set_brightness(50).unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct Recorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+impl Recorder {
+    /// Creates recorder that keeps capture in memory
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    /// Creates recorder that also appends every report to the file as it happens
+    pub fn to_file(path: impl AsRef<Path>) -> Result<Recorder, IoError> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Recorder {
+            state: Arc::new(Mutex::new(RecorderState {
+                capture: Capture::default(),
+                file: Some(file),
+            })),
+        })
+    }
+
+    /// Wraps transport, so reports going through it are recorded
+    pub fn wrap<T: Transport>(&self, inner: T) -> RecordingTransport<T> {
+        RecordingTransport {
+            inner,
+            recorder: self.clone(),
+        }
+    }
+
+    /// Returns everything recorded so far
+    pub fn capture(&self) -> Capture {
+        lock(&self.state).capture.clone()
+    }
+
+    fn record(&self, direction: Direction, data: &[u8]) -> HidResult<()> {
+        lock(&self.state).record(direction, data)
+    }
+}
+
+/// Transport that records reports going through the inner transport, see [Recorder]
+pub struct RecordingTransport<T> {
+    inner: T,
+    recorder: Recorder,
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn write(&self, data: &[u8]) -> HidResult<usize> {
+        let written = self.inner.write(data)?;
+        self.recorder.record(Direction::Write, data)?;
+        Ok(written)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> HidResult<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.recorder.record(Direction::Read, &buf[..read])?;
+        }
+        Ok(read)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> HidResult<usize> {
+        let read = self.inner.read_timeout(buf, timeout)?;
+        if read > 0 {
+            self.recorder.record(Direction::Read, &buf[..read])?;
+        }
+        Ok(read)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> HidResult<()> {
+        self.inner.set_blocking_mode(blocking)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+        let read = self.inner.get_feature_report(buf)?;
+        self.recorder
+            .record(Direction::FeatureReport, &buf[..read.min(buf.len())])?;
+        Ok(read)
+    }
+
+    fn get_manufacturer_string(&self) -> HidResult<Option<String>> {
+        self.inner.get_manufacturer_string()
+    }
+
+    fn get_product_string(&self) -> HidResult<Option<String>> {
+        self.inner.get_product_string()
+    }
+
+    fn get_serial_number_string(&self) -> HidResult<Option<String>> {
+        self.inner.get_serial_number_string()
+    }
+}
+
+/// Report of a [CommandLog] with the time it happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    /// Time since the log was enabled
+    pub elapsed: Duration,
+    /// Logged report
+    pub packet: Packet,
+}
+
+/// Timestamped log of reports exchanged with the device, written by
+/// [Ajazz::enable_command_log](crate::Ajazz::enable_command_log).
+///
+/// Every line is a capture line after time since the log was enabled, in microseconds.
+/// Logging again to the same file appends to it, starting with a comment line
+///
+/// ```text
+/// # Log started
+/// 0 W 513 00435254000044495300
+/// 1520 W 513 0043525400004c4947000032
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandLog {
+    /// Logged reports in order of occurrence
+    pub entries: Vec<LogEntry>,
+}
+
+impl CommandLog {
+    /// Loads log from the file
+    pub fn load(path: impl AsRef<Path>) -> Result<CommandLog, IoError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Returns reports without timestamps, e.g. to replay them with [ReplayTransport]
+    pub fn capture(&self) -> Capture {
+        Capture {
+            packets: self.entries.iter().map(|e| e.packet.clone()).collect(),
+        }
+    }
+
+    /// Returns human readable listing of the log, one report per line
+    /// with names of the commands
+    pub fn pretty(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{:>10.3} ms  {}  {}\n",
+                    entry.elapsed.as_secs_f64() * 1000.0,
+                    entry.packet.direction.tag(),
+                    describe_packet(&entry.packet)
+                )
+            })
+            .collect()
+    }
+}
+
+impl FromStr for CommandLog {
+    type Err = IoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let entries = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (micros, packet) = line.split_once(' ').unwrap_or((line, ""));
+                let micros = micros.parse().map_err(|_| {
+                    IoError::new(ErrorKind::InvalidData, format!("Invalid log line: {line}"))
+                })?;
+
+                Ok(LogEntry {
+                    elapsed: Duration::from_micros(micros),
+                    packet: parse_packet(packet)?,
+                })
+            })
+            .collect::<Result<_, IoError>>()?;
+
+        Ok(CommandLog { entries })
+    }
+}
+
+/// Returns command name and arguments of requests, size of other reports
+fn describe_packet(packet: &Packet) -> String {
+    let data = &packet.data;
+    let used = data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    let Some(request) = data[..used].strip_prefix(codes::REQUEST_HEADER) else {
+        return match packet.direction {
+            Direction::Write => format!("image data, {used} bytes"),
+            _ => format!("{} bytes: {}", used, hex_prefix(&data[..used])),
+        };
+    };
+
+    let name_length = request
+        .iter()
+        .position(|b| !b.is_ascii_uppercase())
+        .unwrap_or(request.len());
+    let name = String::from_utf8_lossy(&request[..name_length]);
+
+    format!("{name} {}", hex_prefix(&request[name_length..]))
+        .trim_end()
+        .to_string()
+}
+
+/// Formats up to 16 first bytes as hex
+fn hex_prefix(data: &[u8]) -> String {
+    let mut hex: Vec<String> = data.iter().take(16).map(|b| format!("{b:02x}")).collect();
+    if data.len() > 16 {
+        hex.push("..".to_string());
+    }
+
+    hex.join(" ")
+}
+
+/// Appends reports to the command log file
+pub(crate) struct CommandLogWriter {
+    file: BufWriter<File>,
+    started: Instant,
+}
+
+impl CommandLogWriter {
+    pub(crate) fn open(path: impl AsRef<Path>) -> Result<CommandLogWriter, IoError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut file = BufWriter::new(file);
+        writeln!(file, "# Log started")?;
+        file.flush()?;
+
+        Ok(CommandLogWriter {
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    pub(crate) fn record(&mut self, direction: Direction, data: &[u8]) -> Result<(), IoError> {
+        let packet = Packet {
+            direction,
+            data: data.to_vec(),
+        };
+        let micros = self.started.elapsed().as_micros();
+        writeln!(self.file, "{micros} {}", format_packet(&packet))?;
+        self.file.flush()
+    }
+}
+
+/// Difference between reports written during replay and reports in the capture
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Written report #{index} doesn't match the capture")]
+pub struct ReplayMismatch {
+    /// Index of the first mismatched written report
+    pub index: usize,
+    /// Report from the capture, `None` if more reports were written than captured
+    pub expected: Option<Vec<u8>>,
+    /// Written report, `None` if less reports were written than captured
+    pub actual: Option<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    reads: VecDeque<Vec<u8>>,
+    feature_reports: VecDeque<Vec<u8>>,
+    expected_writes: Vec<Vec<u8>>,
+    written: Vec<Vec<u8>>,
+}
+
+/// Fake device that plays back captured responses.
+///
+/// Read reports are returned in capture order, reads past the end of the capture
+/// behave like a timeout. Written reports are collected and can be compared
+/// to the capture with [verify](ReplayTransport::verify).
+///
+/// Transport is a cheap handle, clones share the same state, so one clone can be passed
+/// to [Ajazz::from_transport](crate::Ajazz::from_transport) and another kept for assertions.
+#[derive(Clone, Default)]
+pub struct ReplayTransport {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl ReplayTransport {
+    /// Creates transport that replays the capture
+    pub fn new(capture: &Capture) -> ReplayTransport {
+        let state = ReplayState {
+            reads: capture
+                .filter(Direction::Read)
+                .map(<[u8]>::to_vec)
+                .collect(),
+            feature_reports: capture
+                .filter(Direction::FeatureReport)
+                .map(<[u8]>::to_vec)
+                .collect(),
+            expected_writes: capture
+                .filter(Direction::Write)
+                .map(<[u8]>::to_vec)
+                .collect(),
+            written: vec![],
+        };
+
+        ReplayTransport {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Queues input report to be returned by the next read
+    pub fn push_read(&self, data: &[u8]) {
+        lock(&self.state).reads.push_back(data.to_vec());
+    }
+
+    /// Queues feature report to be returned by the next feature report request
+    pub fn push_feature_report(&self, data: &[u8]) {
+        lock(&self.state).feature_reports.push_back(data.to_vec());
+    }
+
+    /// Returns amount of input reports that were not read yet
+    pub fn remaining_reads(&self) -> usize {
+        lock(&self.state).reads.len()
+    }
+
+    /// Returns reports written so far
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        lock(&self.state).written.clone()
+    }
+
+    /// Checks that written reports are exactly the reports from the capture
+    pub fn verify(&self) -> Result<(), ReplayMismatch> {
+        let state = lock(&self.state);
+        let count = state.expected_writes.len().max(state.written.len());
+
+        for index in 0..count {
+            let expected = state.expected_writes.get(index);
+            let actual = state.written.get(index);
+            if expected != actual {
+                return Err(ReplayMismatch {
+                    index,
+                    expected: expected.cloned(),
+                    actual: actual.cloned(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn next_read(&self, buf: &mut [u8]) -> usize {
+        let Some(data) = lock(&self.state).reads.pop_front() else {
+            return 0;
+        };
+
+        let length = data.len().min(buf.len());
+        buf[..length].copy_from_slice(&data[..length]);
+        buf[length..].fill(0);
+        length
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn write(&self, data: &[u8]) -> HidResult<usize> {
+        lock(&self.state).written.push(data.to_vec());
+        Ok(data.len())
+    }
+
+    fn read(&self, buf: &mut [u8]) -> HidResult<usize> {
+        Ok(self.next_read(buf))
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], _timeout: i32) -> HidResult<usize> {
+        Ok(self.next_read(buf))
+    }
+
+    fn set_blocking_mode(&self, _blocking: bool) -> HidResult<()> {
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+        let Some(data) = lock(&self.state).feature_reports.pop_front() else {
+            return Err(HidError::HidApiError {
+                message: "No feature reports left in the capture".to_string(),
+            });
+        };
+
+        let length = data.len().min(buf.len());
+        buf[..length].copy_from_slice(&data[..length]);
+        Ok(length)
+    }
+
+    fn get_manufacturer_string(&self) -> HidResult<Option<String>> {
+        Ok(None)
+    }
+
+    fn get_product_string(&self) -> HidResult<Option<String>> {
+        Ok(None)
+    }
+
+    fn get_serial_number_string(&self) -> HidResult<Option<String>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "akp153", feature = "akp03"))]
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{Ajazz, Kind};
+
+    fn input_report(action_code: u8, state: u8) -> Vec<u8> {
+        let mut data = vec![0u8; 512];
+        data[0] = 0x41;
+        data[9] = action_code;
+        data[10] = state;
+        data
+    }
+
+    #[test]
+    fn test_capture_text_round_trip() {
+        let mut capture = Capture::default();
+        capture.push(Direction::Write, &[0x00, 0x43, 0x52, 0x54, 0x00, 0x00]);
+        capture.push(Direction::Read, &input_report(0x01, 0x01));
+        capture.push(Direction::FeatureReport, &[0x01, 0x56, 0x31]);
+        capture.push(Direction::Read, &[0x00; 4]);
+
+        let text = capture.to_string();
+        assert_eq!(text.lines().next(), Some("W 6 00435254"));
+        assert_eq!(text.lines().last(), Some("R 4"));
+        assert_eq!(text.parse::<Capture>().unwrap(), capture);
+    }
+
+    #[test]
+    #[cfg(feature = "akp153")]
+    fn test_command_log() {
+        let path = std::env::temp_dir().join(format!("ajazz-log-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let replay = ReplayTransport::default();
+        replay.push_read(&input_report(0x01, 0x01));
+        let device = Ajazz::from_transport(Kind::Akp153, replay);
+
+        device.enable_command_log(&path).unwrap();
+        device.set_brightness(50).unwrap();
+        device.read_input(Some(Duration::ZERO)).unwrap();
+        device.disable_command_log();
+        device.set_brightness(60).unwrap();
+
+        let log = CommandLog::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let directions: Vec<Direction> =
+            log.entries.iter().map(|e| e.packet.direction).collect();
+        assert_eq!(
+            directions,
+            [Direction::Write, Direction::Write, Direction::Read]
+        );
+        assert!(log.entries[1].elapsed >= log.entries[0].elapsed);
+
+        let pretty = log.pretty();
+        let lines: Vec<&str> = pretty.lines().collect();
+        assert!(lines[0].ends_with("W  DIS"));
+        assert!(lines[1].ends_with("W  LIG 00 00 32"));
+        assert!(lines[2].ends_with("R  11 bytes: 41 00 00 00 00 00 00 00 00 01 01"));
+        assert_eq!(log.capture().packets.len(), 3);
+    }
+
+    #[test]
+    fn test_capture_rejects_invalid_lines() {
+        assert!("X 1 00".parse::<Capture>().is_err());
+        assert!("W 1 0000".parse::<Capture>().is_err());
+        assert!("W 2 0".parse::<Capture>().is_err());
+        assert!("W two".parse::<Capture>().is_err());
+        assert!("# comment\n\nW 1 ff".parse::<Capture>().is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "akp153")]
+    fn test_record_and_verify_session() {
+        let recorder = Recorder::new();
+        let fake = ReplayTransport::default();
+        fake.push_read(&input_report(0x01, 0x01));
+
+        let device = Ajazz::from_transport(Kind::Akp153, recorder.wrap(fake));
+        device.set_brightness(50).unwrap();
+        device.read_input(Some(Duration::from_millis(10))).unwrap();
+
+        let capture = recorder.capture();
+        assert_eq!(capture.filter(Direction::Write).count(), 2);
+        assert_eq!(capture.filter(Direction::Read).count(), 1);
+
+        let replay = ReplayTransport::new(&capture);
+        let device = Ajazz::from_transport(Kind::Akp153, replay.clone());
+        device.set_brightness(50).unwrap();
+        assert_eq!(replay.remaining_reads(), 1);
+        device.read_input(Some(Duration::from_millis(10))).unwrap();
+        assert_eq!(replay.verify(), Ok(()));
+
+        device.set_brightness(10).unwrap();
+        assert_eq!(replay.verify().unwrap_err().index, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "akp153")]
+    fn test_akp153_key_remapping() {
+        use std::sync::Arc;
+
+        use crate::Event;
+
+        let replay = ReplayTransport::default();
+        replay.push_read(&input_report(0x01, 0x01));
+        replay.push_read(&input_report(0x01, 0x00));
+
+        #[allow(clippy::arc_with_non_send_sync)]
+        let device = Arc::new(Ajazz::from_transport(Kind::Akp153, replay));
+        let reader = device.get_reader();
+
+        let events = reader.read(Some(Duration::from_millis(10))).unwrap();
+        assert!(matches!(events.as_slice(), [Event::ButtonDown(4)]));
+        let events = reader.read(Some(Duration::from_millis(10))).unwrap();
+        assert!(matches!(events.as_slice(), [Event::ButtonUp(4)]));
+    }
+
+    #[test]
+    fn test_clear_all_flushes_on_v2() {
+        for (kind, reports) in [(Kind::Akp153, 2), (Kind::Akp03R, 3)] {
+            let replay = ReplayTransport::default();
+            let device = Ajazz::from_transport(kind, replay.clone());
+            device.clear_all_button_images().unwrap();
+
+            let written = replay.written();
+            assert_eq!(written.len(), reports, "{kind:?}");
+            assert_eq!(written[0][6..9], *b"DIS");
+            assert_eq!(written[1][6..9], *b"CLE");
+            assert_eq!(written[1][12], 0xff);
+            if kind.is_v2_api() {
+                assert_eq!(written[2][6..9], *b"STP");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "akp03")]
+    fn test_akp03_capture_file() {
+        use crate::AjazzInput;
+
+        // Synthetic fixture, it checks action code parsing rather than real firmware output
+        let capture = Capture::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/testdata/akp03_input.capture"
+        ))
+        .unwrap();
+        let replay = ReplayTransport::new(&capture);
+        let device = Ajazz::from_transport(Kind::Akp03R, replay.clone());
+        let read = || device.read_input(Some(Duration::from_millis(10))).unwrap();
+
+        assert!(matches!(read(), AjazzInput::ButtonStateChange(s) if s[0]));
+        assert!(matches!(read(), AjazzInput::EncoderTwist(t) if t == [0, 1, 0]));
+        assert!(
+            matches!(read(), AjazzInput::EncoderStateChange(s) if s == [false, false, true])
+        );
+        assert!(matches!(read(), AjazzInput::NoData));
+        assert_eq!(replay.verify(), Ok(()));
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Brightness set from the time of day until the next change of the schedule
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BrightnessChange {
+    /// Hour of the day, from 0 to 23
+    pub hour: u8,
+    /// Minute of the hour, from 0 to 59
+    pub minute: u8,
+    /// Brightness in percent
+    pub percent: u8,
+}
+
+/// Brightness depending on the time of day, see [FramePump::set_brightness_schedule].
+///
+/// Changes can be listed in any order. The latest change of the day lasts past midnight
+/// until the earliest one.
+///
+/// ```
+/// use ajazz_sdk::{BrightnessChange, BrightnessSchedule};
+///
+/// let schedule = BrightnessSchedule {
+///     changes: vec![
+///         BrightnessChange { hour: 8, minute: 0, percent: 80 },
+///         BrightnessChange { hour: 22, minute: 30, percent: 10 },
+///     ],
+///     utc_offset_minutes: 60,
+/// };
+/// ```
+///
+/// [FramePump::set_brightness_schedule]: crate::FramePump::set_brightness_schedule
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BrightnessSchedule {
+    /// Brightness changes during the day
+    pub changes: Vec<BrightnessChange>,
+    /// Offset of the local time from UTC in minutes. The standard library doesn't know
+    /// the time zone, so it has to be provided by the application
+    pub utc_offset_minutes: i32,
+}
+
+impl BrightnessChange {
+    fn second_of_day(&self) -> u64 {
+        (u64::from(self.hour) * 60 + u64::from(self.minute)) * 60 % SECONDS_PER_DAY
+    }
+}
+
+impl BrightnessSchedule {
+    /// Returns brightness at the local time of day, or `None` if the schedule is empty
+    pub fn brightness_at(&self, hour: u8, minute: u8) -> Option<u8> {
+        let second = (u64::from(hour) * 60 + u64::from(minute)) * 60;
+        self.current_change(second).map(|change| change.percent)
+    }
+
+    /// Returns brightness the deck should have now
+    pub(crate) fn current_brightness(&self, now: SystemTime) -> Option<u8> {
+        self.current_change(self.second_of_day(now))
+            .map(|change| change.percent)
+    }
+
+    /// Returns time left until the next change of the schedule
+    pub(crate) fn until_next_change(&self, now: SystemTime) -> Option<Duration> {
+        let second = self.second_of_day(now);
+        self.changes
+            .iter()
+            .map(|change| {
+                let start = change.second_of_day();
+                (start + SECONDS_PER_DAY - second - 1) % SECONDS_PER_DAY + 1
+            })
+            .min()
+            .map(Duration::from_secs)
+    }
+
+    fn current_change(&self, second: u64) -> Option<&BrightnessChange> {
+        let latest = |a: &&BrightnessChange, b: &&BrightnessChange| {
+            a.second_of_day().cmp(&b.second_of_day())
+        };
+        self.changes
+            .iter()
+            .filter(|change| change.second_of_day() <= second)
+            .max_by(latest)
+            .or_else(|| self.changes.iter().max_by(latest))
+    }
+
+    fn second_of_day(&self, now: SystemTime) -> u64 {
+        let utc = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let offset = i64::from(self.utc_offset_minutes) * 60;
+        (utc as i64 + offset).rem_euclid(SECONDS_PER_DAY as i64) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule() -> BrightnessSchedule {
+        BrightnessSchedule {
+            changes: vec![
+                BrightnessChange {
+                    hour: 22,
+                    minute: 0,
+                    percent: 10,
+                },
+                BrightnessChange {
+                    hour: 8,
+                    minute: 30,
+                    percent: 80,
+                },
+            ],
+            utc_offset_minutes: 0,
+        }
+    }
+
+    #[test]
+    fn test_brightness_by_time_of_day() {
+        let schedule = schedule();
+        assert_eq!(schedule.brightness_at(3, 0), Some(10));
+        assert_eq!(schedule.brightness_at(8, 29), Some(10));
+        assert_eq!(schedule.brightness_at(8, 30), Some(80));
+        assert_eq!(schedule.brightness_at(21, 59), Some(80));
+        assert_eq!(schedule.brightness_at(22, 0), Some(10));
+        assert_eq!(BrightnessSchedule::default().brightness_at(12, 0), None);
+    }
+
+    #[test]
+    fn test_time_zone_and_next_change() {
+        let mut schedule = schedule();
+        // 21:00 UTC
+        let now = UNIX_EPOCH + Duration::from_secs(21 * 60 * 60);
+        assert_eq!(schedule.current_brightness(now), Some(80));
+        assert_eq!(
+            schedule.until_next_change(now),
+            Some(Duration::from_secs(60 * 60))
+        );
+
+        schedule.utc_offset_minutes = 120;
+        assert_eq!(schedule.current_brightness(now), Some(10));
+        assert_eq!(
+            schedule.until_next_change(now),
+            Some(Duration::from_secs(9 * 60 * 60 + 30 * 60))
+        );
+    }
+}
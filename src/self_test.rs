@@ -0,0 +1,280 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "image")]
+use image::{DynamicImage, Rgb, RgbImage};
+
+#[cfg(feature = "image")]
+use crate::ImageRect;
+
+use crate::device::Ajazz;
+use crate::{AjazzError, Kind};
+
+/// Pause between visual steps, so a person watching the device can follow along
+const STEP_DELAY: Duration = Duration::from_millis(500);
+
+/// Brightness values the self-test cycles through
+const BRIGHTNESS_STEPS: [u8; 5] = [0, 25, 50, 75, 100];
+
+/// Outcome of a single self-test step
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StepStatus {
+    /// Step finished without errors
+    Passed,
+    /// Step failed with an error
+    Failed(String),
+    /// Step doesn't apply to this device
+    Skipped,
+}
+
+/// Single step of the self-test
+#[derive(Clone, Debug)]
+pub struct SelfTestStep {
+    /// Name of the step
+    pub name: &'static str,
+    /// Outcome of the step
+    pub status: StepStatus,
+    /// Time the step took
+    pub duration: Duration,
+}
+
+/// Report produced by [Ajazz::self_test]
+#[derive(Clone, Debug)]
+pub struct SelfTestReport {
+    /// Kind of the tested device
+    pub kind: Kind,
+    /// Firmware version, if device reported it
+    pub firmware_version: Option<String>,
+    /// Steps in order of execution
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    /// Returns true if no step failed
+    pub fn is_passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| !matches!(step.status, StepStatus::Failed(_)))
+    }
+
+    /// Returns steps that failed
+    pub fn failed_steps(&self) -> impl Iterator<Item = &SelfTestStep> {
+        self.steps
+            .iter()
+            .filter(|step| matches!(step.status, StepStatus::Failed(_)))
+    }
+
+    fn run(&mut self, name: &'static str, step: impl FnOnce() -> Result<(), AjazzError>) {
+        let started = Instant::now();
+        let status = match step() {
+            Ok(()) => StepStatus::Passed,
+            Err(e) => StepStatus::Failed(e.to_string()),
+        };
+
+        self.steps.push(SelfTestStep {
+            name,
+            status,
+            duration: started.elapsed(),
+        });
+    }
+
+    fn skip(&mut self, name: &'static str) {
+        self.steps.push(SelfTestStep {
+            name,
+            status: StepStatus::Skipped,
+            duration: Duration::ZERO,
+        });
+    }
+}
+
+impl Ajazz {
+    /// Runs through every basic device feature and reports which of them work.
+    ///
+    /// Cycles brightness, paints a test pattern on every display key, clears the keys,
+    /// paints the LCD strip if the device has one, then puts the device to sleep and wakes it up. Steps keep running after a failure,
+    /// so the report shows the state of every feature. The boot logo is persistent
+    /// and is left untouched.
+    ///
+    /// Device is left cleared with full brightness.
+    pub fn self_test(&self) -> SelfTestReport {
+        let mut report = SelfTestReport {
+            kind: self.kind(),
+            firmware_version: self.firmware_version().ok(),
+            steps: vec![],
+        };
+
+        report.run("brightness", || {
+            for percent in BRIGHTNESS_STEPS {
+                self.set_brightness(percent)?;
+                sleep(STEP_DELAY / 2);
+            }
+            Ok(())
+        });
+
+        // Test pattern can't be drawn without image support
+        #[cfg(not(feature = "image"))]
+        report.skip("key_images");
+        #[cfg(feature = "image")]
+        report.run("key_images", || {
+            for key in 0..self.kind().display_key_count() {
+                self.set_button_image(key, test_pattern(self.kind(), key))?;
+            }
+            self.flush()?;
+            sleep(STEP_DELAY);
+            Ok(())
+        });
+
+        report.run("clear_key", || {
+            self.clear_button_image(0)?;
+            self.flush()?;
+            sleep(STEP_DELAY);
+            Ok(())
+        });
+
+        report.run("clear_all", || {
+            self.clear_all_button_images()?;
+            self.flush()?;
+            sleep(STEP_DELAY);
+            Ok(())
+        });
+
+        // Strip is painted through the image pipeline, same as key images
+        #[cfg(not(feature = "image"))]
+        report.skip("lcd_strip");
+        #[cfg(feature = "image")]
+        match self.kind().lcd_strip_size() {
+            Some((width, height)) => report.run("lcd_strip", || {
+                let pattern = strip_pattern(width as u32, height as u32);
+                self.write_lcd_fill(&ImageRect::from_image(pattern)?)?;
+                sleep(STEP_DELAY);
+                let black = RgbImage::new(width as u32, height as u32);
+                self.write_lcd_fill(&ImageRect::from_image(DynamicImage::ImageRgb8(black))?)
+            }),
+            None => report.skip("lcd_strip"),
+        }
+
+        report.run("sleep", || {
+            self.sleep()?;
+            sleep(STEP_DELAY);
+            Ok(())
+        });
+
+        report.run("wake", || {
+            self.wake()?;
+            sleep(STEP_DELAY);
+            Ok(())
+        });
+
+        report.run("keep_alive", || self.keep_alive());
+
+        report
+    }
+}
+
+/// Generates test pattern for the key.
+///
+/// Pattern is filled with a color unique to the key and has a white marker in the top left
+/// corner, so wrong rotation or mirroring is easy to spot.
+#[cfg(feature = "image")]
+pub(crate) fn test_pattern(kind: Kind, key: u8) -> DynamicImage {
+    let (width, height) = kind.key_image_format().size;
+    let (width, height) = (width as u32, height as u32);
+
+    let hue = u32::from(key) * 360 / u32::from(kind.display_key_count().max(1));
+    let color = hue_to_rgb(hue);
+    let marker = width.min(height) / 4;
+
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        if x < marker && y < marker {
+            Rgb([255, 255, 255])
+        } else if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+            Rgb([0, 0, 0])
+        } else {
+            color
+        }
+    });
+
+    DynamicImage::ImageRgb8(image)
+}
+
+/// Generates rainbow running left to right across the LCD strip
+#[cfg(feature = "image")]
+fn strip_pattern(width: u32, height: u32) -> DynamicImage {
+    let width = width.max(1);
+    let image = RgbImage::from_fn(width, height, |x, _| hue_to_rgb(x * 360 / width));
+
+    DynamicImage::ImageRgb8(image)
+}
+
+#[cfg(feature = "image")]
+fn hue_to_rgb(hue: u32) -> Rgb<u8> {
+    let sector = (hue % 360) / 60;
+    let offset = ((hue % 60) * 255 / 60) as u8;
+
+    match sector {
+        0 => Rgb([255, offset, 0]),
+        1 => Rgb([255 - offset, 255, 0]),
+        2 => Rgb([0, 255, offset]),
+        3 => Rgb([0, 255 - offset, 255]),
+        4 => Rgb([offset, 0, 255]),
+        _ => Rgb([255, 0, 255 - offset]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "image")]
+    use image::GenericImageView;
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_pattern_matches_key_size() {
+        for kind in Kind::ALL {
+            let (width, height) = kind.key_image_format().size;
+            let pattern = test_pattern(kind, 0);
+            assert_eq!(pattern.dimensions(), (width as u32, height as u32));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_pattern_is_unique_per_key() {
+        let kind = Kind::Akp153;
+        let center = |key| test_pattern(kind, key).get_pixel(40, 40);
+
+        for key in 1..kind.display_key_count() {
+            assert_ne!(center(0), center(key));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_strip_pattern() {
+        let (width, height) = Kind::Akp153.lcd_strip_size().unwrap();
+        let pattern = strip_pattern(width as u32, height as u32);
+
+        assert_eq!(pattern.dimensions(), (width as u32, height as u32));
+        assert_ne!(
+            pattern.get_pixel(0, 0),
+            pattern.get_pixel(width as u32 / 2, 0)
+        );
+    }
+
+    #[test]
+    fn test_report_status() {
+        let mut report = SelfTestReport {
+            kind: Kind::Akp03,
+            firmware_version: None,
+            steps: vec![],
+        };
+
+        report.run("ok", || Ok(()));
+        report.skip("skipped");
+        assert!(report.is_passed());
+
+        report.run("failed", || Err(AjazzError::NoAck));
+        assert!(!report.is_passed());
+        assert_eq!(report.failed_steps().count(), 1);
+    }
+}
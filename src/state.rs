@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+
+use crate::device::Ajazz;
+#[cfg(feature = "image")]
+use crate::formats::ImageMode;
+#[cfg(feature = "image")]
+use crate::images::revert_orientation;
+use crate::locks::{read, write};
+use crate::protocol::codes;
+use crate::AjazzError;
+
+/// Snapshot of what the deck shows, see [Ajazz::export_state].
+///
+/// Images are kept encoded in the format of the device kind, so restoring them
+/// doesn't need any rendering
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeckState {
+    /// Encoded images of the keys, keys without image are blank
+    pub keys: BTreeMap<u8, Vec<u8>>,
+    /// Brightness of the device, if it was set
+    pub brightness: Option<u8>,
+    /// Encoded boot logo, if it was set
+    pub logo: Option<Vec<u8>>,
+}
+
+/// What was sent to the device, kept to export it as [DeckState]
+#[derive(Default)]
+pub(crate) struct ShownState {
+    keys: BTreeMap<u8, Arc<[u8]>>,
+    brightness: Option<u8>,
+    logo: Option<Arc<[u8]>>,
+}
+
+impl Ajazz {
+    /// Returns snapshot of images, brightness and logo sent to the device by this instance.
+    /// Images waiting for the flush are not included
+    pub fn export_state(&self) -> DeckState {
+        let shown = read(&self.shown);
+        DeckState {
+            keys: shown
+                .keys
+                .iter()
+                .map(|(key, image_data)| (*key, image_data.to_vec()))
+                .collect(),
+            brightness: shown.brightness,
+            logo: shown.logo.as_deref().map(<[u8]>::to_vec),
+        }
+    }
+
+    /// Returns brightness last set through this instance, or `None` if it wasn't set.
+    ///
+    /// Devices don't report their settings, so brightness set by another application,
+    /// or persisted from an earlier session, is unknown. Set brightness after connecting
+    /// to keep the device and the application in sync
+    pub fn get_brightness(&self) -> Option<u8> {
+        read(&self.shown).brightness
+    }
+
+    /// Restores snapshot made by [Ajazz::export_state]. Keys without image in the snapshot
+    /// are cleared, images are flushed at once
+    pub fn apply_state(&self, state: &DeckState) -> Result<(), AjazzError> {
+        self.initialize()?;
+
+        if let Some(brightness) = state.brightness {
+            self.write_brightness(brightness)?;
+        }
+
+        if let Some(logo) = &state.logo {
+            self.set_logo_image_data(logo)?;
+        }
+
+        let blank: Vec<u8> = (0..self.display_key_count())
+            .filter(|key| !state.keys.contains_key(key))
+            .collect();
+        self.clear_button_images(&blank)?;
+
+        for (key, image_data) in &state.keys {
+            self.set_button_image_data(*key, image_data.as_slice())?;
+        }
+        self.flush()
+    }
+
+    /// Returns image the key shows decoded and upright, or `None` if it wasn't set
+    /// by this instance. Useful for drawing over it, e.g. with [Overlay]
+    ///
+    /// [Overlay]: crate::Overlay
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn key_image(&self, key: u8) -> Result<Option<DynamicImage>, AjazzError> {
+        let Some(image_data) = self.shown_key_image(key) else {
+            return Ok(None);
+        };
+
+        let format = self.key_image_format();
+        if format.mode != ImageMode::JPEG {
+            return Err(AjazzError::UnsupportedOperation);
+        }
+
+        let image =
+            image::load_from_memory_with_format(&image_data, image::ImageFormat::Jpeg)?;
+        Ok(Some(revert_orientation(format, image)))
+    }
+
+    /// Returns image the key shows, if it was set by this instance
+    #[cfg(feature = "image")]
+    pub(crate) fn shown_key_image(&self, key: u8) -> Option<Arc<[u8]>> {
+        read(&self.shown).keys.get(&key).cloned()
+    }
+
+    pub(crate) fn record_key_image(&self, key: u8, image_data: &Arc<[u8]>) {
+        write(&self.shown).keys.insert(key, image_data.clone());
+    }
+
+    pub(crate) fn record_cleared_key(&self, key: u8) {
+        let mut shown = write(&self.shown);
+        if key == codes::CMD_CLEAR_ALL {
+            shown.keys.clear();
+        } else {
+            shown.keys.remove(&key);
+        }
+    }
+
+    pub(crate) fn record_brightness(&self, percent: u8) {
+        write(&self.shown).brightness = Some(percent);
+    }
+
+    pub(crate) fn record_logo(&self, image_data: Arc<[u8]>) {
+        write(&self.shown).logo = Some(image_data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayTransport;
+    use crate::Kind;
+
+    #[test]
+    fn test_export_and_apply_state() {
+        let device = Ajazz::from_transport(Kind::Akp03, ReplayTransport::default());
+        device.set_brightness(40).unwrap();
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        device.set_button_image_data(3, [0xBB; 10]).unwrap();
+        device.flush().unwrap();
+        device.clear_button_image(3).unwrap();
+
+        let state = device.export_state();
+        assert_eq!(device.get_brightness(), Some(40));
+        assert_eq!(state.brightness, Some(40));
+        assert_eq!(state.keys, BTreeMap::from([(0, vec![0xAA; 10])]));
+        assert_eq!(state.logo, None);
+
+        let replay = ReplayTransport::default();
+        let restored = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        restored.apply_state(&state).unwrap();
+        assert_eq!(restored.export_state(), state);
+
+        let written = replay.written();
+        let commands: Vec<&[u8]> = written.iter().map(|p| &p[6..9]).collect();
+        assert_eq!(commands.iter().filter(|c| **c == b"CLE").count(), 5);
+        assert!(written.iter().any(|p| p[1..11] == [0xAA; 10]));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_key_image_is_upright() {
+        let device = Ajazz::from_transport(Kind::Akp153, ReplayTransport::default());
+        assert!(device.key_image(0).unwrap().is_none());
+
+        let mut image = image::RgbImage::new(85, 85);
+        for y in 0..20 {
+            for x in 0..20 {
+                image.put_pixel(x, y, image::Rgb([255, 255, 255]));
+            }
+        }
+        device
+            .set_button_image(0, DynamicImage::ImageRgb8(image))
+            .unwrap();
+        device.flush().unwrap();
+
+        let shown = device.key_image(0).unwrap().unwrap().into_rgb8();
+        assert_eq!(shown.dimensions(), (85, 85));
+        assert!(shown.get_pixel(5, 5).0[0] > 200);
+        assert!(shown.get_pixel(80, 80).0[0] < 50);
+    }
+}
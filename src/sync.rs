@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use hidapi::HidApi;
+#[cfg(feature = "image")]
+use image::DynamicImage;
+
+use crate::device_lock::DeviceLock;
+use crate::hid::open_error;
+#[cfg(feature = "image")]
+use crate::images::convert_image_with_format;
+use crate::protocol::{
+    codes, is_command_response, key_image_packets, parse_input, AjazzRequestBuilder,
+};
+use crate::transport::{write_whole_report, Transport};
+use crate::{AjazzError, AjazzInput, Kind};
+
+/// Ajazz device interface for single-threaded use.
+///
+/// Methods take `&mut self`, so the device needs no locks: nothing can fail with
+/// a poisoned lock, and nothing is paid for synchronization. Packets are built by the
+/// same protocol code as [Ajazz](crate::Ajazz) uses, but only the basic commands are
+/// available. Use [Ajazz](crate::Ajazz) to share the device between threads, or for
+/// readers, transactions and other features built on top of it
+pub struct AjazzMut {
+    kind: Kind,
+    hid: Box<dyn Transport>,
+    initialized: bool,
+    /// Images waiting for the flush
+    image_cache: BTreeMap<u8, Vec<u8>>,
+    /// Images were sent, but the flush command that shows them wasn't
+    unflushed: bool,
+    /// Writes of partially accepted reports, see [AjazzMut::set_write_attempts]
+    write_attempts: u8,
+    /// Cross-process lock of the device, held while it's open
+    device_lock: Option<DeviceLock>,
+}
+
+impl AjazzMut {
+    /// Attempts to connect to the device
+    pub fn connect(hidapi: &HidApi, kind: Kind, serial: &str) -> Result<AjazzMut, AjazzError> {
+        if !kind.is_enabled() {
+            return Err(AjazzError::UnsupportedOperation);
+        }
+
+        let device_lock = DeviceLock::acquire(serial)?;
+        let device = hidapi
+            .open_serial(kind.vendor_id(), kind.product_id(), serial)
+            .map_err(open_error)?;
+
+        let mut device = AjazzMut::from_transport(kind, device);
+        device.device_lock = device_lock;
+
+        Ok(device)
+    }
+
+    /// Creates device interface on top of a custom [Transport]
+    pub fn from_transport(kind: Kind, transport: impl Transport + 'static) -> AjazzMut {
+        AjazzMut {
+            kind,
+            hid: Box::new(transport),
+            initialized: false,
+            image_cache: BTreeMap::new(),
+            unflushed: false,
+            write_attempts: 1,
+            device_lock: None,
+        }
+    }
+
+    /// Returns kind of the Ajazz device
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// Sets how many times a report is written while the device accepts only a part of it,
+    /// see [Ajazz::set_write_attempts](crate::Ajazz::set_write_attempts)
+    pub fn set_write_attempts(&mut self, attempts: u8) {
+        self.write_attempts = attempts.max(1);
+    }
+
+    /// Reads input from the device.
+    /// If timeout is not specified, behavior depends on the blocking mode of the connection
+    pub fn read_input(&mut self, timeout: Option<Duration>) -> Result<AjazzInput, AjazzError> {
+        self.initialize()?;
+
+        let mut buf = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        let read = match timeout {
+            Some(timeout) => self
+                .hid
+                .read_timeout(buf.as_mut_slice(), timeout.as_millis() as i32),
+            None => self.hid.read(buf.as_mut_slice()),
+        }?;
+        buf.truncate(read);
+
+        // Responses to commands are not input
+        if is_command_response(&buf) {
+            return Ok(AjazzInput::NoData);
+        }
+
+        match parse_input(self.kind, &buf)? {
+            AjazzInput::ButtonStateChange(mut buttons) => {
+                buttons.resize(self.kind.key_count() as usize, false);
+                Ok(AjazzInput::ButtonStateChange(buttons))
+            }
+            input => Ok(input),
+        }
+    }
+
+    /// Sets brightness of the device, value range is 0 - 100
+    pub fn set_brightness(&mut self, percent: u8) -> Result<(), AjazzError> {
+        self.initialize()?;
+        self.write_report(&self.kind.brightness_packet(percent))
+    }
+
+    /// Sleeps the device
+    pub fn sleep(&mut self) -> Result<(), AjazzError> {
+        self.initialize()?;
+        self.write_report(&self.kind.sleep_packet())
+    }
+
+    /// Wakes the device up after [sleep](AjazzMut::sleep)
+    pub fn wake(&mut self) -> Result<(), AjazzError> {
+        self.initialize()?;
+        self.write_report(&self.kind.initialize_packet())
+    }
+
+    /// Sets button's image to blank, changes must be flushed with `.flush()` before
+    /// they will appear on the device!
+    pub fn clear_button_image(&mut self, key: u8) -> Result<(), AjazzError> {
+        self.initialize()?;
+        self.write_report(&self.kind.clear_button_image_packet(key))
+    }
+
+    /// Sets specified button's image from data encoded in [Kind::key_image_format],
+    /// changes must be flushed with `.flush()` before they will appear on the device!
+    pub fn set_button_image_data(
+        &mut self,
+        key: u8,
+        image_data: impl Into<Vec<u8>>,
+    ) -> Result<(), AjazzError> {
+        self.kind
+            .check_display_key(key, self.kind.display_key_count())?;
+
+        self.image_cache.insert(key, image_data.into());
+        Ok(())
+    }
+
+    /// Sets specified button's image, changes must be flushed with `.flush()` before
+    /// they will appear on the device!
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn set_button_image(
+        &mut self,
+        key: u8,
+        image: DynamicImage,
+    ) -> Result<(), AjazzError> {
+        let image_data = convert_image_with_format(self.kind.key_image_format(), image)?;
+        self.set_button_image_data(key, image_data)
+    }
+
+    /// Flushes the button's image to the device, sending the same packets as
+    /// [flush_packets](crate::flush_packets). Images not sent because of an error
+    /// stay in the cache for the next flush
+    pub fn flush(&mut self) -> Result<(), AjazzError> {
+        self.initialize()?;
+        if self.image_cache.is_empty() && !self.unflushed {
+            return Ok(());
+        }
+
+        let mut images = std::mem::take(&mut self.image_cache);
+        while let Some((key, image_data)) = images.pop_first() {
+            if let Err(e) = self.write_key_image(key, &image_data) {
+                images.insert(key, image_data);
+                self.image_cache = images;
+                return Err(e);
+            }
+
+            // Sent images wait for the flush command, even if it fails
+            self.unflushed = true;
+        }
+
+        self.write_report(&self.kind.flush_packet())?;
+        self.unflushed = false;
+        Ok(())
+    }
+
+    fn write_key_image(&self, key: u8, image_data: &[u8]) -> Result<(), AjazzError> {
+        for packet in key_image_packets(self.kind, key, image_data)? {
+            self.write_report(&packet)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_report(&self, data: &[u8]) -> Result<(), AjazzError> {
+        write_whole_report(self.hid.as_ref(), data, self.write_attempts, |_| {})
+    }
+
+    fn initialize(&mut self) -> Result<(), AjazzError> {
+        if self.initialized {
+            return Ok(());
+        }
+
+        match self.write_report(&self.kind.initialize_packet()) {
+            Err(AjazzError::HidError(e)) => return Err(AjazzError::InitializationFailed(e)),
+            result => result?,
+        }
+        self.initialized = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use hidapi::HidResult;
+
+    use super::*;
+    use crate::flush_packets;
+    use crate::replay::ReplayTransport;
+
+    #[test]
+    fn test_flush_matches_protocol_packets() {
+        let replay = ReplayTransport::default();
+        let mut device = AjazzMut::from_transport(Kind::Akp03, replay.clone());
+
+        device.set_button_image_data(2, vec![0xBB; 1500]).unwrap();
+        device.set_button_image_data(0, vec![0xAA; 10]).unwrap();
+        assert!(matches!(
+            device.set_button_image_data(6, vec![]),
+            Err(AjazzError::NoScreenOnKey(6))
+        ));
+        assert!(matches!(
+            device.set_button_image_data(9, vec![]),
+            Err(AjazzError::InvalidKeyIndex(9))
+        ));
+        device.flush().unwrap();
+
+        let expected =
+            flush_packets(Kind::Akp03, &[(0, &[0xAA; 10]), (2, &[0xBB; 1500])]).unwrap();
+        let written = replay.written();
+        assert_eq!(&written[0][6..9], b"DIS");
+        assert_eq!(written[1..], expected[..]);
+
+        device.flush().unwrap();
+        assert_eq!(replay.written().len(), written.len());
+    }
+
+    /// Transport that accepts only half of every report after the allowed writes
+    struct ShortWrites {
+        inner: ReplayTransport,
+        allowed: Arc<AtomicUsize>,
+    }
+
+    impl Transport for ShortWrites {
+        fn write(&self, data: &[u8]) -> HidResult<usize> {
+            match self.allowed.load(Ordering::SeqCst) {
+                0 => Ok(data.len() / 2),
+                allowed => {
+                    self.allowed.store(allowed - 1, Ordering::SeqCst);
+                    self.inner.write(data)
+                }
+            }
+        }
+
+        fn read(&self, buf: &mut [u8]) -> HidResult<usize> {
+            self.inner.read(buf)
+        }
+
+        fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> HidResult<usize> {
+            self.inner.read_timeout(buf, timeout)
+        }
+
+        fn set_blocking_mode(&self, blocking: bool) -> HidResult<()> {
+            self.inner.set_blocking_mode(blocking)
+        }
+
+        fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+            self.inner.get_feature_report(buf)
+        }
+
+        fn get_manufacturer_string(&self) -> HidResult<Option<String>> {
+            self.inner.get_manufacturer_string()
+        }
+
+        fn get_product_string(&self) -> HidResult<Option<String>> {
+            self.inner.get_product_string()
+        }
+
+        fn get_serial_number_string(&self) -> HidResult<Option<String>> {
+            self.inner.get_serial_number_string()
+        }
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_unsent_images() {
+        let replay = ReplayTransport::default();
+        // Initialization and the whole first image get through
+        let allowed = Arc::new(AtomicUsize::new(3));
+        let transport = ShortWrites {
+            inner: replay.clone(),
+            allowed: allowed.clone(),
+        };
+        let mut device = AjazzMut::from_transport(Kind::Akp03, transport);
+
+        device.set_button_image_data(0, vec![0xAA; 10]).unwrap();
+        device.set_button_image_data(2, vec![0xBB; 1500]).unwrap();
+        assert!(matches!(
+            device.flush(),
+            Err(AjazzError::PartialWrite { .. })
+        ));
+        assert_eq!(device.image_cache.keys().collect::<Vec<_>>(), [&2]);
+
+        allowed.store(usize::MAX, Ordering::SeqCst);
+        device.flush().unwrap();
+        let expected = flush_packets(Kind::Akp03, &[(2, &[0xBB; 1500])]).unwrap();
+        assert_eq!(replay.written()[3..], expected[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "akp03")]
+    fn test_read_input() {
+        let replay = ReplayTransport::default();
+        let mut report = vec![0u8; codes::INPUT_PACKET_LENGTH];
+        report[codes::OFFSET_DATA_LENGTH] = 0x41;
+        report[codes::OFFSET_ACTION_CODE] = 0x01;
+        replay.push_read(&report);
+        let mut device = AjazzMut::from_transport(Kind::Akp03, replay);
+
+        let input = device.read_input(Some(Duration::from_millis(1))).unwrap();
+        let AjazzInput::ButtonStateChange(buttons) = input else {
+            panic!("Expected button state change");
+        };
+        assert_eq!(buttons.len(), 9);
+        assert!(buttons[0]);
+        assert!(matches!(
+            device.read_input(Some(Duration::from_millis(1))).unwrap(),
+            AjazzInput::NoData
+        ));
+    }
+}
@@ -0,0 +1,373 @@
+use ab_glyph::{point, Font, PxScale, ScaleFont};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::widgets::Rect;
+use crate::{AjazzError, ImageFormat};
+
+pub use ab_glyph::{FontArc, FontRef, InvalidFont};
+
+/// Part of the key size left empty around icons on every side
+const ICON_PADDING: f32 = 0.15;
+
+/// Renders the glyph of an icon font (Material Icons, emoji fonts and so on) into a key-sized
+/// image. The glyph is scaled to fit the key with padding and centered by its outline,
+/// so icons with uneven side bearings stay in the middle
+///
+/// ```no_run
+/// # fn example(device: &ajazz_sdk::Ajazz) -> Result<(), ajazz_sdk::AjazzError> {
+/// use ajazz_sdk::text::{render_icon, FontRef};
+///
+/// let data = std::fs::read("MaterialIcons-Regular.ttf").unwrap();
+/// let font = FontRef::try_from_slice(&data).unwrap();
+///
+/// let white = image::Rgb([255, 255, 255]);
+/// let icon = render_icon('\u{e037}', &font, white, device.key_image_format())?;
+/// device.set_button_image(0, icon)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn render_icon(
+    codepoint: char,
+    font: &impl Font,
+    color: Rgb<u8>,
+    format: ImageFormat,
+) -> Result<DynamicImage, AjazzError> {
+    let (w, h) = (format.size.0 as f32, format.size.1 as f32);
+    let area = (
+        w * (1.0 - 2.0 * ICON_PADDING),
+        h * (1.0 - 2.0 * ICON_PADDING),
+    );
+
+    let id = font.glyph_id(codepoint);
+    // Glyph 0 is the placeholder shown for characters missing in the font
+    if id.0 == 0 {
+        return Err(AjazzError::MissingGlyph(codepoint));
+    }
+
+    let unscaled = font
+        .outline_glyph(id.with_scale(PxScale::from(h)))
+        .ok_or(AjazzError::MissingGlyph(codepoint))?;
+    let bounds = unscaled.px_bounds();
+    let scale = fit_scale(h, (bounds.width(), bounds.height()), area);
+
+    let glyph = font
+        .outline_glyph(id.with_scale(PxScale::from(scale)))
+        .ok_or(AjazzError::MissingGlyph(codepoint))?;
+    let bounds = glyph.px_bounds();
+    let left = ((w - bounds.width()) / 2.0).round() as i64;
+    let top = ((h - bounds.height()) / 2.0).round() as i64;
+
+    let mut image = RgbImage::new(format.size.0 as u32, format.size.1 as u32);
+    draw_coverage(&mut image, color, |draw| {
+        glyph.draw(|x, y, c| draw(left + i64::from(x), top + i64::from(y), c))
+    });
+
+    Ok(DynamicImage::ImageRgb8(image))
+}
+
+/// Renders single line of text into an image fitting the width, at most `height` pixels
+/// high. Text that is too wide is scaled down
+pub fn render_text(
+    text: &str,
+    font: &impl Font,
+    color: Rgb<u8>,
+    width: u32,
+    height: u32,
+) -> DynamicImage {
+    let measured = measure_line(text, font, PxScale::from(height as f32));
+    let scale = fit_scale(
+        height as f32,
+        (measured, height as f32),
+        (width as f32, height as f32),
+    );
+    let scale = PxScale::from(scale);
+    let scaled = font.as_scaled(scale);
+
+    let line_width = measure_line(text, font, scale);
+    let mut caret = point(
+        ((width as f32 - line_width) / 2.0).max(0.0),
+        scaled.ascent(),
+    );
+    let mut image = RgbImage::new(width, height);
+    let mut previous = None;
+    for character in text.chars() {
+        let id = scaled.glyph_id(character);
+        if let Some(previous) = previous {
+            caret.x += scaled.kern(previous, id);
+        }
+        previous = Some(id);
+
+        let glyph = id.with_scale_and_position(scale, caret);
+        caret.x += scaled.h_advance(id);
+        let Some(glyph) = font.outline_glyph(glyph) else {
+            continue;
+        };
+
+        let bounds = glyph.px_bounds();
+        let (left, top) = (bounds.min.x as i64, bounds.min.y as i64);
+        draw_coverage(&mut image, color, |draw| {
+            glyph.draw(|x, y, c| draw(left + i64::from(x), top + i64::from(y), c))
+        });
+    }
+
+    DynamicImage::ImageRgb8(image)
+}
+
+/// Content of a key, laid out for the key size by [KeyFace::render].
+///
+/// With both icon and label, the icon is placed above the label. Badge is drawn
+/// as a circle in the top right corner, e.g. for unread counters
+///
+/// ```no_run
+/// # fn example(device: &ajazz_sdk::Ajazz, font: &ajazz_sdk::text::FontArc, icon: image::DynamicImage) -> Result<(), ajazz_sdk::AjazzError> {
+/// use ajazz_sdk::text::KeyFace;
+///
+/// let face = KeyFace {
+///     icon: Some(icon),
+///     label: Some("Mail".to_string()),
+///     badge: Some("3".to_string()),
+///     ..Default::default()
+/// };
+/// device.set_button_image(0, face.render(device.key_image_format(), font))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeyFace {
+    /// Image scaled to fit the icon area, e.g. made with [render_icon]
+    pub icon: Option<DynamicImage>,
+    /// Single line of text at the bottom, or in the middle without icon
+    pub label: Option<String>,
+    /// Short text in the top right corner
+    pub badge: Option<String>,
+    /// Color of the whole key
+    pub background: Rgb<u8>,
+    /// Color of the label
+    pub label_color: Rgb<u8>,
+    /// Color of the badge circle, badge text is white
+    pub badge_color: Rgb<u8>,
+}
+
+impl Default for KeyFace {
+    fn default() -> Self {
+        Self {
+            icon: None,
+            label: None,
+            badge: None,
+            background: Rgb([0, 0, 0]),
+            label_color: Rgb([255, 255, 255]),
+            badge_color: Rgb([220, 30, 30]),
+        }
+    }
+}
+
+/// Areas of the key face elements
+#[derive(Debug, PartialEq)]
+struct FaceLayout {
+    icon: Option<Rect>,
+    label: Option<Rect>,
+    badge: Rect,
+}
+
+impl FaceLayout {
+    fn new(w: u32, h: u32, has_icon: bool, has_label: bool) -> FaceLayout {
+        let padding = (w.min(h) as f32 * 0.08).round() as u32;
+        let label_height = h * 22 / 100;
+        let badge_size = w.min(h) * 35 / 100;
+        let (icon, label) = match (has_icon, has_label) {
+            (true, true) => {
+                let icon_height = h - label_height - padding * 3;
+                (
+                    Some(Rect::new(padding, padding, w - padding * 2, icon_height)),
+                    Some(Rect::new(
+                        padding,
+                        h - padding - label_height,
+                        w - padding * 2,
+                        label_height,
+                    )),
+                )
+            }
+            (true, false) => (
+                Some(Rect::new(
+                    padding,
+                    padding,
+                    w - padding * 2,
+                    h - padding * 2,
+                )),
+                None,
+            ),
+            (false, true) => (
+                None,
+                Some(Rect::new(
+                    padding,
+                    (h - label_height) / 2,
+                    w - padding * 2,
+                    label_height,
+                )),
+            ),
+            (false, false) => (None, None),
+        };
+
+        FaceLayout {
+            icon,
+            label,
+            badge: Rect::new(w - badge_size, 0, badge_size, badge_size),
+        }
+    }
+}
+
+impl KeyFace {
+    /// Renders the face at the size of the format, ready for
+    /// [Ajazz::set_button_image](crate::Ajazz::set_button_image)
+    pub fn render(&self, format: ImageFormat, font: &impl Font) -> DynamicImage {
+        let (w, h) = (format.size.0 as u32, format.size.1 as u32);
+        let layout = FaceLayout::new(w, h, self.icon.is_some(), self.label.is_some());
+        let mut image = RgbImage::from_pixel(w, h, self.background);
+
+        if let (Some(icon), Some(rect)) = (&self.icon, layout.icon) {
+            let icon = icon
+                .resize(rect.w, rect.h, FilterType::Triangle)
+                .into_rgba8();
+            let x = rect.x + (rect.w - icon.width()) / 2;
+            let y = rect.y + (rect.h - icon.height()) / 2;
+            imageops::overlay(
+                &mut image,
+                &DynamicImage::ImageRgba8(icon).into_rgb8(),
+                x.into(),
+                y.into(),
+            );
+        }
+
+        if let (Some(label), Some(rect)) = (&self.label, layout.label) {
+            let text = render_text(label, font, self.label_color, rect.w, rect.h).into_rgb8();
+            blend_text(&mut image, &text, rect.x, rect.y, self.label_color);
+        }
+
+        if let Some(badge) = &self.badge {
+            let rect = layout.badge;
+            let radius = rect.w as f32 / 2.0;
+            let center = (rect.x as f32 + radius, rect.y as f32 + radius);
+            for y in rect.y..rect.y + rect.h {
+                for x in rect.x..rect.x + rect.w {
+                    let (dx, dy) = (x as f32 + 0.5 - center.0, y as f32 + 0.5 - center.1);
+                    if dx * dx + dy * dy <= radius * radius {
+                        image.put_pixel(x, y, self.badge_color);
+                    }
+                }
+            }
+
+            let inner = (rect.w as f32 * 0.7) as u32;
+            let offset = (rect.w - inner) / 2;
+            let white = Rgb([255, 255, 255]);
+            let text = render_text(badge, font, white, inner, inner).into_rgb8();
+            blend_text(&mut image, &text, rect.x + offset, rect.y + offset, white);
+        }
+
+        DynamicImage::ImageRgb8(image)
+    }
+}
+
+/// Draws text rendered on black onto the image, using brightness of the text as coverage
+fn blend_text(image: &mut RgbImage, text: &RgbImage, x: u32, y: u32, color: Rgb<u8>) {
+    let brightest = color.0.iter().copied().max().unwrap_or(0).max(1);
+    draw_coverage(image, color, |draw| {
+        for (tx, ty, pixel) in text.enumerate_pixels() {
+            let coverage =
+                f32::from(pixel.0.iter().copied().max().unwrap_or(0)) / f32::from(brightest);
+            if coverage > 0.0 {
+                draw(i64::from(x + tx), i64::from(y + ty), coverage.min(1.0));
+            }
+        }
+    });
+}
+
+/// Returns width of the line of text at the scale
+fn measure_line(text: &str, font: &impl Font, scale: PxScale) -> f32 {
+    let scaled = font.as_scaled(scale);
+    let mut previous = None;
+    let mut width = 0.0;
+    for character in text.chars() {
+        let id = scaled.glyph_id(character);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Returns scale making content measured at `scale` fit into the area, keeping aspect ratio
+fn fit_scale(scale: f32, measured: (f32, f32), area: (f32, f32)) -> f32 {
+    if measured.0 <= 0.0 || measured.1 <= 0.0 {
+        return scale;
+    }
+
+    scale * (area.0 / measured.0).min(area.1 / measured.1)
+}
+
+/// Blends color into the image with coverage reported by the drawing function
+fn draw_coverage(
+    image: &mut RgbImage,
+    color: Rgb<u8>,
+    draw: impl FnOnce(&mut dyn FnMut(i64, i64, f32)),
+) {
+    let (w, h) = image.dimensions();
+    draw(&mut |x, y, coverage| {
+        if x < 0 || y < 0 || x >= i64::from(w) || y >= i64::from(h) {
+            return;
+        }
+
+        let pixel = image.get_pixel_mut(x as u32, y as u32);
+        for (channel, target) in pixel.0.iter_mut().zip(color.0) {
+            let blended =
+                f32::from(*channel) + (f32::from(target) - f32::from(*channel)) * coverage;
+            *channel = blended.round() as u8;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_scale_keeps_aspect_ratio() {
+        assert!((fit_scale(100.0, (50.0, 100.0), (60.0, 60.0)) - 60.0).abs() < 0.01);
+        assert!((fit_scale(100.0, (200.0, 50.0), (60.0, 60.0)) - 30.0).abs() < 0.01);
+        assert_eq!(fit_scale(100.0, (0.0, 0.0), (60.0, 60.0)), 100.0);
+    }
+
+    #[test]
+    fn test_face_layout() {
+        let layout = FaceLayout::new(100, 100, true, true);
+        let icon = layout.icon.unwrap();
+        let label = layout.label.unwrap();
+        assert!(icon.y + icon.h <= label.y);
+        assert!(label.y + label.h <= 100);
+        assert_eq!(layout.badge, Rect::new(65, 0, 35, 35));
+
+        let layout = FaceLayout::new(60, 60, false, true);
+        let label = layout.label.unwrap();
+        assert_eq!(layout.icon, None);
+        assert!((59..=60).contains(&(label.y * 2 + label.h)));
+
+        let layout = FaceLayout::new(60, 60, true, false);
+        assert_eq!(layout.icon, Some(Rect::new(5, 5, 50, 50)));
+        assert_eq!(layout.label, None);
+    }
+
+    #[test]
+    fn test_coverage_is_blended_and_clipped() {
+        let mut image = RgbImage::new(2, 1);
+        draw_coverage(&mut image, Rgb([200, 100, 0]), |draw| {
+            draw(0, 0, 0.5);
+            draw(1, 0, 1.0);
+            draw(5, 5, 1.0);
+            draw(-1, 0, 1.0);
+        });
+        assert_eq!(image.get_pixel(0, 0), &Rgb([100, 50, 0]));
+        assert_eq!(image.get_pixel(1, 0), &Rgb([200, 100, 0]));
+    }
+}
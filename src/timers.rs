@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+use crate::Event;
+
+struct Timer {
+    id: u32,
+    interval: Duration,
+    next: Instant,
+}
+
+/// Periodic timers of the reader, reported as [Event::Tick] among device events
+#[derive(Default)]
+pub(crate) struct Timers {
+    timers: Vec<Timer>,
+    next_id: u32,
+}
+
+impl Timers {
+    /// Adds timer firing every interval starting from now, returns its id
+    pub(crate) fn add(&mut self, interval: Duration, now: Instant) -> u32 {
+        let interval = interval.max(Duration::from_millis(1));
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.timers.push(Timer {
+            id,
+            interval,
+            next: now + interval,
+        });
+        id
+    }
+
+    /// Removes the timer, returns false if there was no such timer
+    pub(crate) fn remove(&mut self, id: u32) -> bool {
+        let count = self.timers.len();
+        self.timers.retain(|timer| timer.id != id);
+        self.timers.len() != count
+    }
+
+    /// Returns time left until the closest timer fires
+    pub(crate) fn until_next(&self, now: Instant) -> Option<Duration> {
+        self.timers
+            .iter()
+            .map(|timer| timer.next.saturating_duration_since(now))
+            .min()
+    }
+
+    /// Returns ticks of timers that fired. Ticks missed while nobody was reading
+    /// are merged into one, so slow loops don't get bursts of them
+    pub(crate) fn poll(&mut self, now: Instant) -> Vec<Event> {
+        let mut ticks = vec![];
+        for timer in self.timers.iter_mut().filter(|timer| timer.next <= now) {
+            ticks.push(Event::Tick(timer.id));
+            while timer.next <= now {
+                timer.next += timer.interval;
+            }
+        }
+        ticks
+    }
+}
+
+/// Returns the shorter of the timeouts, where `None` waits forever
+pub(crate) fn shortest_timeout(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    a.into_iter().chain(b).min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timers_fire_periodically() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        let fast = timers.add(Duration::from_millis(10), start);
+        let slow = timers.add(Duration::from_millis(25), start);
+
+        assert!(timers.poll(start).is_empty());
+        assert_eq!(timers.until_next(start), Some(Duration::from_millis(10)));
+
+        let later = start + Duration::from_millis(20);
+        assert_eq!(timers.poll(later), [Event::Tick(fast)]);
+        assert_eq!(timers.until_next(later), Some(Duration::from_millis(5)));
+
+        let later = start + Duration::from_millis(55);
+        assert_eq!(timers.poll(later), [Event::Tick(fast), Event::Tick(slow)]);
+
+        assert!(timers.remove(fast));
+        assert!(!timers.remove(fast));
+        assert_eq!(timers.until_next(later), Some(Duration::from_millis(20)));
+    }
+}
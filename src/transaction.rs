@@ -0,0 +1,239 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+
+use crate::device::Ajazz;
+#[cfg(feature = "image")]
+use crate::images::convert_image_with_format;
+use crate::protocol::AjazzRequestBuilder;
+use crate::AjazzError;
+
+enum StagedChange {
+    Image(Arc<[u8]>),
+    Clear,
+}
+
+/// Set of key changes that are committed to the device at once, see [Ajazz::transaction]
+pub struct Transaction<'a> {
+    device: &'a Ajazz,
+    changes: Vec<(u8, StagedChange)>,
+}
+
+impl Transaction<'_> {
+    /// Stages button's image
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn set_button_image(
+        &mut self,
+        key: u8,
+        image: DynamicImage,
+    ) -> Result<(), AjazzError> {
+        self.validate_key(key)?;
+        let image_data = self.device.encode_key_image(image)?;
+        self.changes
+            .push((key, StagedChange::Image(image_data.into())));
+        Ok(())
+    }
+
+    /// Stages button's image from already encoded data
+    pub fn set_button_image_data(
+        &mut self,
+        key: u8,
+        image_data: impl Into<Arc<[u8]>>,
+    ) -> Result<(), AjazzError> {
+        self.validate_key(key)?;
+        self.changes
+            .push((key, StagedChange::Image(image_data.into())));
+        Ok(())
+    }
+
+    /// Stages blank image for the button
+    pub fn clear_button(&mut self, key: u8) -> Result<(), AjazzError> {
+        self.validate_key(key)?;
+        self.changes.push((key, StagedChange::Clear));
+        Ok(())
+    }
+
+    fn validate_key(&self, key: u8) -> Result<(), AjazzError> {
+        self.device.check_display_key(key)
+    }
+
+    /// Sends staged changes together with images waiting in the cache,
+    /// followed by a single flush command.
+    /// If the same key was changed several times, only the last change is sent
+    fn commit(self) -> Result<(), AjazzError> {
+        let kind = self.device.kind();
+
+        let cached = self.device.take_cached_images();
+        let mut changes: Vec<(u8, StagedChange)> = cached
+            .into_iter()
+            .map(|(key, image_data)| (key, StagedChange::Image(image_data)))
+            .chain(self.changes)
+            .collect();
+
+        // Layout can change after keys were staged, so keys aren't assumed to fit it
+        let mut seen = BTreeSet::new();
+        changes.reverse();
+        changes.retain(|(key, _)| seen.insert(*key));
+        changes.reverse();
+
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        for (key, change) in changes {
+            match change {
+                StagedChange::Image(image_data) => {
+                    self.device.write_key_image(key, &image_data)?;
+                }
+                // Clearing is committed by flush only on "v2" devices,
+                // so "v1" devices get a blank image instead
+                StagedChange::Clear if kind.is_v2_api() => {
+                    self.device
+                        .write_packet(&kind.clear_button_image_packet(key))?;
+                    self.device.record_cleared_key(key);
+                }
+                #[cfg(feature = "image")]
+                StagedChange::Clear => {
+                    let format = self.device.key_image_format();
+                    let (w, h) = format.size;
+                    let blank = convert_image_with_format(
+                        format,
+                        DynamicImage::new_rgb8(w as u32, h as u32),
+                    )?;
+                    self.device.write_key_image(key, &blank.into())?;
+                }
+                // Without image support the blank image can't be encoded,
+                // so the key is cleared right away
+                #[cfg(not(feature = "image"))]
+                StagedChange::Clear => {
+                    self.device
+                        .write_packet(&kind.clear_button_image_packet(key))?;
+                    self.device.record_cleared_key(key);
+                }
+            }
+        }
+
+        self.device.write_verified_packet(&kind.flush_packet())
+    }
+}
+
+impl Ajazz {
+    /// Stages key changes made in the closure and commits them with a single flush,
+    /// so the device never shows a half-updated layout. Images already waiting
+    /// in the cache are committed together with the transaction.
+    ///
+    /// If the closure returns an error, nothing is sent to the device.
+    ///
+    /// ```no_run
+    /// # fn example(device: &ajazz_sdk::Ajazz, icon: Vec<u8>) -> Result<(), ajazz_sdk::AjazzError> {
+    /// device.transaction(|tx| {
+    ///     tx.set_button_image_data(0, icon)?;
+    ///     tx.clear_button(1)
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn transaction<R>(
+        &self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<R, AjazzError>,
+    ) -> Result<R, AjazzError> {
+        self.initialize()?;
+
+        let mut transaction = Transaction {
+            device: self,
+            changes: vec![],
+        };
+
+        let result = f(&mut transaction)?;
+        transaction.commit()?;
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayTransport;
+    use crate::Kind;
+
+    fn commands(written: &[Vec<u8>]) -> Vec<&[u8]> {
+        written.iter().map(|p| &p[6..9]).collect()
+    }
+
+    #[test]
+    fn test_transaction_commits_with_single_flush() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+
+        device.set_button_image_data(0, [0xAA; 10]).unwrap();
+        device
+            .transaction(|tx| {
+                tx.set_button_image_data(1, [0xBB; 10])?;
+                tx.set_button_image_data(1, [0xCC; 10])?;
+                tx.clear_button(2)
+            })
+            .unwrap();
+
+        let written = replay.written();
+        let commands = commands(&written);
+        assert_eq!(commands.iter().filter(|c| **c == b"STP").count(), 1);
+        assert_eq!(commands.last(), Some(&b"STP".as_slice()));
+        assert_eq!(commands.iter().filter(|c| **c == b"BAT").count(), 2);
+        assert_eq!(commands.iter().filter(|c| **c == b"CLE").count(), 1);
+        assert!(written.iter().any(|p| p[1..11] == [0xCC; 10]));
+        assert!(!written.iter().any(|p| p[1..11] == [0xBB; 10]));
+
+        device.flush().unwrap();
+        assert_eq!(replay.written().len(), written.len());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_transaction_clears_v1_keys_with_blank_image() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp153, replay.clone());
+        device.transaction(|tx| tx.clear_button(3)).unwrap();
+
+        let written = replay.written();
+        let commands = commands(&written);
+        assert!(!commands.contains(&b"CLE".as_slice()));
+        assert_eq!(commands[1], b"BAT");
+        assert_eq!(commands.last(), Some(&b"STP".as_slice()));
+    }
+
+    #[test]
+    fn test_transaction_after_layout_override() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp153, replay.clone());
+
+        device.set_button_image_data(14, [0xAA; 10]).unwrap();
+        device.override_layout(2, 2, (85, 85)).unwrap();
+        assert_eq!(device.cache_len(), 0);
+        device
+            .transaction(|tx| tx.set_button_image_data(1, [0xBB; 10]))
+            .unwrap();
+
+        let written = replay.written();
+        assert_eq!(commands(&written).last(), Some(&b"STP".as_slice()));
+        assert!(written.iter().any(|p| p[1..11] == [0xBB; 10]));
+        assert!(!written.iter().any(|p| p[1..11] == [0xAA; 10]));
+    }
+
+    #[test]
+    fn test_failed_transaction_sends_nothing() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+
+        let result = device.transaction(|tx| {
+            tx.set_button_image_data(0, [0xAA; 10])?;
+            tx.clear_button(6)
+        });
+
+        assert!(matches!(result, Err(AjazzError::NoScreenOnKey(6))));
+        assert_eq!(commands(&replay.written()), [b"DIS"]);
+    }
+}
@@ -0,0 +1,93 @@
+use hidapi::{HidDevice, HidResult};
+
+use crate::AjazzError;
+
+/// Low level channel used by [Ajazz](crate::Ajazz) to exchange reports with the device.
+///
+/// Implemented for [HidDevice]. Custom implementations allow running the library
+/// against recorded sessions (see [replay](crate::replay)) or virtual devices.
+pub trait Transport: Send {
+    /// Writes an output report, first byte is the report ID
+    fn write(&self, data: &[u8]) -> HidResult<usize>;
+
+    /// Reads an input report, blocking until data arrives
+    fn read(&self, buf: &mut [u8]) -> HidResult<usize>;
+
+    /// Reads an input report, waiting at most `timeout` milliseconds.
+    /// Negative timeout waits forever
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> HidResult<usize>;
+
+    /// Switches between blocking and non-blocking reads
+    fn set_blocking_mode(&self, blocking: bool) -> HidResult<()>;
+
+    /// Reads a feature report, first byte of `buf` must contain the report ID
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize>;
+
+    /// Returns manufacturer string of the device
+    fn get_manufacturer_string(&self) -> HidResult<Option<String>>;
+
+    /// Returns product string of the device
+    fn get_product_string(&self) -> HidResult<Option<String>>;
+
+    /// Returns serial number of the device
+    fn get_serial_number_string(&self) -> HidResult<Option<String>>;
+}
+
+impl Transport for HidDevice {
+    fn write(&self, data: &[u8]) -> HidResult<usize> {
+        HidDevice::write(self, data)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> HidResult<usize> {
+        HidDevice::read(self, buf)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> HidResult<usize> {
+        HidDevice::read_timeout(self, buf, timeout)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> HidResult<()> {
+        HidDevice::set_blocking_mode(self, blocking)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+        HidDevice::get_feature_report(self, buf)
+    }
+
+    fn get_manufacturer_string(&self) -> HidResult<Option<String>> {
+        HidDevice::get_manufacturer_string(self)
+    }
+
+    fn get_product_string(&self) -> HidResult<Option<String>> {
+        HidDevice::get_product_string(self)
+    }
+
+    fn get_serial_number_string(&self) -> HidResult<Option<String>> {
+        HidDevice::get_serial_number_string(self)
+    }
+}
+
+/// Writes the report, writing it again while the transport accepts only a part of it,
+/// up to `attempts` times. `on_write` is called with the byte count of every write
+pub(crate) fn write_whole_report(
+    transport: &dyn Transport,
+    data: &[u8],
+    attempts: u8,
+    mut on_write: impl FnMut(usize),
+) -> Result<(), AjazzError> {
+    let mut written = 0;
+    for _ in 0..attempts.max(1) {
+        written = transport.write(data)?;
+        on_write(written);
+
+        // Some backends count padding up to the report length of the device
+        if written >= data.len() {
+            return Ok(());
+        }
+    }
+
+    Err(AjazzError::PartialWrite {
+        expected: data.len(),
+        written,
+    })
+}
@@ -0,0 +1,446 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+
+use crate::device::Ajazz;
+use crate::formats::{ImageFormat, ImageOptions};
+use crate::images::encode_image_into;
+use crate::locks::lock;
+use crate::{AjazzError, FramePump};
+
+/// Encoded frames kept for reuse, one is being written while the next one is encoded
+const SPARE_BUFFERS: usize = 2;
+
+/// Frame waiting for the conversion
+enum VideoFrame {
+    Image(DynamicImage),
+    Data(Vec<u8>),
+}
+
+#[derive(Default)]
+struct SinkState {
+    /// Latest pushed frame, not converted yet
+    pushed: Option<VideoFrame>,
+    /// Frame is being converted
+    converting: bool,
+    /// Latest converted frame, not written yet
+    encoded: Option<Vec<u8>>,
+    /// Buffers of written frames, reused for the next ones
+    spare: Vec<Vec<u8>>,
+    sent: u64,
+    dropped: u64,
+    /// Last error of the conversion or the write, returned by the next push
+    error: Option<AjazzError>,
+    stopped: bool,
+}
+
+impl SinkState {
+    fn take_buffer(&mut self) -> Vec<u8> {
+        self.spare.pop().unwrap_or_default()
+    }
+
+    fn return_buffer(&mut self, buffer: Vec<u8>) {
+        if self.spare.len() < SPARE_BUFFERS {
+            self.spare.push(buffer);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<SinkState>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, SinkState> {
+        lock(&self.state)
+    }
+
+    /// Blocks until the condition is false
+    fn wait_while<'a>(
+        &self,
+        state: MutexGuard<'a, SinkState>,
+        condition: impl FnMut(&mut SinkState) -> bool,
+    ) -> MutexGuard<'a, SinkState> {
+        self.changed
+            .wait_while(state, condition)
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Plays video on the LCD strip, e.g. 15 - 20 fps of a music visualizer on the AKP153.
+///
+/// The sink owns the device and sends frames on its own threads: one converts frames
+/// to the format of the strip, the other writes them to the device, so conversion of
+/// a frame overlaps with the write of the previous one. Frames are written at most
+/// at the target rate and only the latest frame is kept at every stage, so producers
+/// faster than the device drop frames instead of falling behind.
+///
+/// ```no_run
+/// # fn example(device: ajazz_sdk::Ajazz, frames: Vec<image::DynamicImage>) -> Result<(), ajazz_sdk::AjazzError> {
+/// let sink = ajazz_sdk::LcdVideoSink::new(device, 20)?;
+/// for frame in frames {
+///     sink.push_frame(frame)?;
+///     std::thread::sleep(std::time::Duration::from_millis(33));
+/// }
+/// let device = sink.finish();
+/// # Ok(())
+/// # }
+/// ```
+pub struct LcdVideoSink {
+    shared: Arc<Shared>,
+    converter: JoinHandle<()>,
+    writer: JoinHandle<Ajazz>,
+}
+
+impl LcdVideoSink {
+    /// Starts playing on the device at up to `fps` frames per second.
+    /// Fails with [AjazzError::UnsupportedOperation] if the device has no LCD strip
+    pub fn new(device: Ajazz, fps: u32) -> Result<LcdVideoSink, AjazzError> {
+        let kind = device.kind();
+        if kind.lcd_strip_size().is_none() {
+            return Err(AjazzError::UnsupportedOperation);
+        }
+
+        let shared = Arc::new(Shared::default());
+        let options = device.image_options();
+        let interval = Duration::from_secs(1) / fps.max(1);
+
+        let converter = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                convert_frames(&shared, |image, buffer| {
+                    encode_image_into(kind.logo_image_format(), image, options, buffer)
+                        .map_err(AjazzError::from)
+                });
+            })
+        };
+        let writer = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                write_frames(&shared, interval, |frame| {
+                    let result = device.set_logo_image_data(frame);
+                    // Frames aren't decoded back, so areas drawn by write_lcd start
+                    // on black again
+                    *lock(&device.lcd_frame) = None;
+                    result
+                });
+                device
+            })
+        };
+
+        Ok(LcdVideoSink {
+            shared,
+            converter,
+            writer,
+        })
+    }
+
+    /// Queues the image, scaled to the strip, replacing the frame that wasn't sent yet.
+    /// Returns error of the previous frame, if it failed
+    pub fn push_frame(&self, image: DynamicImage) -> Result<(), AjazzError> {
+        self.push(VideoFrame::Image(image))
+    }
+
+    /// Queues image data already encoded in [Kind::logo_image_format], skipping
+    /// the conversion. Returns error of the previous frame, if it failed
+    ///
+    /// [Kind::logo_image_format]: crate::Kind::logo_image_format
+    pub fn push_frame_data(&self, image_data: impl Into<Vec<u8>>) -> Result<(), AjazzError> {
+        self.push(VideoFrame::Data(image_data.into()))
+    }
+
+    /// Returns amount of frames written to the device
+    pub fn sent(&self) -> u64 {
+        self.shared.lock().sent
+    }
+
+    /// Returns amount of frames that were replaced by newer ones before being written
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+
+    /// Writes frames that are still pending, stops the threads and returns the device
+    pub fn finish(self) -> Ajazz {
+        self.shared.lock().stopped = true;
+        self.shared.changed.notify_all();
+
+        // Threads only panic if the conversion does, the device is lost with them then
+        let _ = self.converter.join();
+        self.writer.join().expect("writer thread must not panic")
+    }
+
+    fn push(&self, frame: VideoFrame) -> Result<(), AjazzError> {
+        let mut state = self.shared.lock();
+        if let Some(error) = state.error.take() {
+            return Err(error);
+        }
+
+        if state.pushed.replace(frame).is_some() {
+            state.dropped += 1;
+        }
+        self.shared.changed.notify_all();
+
+        Ok(())
+    }
+}
+
+/// Converts pushed frames until the sink is stopped and nothing is left to convert
+fn convert_frames(
+    shared: &Shared,
+    mut encode: impl FnMut(DynamicImage, &mut Vec<u8>) -> Result<(), AjazzError>,
+) {
+    loop {
+        let mut state = shared.wait_while(shared.lock(), |s| s.pushed.is_none() && !s.stopped);
+        let Some(frame) = state.pushed.take() else {
+            break;
+        };
+        state.converting = true;
+        let mut buffer = match frame {
+            VideoFrame::Image(_) => state.take_buffer(),
+            VideoFrame::Data(_) => vec![],
+        };
+        drop(state);
+
+        let result = match frame {
+            VideoFrame::Image(image) => encode(image, &mut buffer).map(|()| buffer),
+            // Encoded data becomes the buffer, it's reused once written
+            VideoFrame::Data(data) => Ok(data),
+        };
+
+        let mut state = shared.lock();
+        state.converting = false;
+        match result {
+            Ok(buffer) => {
+                if let Some(replaced) = state.encoded.replace(buffer) {
+                    state.dropped += 1;
+                    state.return_buffer(replaced);
+                }
+            }
+            Err(error) => state.error = Some(error),
+        }
+        shared.changed.notify_all();
+    }
+}
+
+/// Writes converted frames at most once per interval, until the sink is stopped
+/// and every frame is written
+fn write_frames(
+    shared: &Shared,
+    interval: Duration,
+    mut write: impl FnMut(&[u8]) -> Result<(), AjazzError>,
+) {
+    let mut next_write = Instant::now();
+    loop {
+        // Frame is picked when it's due, so frames arriving meanwhile replace it
+        std::thread::sleep(next_write.saturating_duration_since(Instant::now()));
+
+        let mut state = shared.wait_while(shared.lock(), |s| {
+            s.encoded.is_none() && !(s.stopped && s.pushed.is_none() && !s.converting)
+        });
+        let Some(frame) = state.encoded.take() else {
+            break;
+        };
+        drop(state);
+
+        next_write = Instant::now() + interval;
+        let result = write(&frame);
+
+        let mut state = shared.lock();
+        match result {
+            Ok(()) => state.sent += 1,
+            Err(error) => state.error = Some(error),
+        }
+        state.return_buffer(frame);
+    }
+}
+
+/// Streams animation to a single key, e.g. a level meter redrawn on every audio buffer.
+///
+/// Frames are encoded on the calling thread and queued to the [FramePump], which
+/// coalesces them with frames of other keys and sends only the latest one. Frames equal
+/// to the previous one are skipped before encoding, so static parts of an animation
+/// cost nothing. Burn-in protection of the pump doesn't apply, frames are queued encoded
+///
+/// ```no_run
+/// # fn example(device: &ajazz_sdk::Ajazz, frames: Vec<image::DynamicImage>) -> Result<(), ajazz_sdk::AjazzError> {
+/// let pump = ajazz_sdk::FramePump::new();
+/// let sink = ajazz_sdk::KeyVideoSink::new(device, &pump, 3)?;
+/// for frame in frames {
+///     sink.push_frame(frame)?;
+///     pump.pump(device)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct KeyVideoSink {
+    pump: FramePump,
+    key: u8,
+    format: ImageFormat,
+    options: ImageOptions,
+    state: Mutex<KeySinkState>,
+}
+
+#[derive(Default)]
+struct KeySinkState {
+    /// Hash of the last queued frame
+    last: Option<u64>,
+    skipped: u64,
+    /// Scratch buffer reused for encoding
+    buffer: Vec<u8>,
+}
+
+impl KeySinkState {
+    /// Remembers the frame, returns false if it's the same as the last one
+    fn is_new(&mut self, hash: u64) -> bool {
+        if self.last == Some(hash) {
+            self.skipped += 1;
+            return false;
+        }
+
+        self.last = Some(hash);
+        true
+    }
+}
+
+impl KeyVideoSink {
+    /// Creates sink of the key, frames are encoded in the current image format
+    /// and options of the device
+    pub fn new(device: &Ajazz, pump: &FramePump, key: u8) -> Result<KeyVideoSink, AjazzError> {
+        device.check_display_key(key)?;
+
+        Ok(KeyVideoSink {
+            pump: pump.clone(),
+            key,
+            format: device.key_image_format(),
+            options: device.image_options(),
+            state: Mutex::default(),
+        })
+    }
+
+    /// Returns the key frames are shown on
+    pub fn key(&self) -> u8 {
+        self.key
+    }
+
+    /// Encodes the image and queues it for the key, unless it's the same as the previous
+    /// frame. Returns true if the frame was queued
+    pub fn push_frame(&self, image: DynamicImage) -> Result<bool, AjazzError> {
+        let mut state = lock(&self.state);
+        if !state.is_new(hash_frame(image.width(), image.height(), image.as_bytes())) {
+            return Ok(false);
+        }
+
+        let mut buffer = std::mem::take(&mut state.buffer);
+        let result = encode_image_into(self.format, image, self.options, &mut buffer);
+        if result.is_ok() {
+            self.pump.push_frame_data(self.key, buffer.as_slice());
+        } else {
+            // Failed frame must not make the same frame look sent
+            state.last = None;
+        }
+        state.buffer = buffer;
+        result?;
+
+        Ok(true)
+    }
+
+    /// Queues image data already encoded in the key image format, unless it's the same
+    /// as the previous frame. Returns true if the frame was queued
+    pub fn push_frame_data(&self, image_data: &[u8]) -> bool {
+        let is_new = lock(&self.state).is_new(hash_frame(0, 0, image_data));
+        if is_new {
+            self.pump.push_frame_data(self.key, image_data);
+        }
+
+        is_new
+    }
+
+    /// Returns amount of frames skipped as repeats of the previous one
+    pub fn skipped(&self) -> u64 {
+        lock(&self.state).skipped
+    }
+}
+
+fn hash_frame(width: u32, height: u32, data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (width, height, data).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use super::*;
+    use crate::protocol::codes;
+    use crate::replay::ReplayTransport;
+    use crate::Kind;
+
+    #[test]
+    fn test_lcd_sink_drops_late_frames() {
+        let replay = ReplayTransport::default();
+        for _ in 0..10 {
+            replay.push_read(codes::RESPONSE_ACK_OK);
+        }
+        let device = Ajazz::from_transport(Kind::Akp153, replay.clone());
+
+        let sink = LcdVideoSink::new(device, 10).unwrap();
+        for _ in 0..5 {
+            sink.push_frame_data(vec![0xAA; 100]).unwrap();
+        }
+        sink.push_frame(DynamicImage::ImageRgb8(RgbImage::new(854, 480)))
+            .unwrap();
+
+        let device = sink.finish();
+        let logos = replay
+            .written()
+            .iter()
+            .filter(|p| &p[6..9] == b"LOG")
+            .count();
+        assert!((1..6).contains(&logos), "{logos} frames written");
+        // Last frame is always written
+        assert_eq!(device.export_state().logo.unwrap()[..2], [0xFF, 0xD8]);
+    }
+
+    #[test]
+    fn test_key_sink_skips_repeated_frames() {
+        let replay = ReplayTransport::default();
+        let device = Ajazz::from_transport(Kind::Akp03, replay.clone());
+        let pump = FramePump::new();
+        let sink = KeyVideoSink::new(&device, &pump, 2).unwrap();
+
+        let black = DynamicImage::ImageRgb8(RgbImage::new(60, 60));
+        let white =
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(60, 60, image::Rgb([255; 3])));
+        assert!(sink.push_frame(black.clone()).unwrap());
+        assert!(!sink.push_frame(black).unwrap());
+        assert!(sink.push_frame(white.clone()).unwrap());
+        assert_eq!((sink.skipped(), pump.pending(), pump.dropped()), (1, 1, 1));
+
+        assert_eq!(pump.pump(&device).unwrap(), 1);
+        assert!(!sink.push_frame(white).unwrap());
+        assert!(sink.push_frame_data(&[0xAA; 10]));
+        assert!(!sink.push_frame_data(&[0xAA; 10]));
+        assert_eq!(sink.skipped(), 3);
+
+        assert!(matches!(
+            KeyVideoSink::new(&device, &pump, 6),
+            Err(AjazzError::NoScreenOnKey(6))
+        ));
+    }
+
+    #[test]
+    fn test_lcd_sink_needs_strip() {
+        let device = Ajazz::from_transport(Kind::Akp03, ReplayTransport::default());
+        assert!(matches!(
+            LcdVideoSink::new(device, 20),
+            Err(AjazzError::UnsupportedOperation)
+        ));
+    }
+}
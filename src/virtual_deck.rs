@@ -0,0 +1,464 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use hidapi::HidResult;
+use image::{imageops, DynamicImage, Rgb, RgbImage};
+
+use crate::deck_preview::{EMPTY_KEY, PREVIEW_BACKGROUND, PREVIEW_GAP};
+use crate::formats::ImageMode;
+use crate::images::revert_orientation;
+use crate::locks::lock;
+use crate::protocol::{codes, parse_input, AjazzProtocolParser};
+use crate::transport::Transport;
+use crate::{AjazzError, AjazzInput, Kind};
+
+/// Firmware version reported by the virtual device
+const VIRTUAL_FIRMWARE_VERSION: &[u8] = b"virtual";
+
+/// Image transfer started by an announce, filled by the following data reports
+enum Transfer {
+    Key {
+        key: u8,
+        length: usize,
+        data: Vec<u8>,
+    },
+    Logo {
+        data: Vec<u8>,
+    },
+}
+
+struct VirtualState {
+    /// Images shown on the keys
+    shown: BTreeMap<u8, DynamicImage>,
+    /// Images and clears waiting for the flush, `None` clears the key
+    pending: BTreeMap<u8, Option<DynamicImage>>,
+    transfer: Option<Transfer>,
+    logo: Option<Vec<u8>>,
+    brightness: u8,
+    asleep: bool,
+    inputs: VecDeque<Vec<u8>>,
+}
+
+/// Simulated device that renders what the library sends into an in-memory framebuffer,
+/// so layouts can be built and demonstrated without hardware.
+///
+/// Virtual deck is a [Transport], connect to it with
+/// [Ajazz::from_transport](crate::Ajazz::from_transport). Input is injected with
+/// [press_key](VirtualAjazz::press_key) and similar methods, and is returned
+/// by the next read as if it came from the device.
+///
+/// Only logo and [ping](crate::Ajazz::ping) are acknowledged,
+/// so [ACK verification](crate::Ajazz::set_ack_verification) must stay disabled.
+///
+/// Deck is a cheap handle, clones share the same state, so one clone can be passed
+/// to the device and another kept for drawing.
+///
+/// ```
+/// use ajazz_sdk::{Ajazz, Kind, VirtualAjazz};
+///
+/// let deck = VirtualAjazz::new(Kind::Akp03);
+/// let device = Ajazz::from_transport(deck.kind(), deck.clone());
+/// device.set_button_image(0, image::DynamicImage::new_rgb8(60, 60)).unwrap();
+/// device.flush().unwrap();
+///
+/// assert!(deck.key_image(0).is_some());
+/// let framebuffer = deck.framebuffer();
+/// ```
+#[derive(Clone)]
+pub struct VirtualAjazz {
+    kind: Kind,
+    state: Arc<Mutex<VirtualState>>,
+}
+
+impl VirtualAjazz {
+    /// Creates blank deck of the kind
+    pub fn new(kind: Kind) -> VirtualAjazz {
+        VirtualAjazz {
+            kind,
+            state: Arc::new(Mutex::new(VirtualState {
+                shown: BTreeMap::new(),
+                pending: BTreeMap::new(),
+                transfer: None,
+                logo: None,
+                brightness: 100,
+                asleep: false,
+                inputs: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Returns kind of the simulated device
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// Returns image the key shows, upright
+    pub fn key_image(&self, key: u8) -> Option<DynamicImage> {
+        lock(&self.state).shown.get(&key).cloned()
+    }
+
+    /// Returns encoded boot logo, if it was set
+    pub fn logo(&self) -> Option<Vec<u8>> {
+        lock(&self.state).logo.clone()
+    }
+
+    /// Returns brightness of the deck, 100 until it's set
+    pub fn brightness(&self) -> u8 {
+        lock(&self.state).brightness
+    }
+
+    /// Returns true if the deck was put to sleep
+    pub fn is_asleep(&self) -> bool {
+        lock(&self.state).asleep
+    }
+
+    /// Renders the deck as it looks: display keys in rows and columns as on the device,
+    /// dimmed by the brightness. Sleeping deck is rendered dark
+    pub fn framebuffer(&self) -> RgbImage {
+        let state = lock(&self.state);
+        let (key_w, key_h) = self.kind.key_image_format().size;
+        let (key_w, key_h) = (key_w as u32, key_h as u32);
+        let columns = u32::from(self.kind.column_count()).max(1);
+        let keys = u32::from(self.kind.display_key_count());
+        let rows = keys.div_ceil(columns);
+
+        let width = columns * (key_w + PREVIEW_GAP) + PREVIEW_GAP;
+        let height = rows * (key_h + PREVIEW_GAP) + PREVIEW_GAP;
+        let mut framebuffer = RgbImage::from_pixel(width, height, PREVIEW_BACKGROUND);
+
+        let brightness = if state.asleep {
+            0
+        } else {
+            state.brightness.min(100)
+        };
+        for key in 0..keys {
+            let x = PREVIEW_GAP + (key % columns) * (key_w + PREVIEW_GAP);
+            let y = PREVIEW_GAP + (key / columns) * (key_h + PREVIEW_GAP);
+            let mut tile = match state.shown.get(&(key as u8)) {
+                Some(image) => image
+                    .resize_exact(key_w, key_h, imageops::FilterType::Triangle)
+                    .into_rgb8(),
+                None => RgbImage::from_pixel(key_w, key_h, EMPTY_KEY),
+            };
+            for pixel in tile.pixels_mut() {
+                *pixel = Rgb(pixel
+                    .0
+                    .map(|c| (u16::from(c) * u16::from(brightness) / 100) as u8));
+            }
+            imageops::replace(&mut framebuffer, &tile, x.into(), y.into());
+        }
+
+        framebuffer
+    }
+
+    /// Presses the key. Device reports press and release the same way,
+    /// so the key stays pressed until [release_key](VirtualAjazz::release_key)
+    pub fn press_key(&self, key: u8) -> Result<(), AjazzError> {
+        let action_code = self.find_action_code(|input| {
+            matches!(input, AjazzInput::ButtonStateChange(states)
+                if states.iter().enumerate().all(|(i, s)| *s == (i == key as usize)))
+        })?;
+        self.push_input(action_code);
+        Ok(())
+    }
+
+    /// Releases the key pressed with [press_key](VirtualAjazz::press_key)
+    pub fn release_key(&self, key: u8) -> Result<(), AjazzError> {
+        self.press_key(key)
+    }
+
+    /// Presses the encoder, it stays pressed until
+    /// [release_encoder](VirtualAjazz::release_encoder)
+    pub fn press_encoder(&self, encoder: u8) -> Result<(), AjazzError> {
+        let action_code = self.find_action_code(|input| {
+            matches!(input, AjazzInput::EncoderStateChange(states)
+                if states.iter().enumerate().all(|(i, s)| *s == (i == encoder as usize)))
+        })?;
+        self.push_input(action_code);
+        Ok(())
+    }
+
+    /// Releases the encoder pressed with [press_encoder](VirtualAjazz::press_encoder)
+    pub fn release_encoder(&self, encoder: u8) -> Result<(), AjazzError> {
+        self.press_encoder(encoder)
+    }
+
+    /// Twists the encoder by `ticks`, positive values turn it clockwise
+    pub fn twist_encoder(&self, encoder: u8, ticks: i8) -> Result<(), AjazzError> {
+        let direction = ticks.signum();
+        let action_code = self.find_action_code(|input| {
+            matches!(input, AjazzInput::EncoderTwist(values)
+                if values.get(encoder as usize) == Some(&direction))
+        })?;
+        for _ in 0..ticks.unsigned_abs() {
+            self.push_input(action_code);
+        }
+        Ok(())
+    }
+
+    /// Finds action code the device would send for the input,
+    /// by parsing every code the same way the library does
+    fn find_action_code(
+        &self,
+        matches: impl Fn(&AjazzInput) -> bool,
+    ) -> Result<u8, AjazzError> {
+        (1..=u8::MAX)
+            .find(|code| {
+                parse_input(self.kind, &input_report(*code)).is_ok_and(|input| matches(&input))
+            })
+            .ok_or(AjazzError::UnsupportedOperation)
+    }
+
+    fn push_input(&self, action_code: u8) {
+        let report = input_report(action_code);
+        lock(&self.state).inputs.push_back(report);
+    }
+
+    fn handle_command(&self, state: &mut VirtualState, report: &[u8]) {
+        let command = &report[codes::REQUEST_HEADER.len()..];
+        let byte = |offset: usize| command.get(offset).copied().unwrap_or(0);
+        let length =
+            |offset: usize| usize::from(byte(offset)) << 8 | usize::from(byte(offset + 1));
+
+        match command.get(..3).unwrap_or(&[]) {
+            b"BAT" => {
+                let raw = byte(7).wrapping_sub(1);
+                let key = (0..self.kind.display_key_count())
+                    .find(|k| self.kind.index_to_native_v1(*k).unwrap_or(*k) == raw)
+                    .unwrap_or(raw);
+                state.transfer = Some(Transfer::Key {
+                    key,
+                    length: length(5),
+                    data: vec![],
+                });
+            }
+            b"LOG" => {
+                state.transfer = Some(Transfer::Logo { data: vec![] });
+                state.inputs.push_back(codes::RESPONSE_ACK_OK.to_vec());
+            }
+            b"STP" => {
+                for (key, image) in std::mem::take(&mut state.pending) {
+                    match image {
+                        Some(image) => state.shown.insert(key, image),
+                        None => state.shown.remove(&key),
+                    };
+                }
+            }
+            b"CLE" if command.get(3..7) == Some(b"\0\0DC") => {}
+            b"CLE" => {
+                let raw = byte(6);
+                let keys: Vec<u8> = if raw == codes::CMD_CLEAR_ALL {
+                    (0..self.kind.display_key_count()).collect()
+                } else {
+                    let raw = raw.wrapping_sub(1);
+                    (0..self.kind.display_key_count())
+                        .filter(|k| self.kind.index_to_native_v1(*k).unwrap_or(*k) == raw)
+                        .collect()
+                };
+                for key in keys {
+                    // Clearing is committed by flush only on "v2" devices
+                    if self.kind.is_v2_api() {
+                        state.pending.insert(key, None);
+                    } else {
+                        state.shown.remove(&key);
+                    }
+                }
+            }
+            b"LIG" => state.brightness = byte(5),
+            b"HAN" => state.asleep = true,
+            b"DIS" => state.asleep = false,
+            b"CON" => state.inputs.push_back(codes::RESPONSE_ACK_OK.to_vec()),
+            _ => {}
+        }
+    }
+
+    fn handle_data(&self, state: &mut VirtualState, payload: &[u8]) {
+        let finished = match &mut state.transfer {
+            Some(Transfer::Key { length, data, .. }) => {
+                let left = length.saturating_sub(data.len());
+                data.extend_from_slice(&payload[..left.min(payload.len())]);
+                data.len() >= *length
+            }
+            Some(Transfer::Logo { data }) => {
+                data.extend_from_slice(payload);
+                state.logo = Some(data.clone());
+                false
+            }
+            None => false,
+        };
+
+        if finished {
+            if let Some(Transfer::Key { key, data, .. }) = state.transfer.take() {
+                let image = self.decode_key_image(&data);
+                state.pending.insert(key, image);
+            }
+        }
+    }
+
+    /// Decodes key image sent by the library, images that can't be decoded are shown blank
+    fn decode_key_image(&self, data: &[u8]) -> Option<DynamicImage> {
+        let format = self.kind.key_image_format();
+        if format.mode != ImageMode::JPEG {
+            return None;
+        }
+
+        let image =
+            image::load_from_memory_with_format(data, image::ImageFormat::Jpeg).ok()?;
+        Some(revert_orientation(format, image))
+    }
+
+    fn next_input(&self, buf: &mut [u8]) -> usize {
+        let Some(data) = lock(&self.state).inputs.pop_front() else {
+            return 0;
+        };
+
+        let length = data.len().min(buf.len());
+        buf[..length].copy_from_slice(&data[..length]);
+        buf[length..].fill(0);
+        length
+    }
+}
+
+/// Builds input report with the action code
+fn input_report(action_code: u8) -> Vec<u8> {
+    // Input reports start with the same header as responses
+    let mut report = vec![0u8; codes::INPUT_PACKET_LENGTH];
+    report[..codes::RESPONSE_ACK_OK.len()].copy_from_slice(codes::RESPONSE_ACK_OK);
+    report[codes::OFFSET_ACTION_CODE] = action_code;
+    report
+}
+
+impl Transport for VirtualAjazz {
+    fn write(&self, data: &[u8]) -> HidResult<usize> {
+        let mut state = lock(&self.state);
+        if data.starts_with(codes::REQUEST_HEADER) {
+            self.handle_command(&mut state, data);
+        } else if let Some(payload) = data.get(1..) {
+            self.handle_data(&mut state, payload);
+        }
+
+        Ok(data.len())
+    }
+
+    fn read(&self, buf: &mut [u8]) -> HidResult<usize> {
+        Ok(self.next_input(buf))
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], _timeout: i32) -> HidResult<usize> {
+        Ok(self.next_input(buf))
+    }
+
+    fn set_blocking_mode(&self, _blocking: bool) -> HidResult<()> {
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+        let length = (VIRTUAL_FIRMWARE_VERSION.len() + 1).min(buf.len());
+        buf[1..length].copy_from_slice(&VIRTUAL_FIRMWARE_VERSION[..length - 1]);
+        Ok(length)
+    }
+
+    fn get_manufacturer_string(&self) -> HidResult<Option<String>> {
+        Ok(Some("Ajazz".to_string()))
+    }
+
+    fn get_product_string(&self) -> HidResult<Option<String>> {
+        Ok(Some(self.kind.display_name().to_string()))
+    }
+
+    fn get_serial_number_string(&self) -> HidResult<Option<String>> {
+        Ok(Some("VIRTUAL".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Ajazz, Event};
+
+    #[test]
+    fn test_virtual_deck_shows_flushed_images() {
+        for kind in [Kind::Akp153, Kind::Akp03] {
+            let deck = VirtualAjazz::new(kind);
+            let device = Ajazz::from_transport(kind, deck.clone());
+            let (w, h) = kind.key_image_format().size;
+            let mut image = RgbImage::from_pixel(w as u32, h as u32, Rgb([0, 0, 0]));
+            for y in 0..20 {
+                for x in 0..20 {
+                    image.put_pixel(x, y, Rgb([255, 255, 255]));
+                }
+            }
+
+            device
+                .set_button_image(1, DynamicImage::ImageRgb8(image))
+                .unwrap();
+            assert!(deck.key_image(1).is_none());
+            device.flush().unwrap();
+
+            // Image is decoded upright, whatever orientation the device needs
+            let shown = deck.key_image(1).unwrap().into_rgb8();
+            assert!(shown.get_pixel(5, 5).0[0] > 200, "{kind:?}");
+            assert!(
+                shown.get_pixel(w as u32 - 5, h as u32 - 5).0[0] < 50,
+                "{kind:?}"
+            );
+            assert!(deck.key_image(0).is_none());
+
+            // Clearing another key leaves the image alone
+            device.clear_button_images(&[0]).unwrap();
+            assert!(deck.key_image(1).is_some(), "{kind:?}");
+            device.clear_button_images(&[1]).unwrap();
+            assert!(deck.key_image(1).is_none(), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_virtual_deck_state() {
+        let deck = VirtualAjazz::new(Kind::Akp03);
+        let device = Ajazz::from_transport(deck.kind(), deck.clone());
+        device.set_brightness(0).unwrap();
+        assert_eq!(deck.brightness(), 0);
+        assert!(deck
+            .framebuffer()
+            .pixels()
+            .all(|p| *p == PREVIEW_BACKGROUND || p.0 == [0; 3]));
+
+        device.sleep().unwrap();
+        assert!(deck.is_asleep());
+        device.wake().unwrap();
+        assert!(!deck.is_asleep());
+
+        assert!(device.ping().is_ok());
+        assert!(device.firmware_version().unwrap().contains("virtual"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "akp153", feature = "akp815", feature = "akp03"))]
+    fn test_virtual_deck_input() {
+        for kind in [Kind::Akp153, Kind::Akp815, Kind::Akp03] {
+            let deck = VirtualAjazz::new(kind);
+            let device = Ajazz::from_transport(kind, deck.clone());
+            #[allow(clippy::arc_with_non_send_sync)]
+            let reader = Arc::new(device).get_reader();
+
+            deck.press_key(2).unwrap();
+            assert_eq!(
+                reader.read(None).unwrap(),
+                [Event::ButtonDown(2)],
+                "{kind:?}"
+            );
+            deck.release_key(2).unwrap();
+            assert_eq!(reader.read(None).unwrap(), [Event::ButtonUp(2)], "{kind:?}");
+        }
+
+        let deck = VirtualAjazz::new(Kind::Akp03);
+        #[allow(clippy::arc_with_non_send_sync)]
+        let device = Arc::new(Ajazz::from_transport(deck.kind(), deck.clone()));
+        let reader = device.get_reader();
+        deck.twist_encoder(1, -2).unwrap();
+        assert_eq!(reader.read(None).unwrap(), [Event::EncoderTwist(1, -1)]);
+        assert_eq!(reader.read(None).unwrap(), [Event::EncoderTwist(1, -1)]);
+        deck.press_encoder(0).unwrap();
+        assert_eq!(reader.read(None).unwrap(), [Event::EncoderDown(0)]);
+    }
+}
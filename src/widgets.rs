@@ -0,0 +1,554 @@
+use image::{imageops, DynamicImage, Rgb, RgbImage};
+
+use crate::{AjazzError, ImageRect, Kind};
+
+/// Area of the LCD strip in pixels
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    /// Left edge
+    pub x: u32,
+    /// Top edge
+    pub y: u32,
+    /// Width of the area
+    pub w: u32,
+    /// Height of the area
+    pub h: u32,
+}
+
+impl Rect {
+    /// Creates new rect
+    pub const fn new(x: u32, y: u32, w: u32, h: u32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    /// Returns smallest rect containing both rects
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.w).max(other.x + other.w);
+        let bottom = (self.y + self.h).max(other.y + other.h);
+
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
+
+/// Named area of the LCD strip, see [LcdZones]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LcdZone {
+    /// Whole strip
+    Full,
+    /// Left third of the strip
+    Left,
+    /// Middle third of the strip
+    Center,
+    /// Right third of the strip
+    Right,
+    /// Area under the key column
+    Column(u8),
+}
+
+/// Maps [LcdZone]s to areas of the LCD strip of the device kind,
+/// so widgets can be placed without hard-coding offsets
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LcdZones {
+    w: u32,
+    h: u32,
+    columns: u8,
+}
+
+impl LcdZones {
+    /// Creates zones of the LCD strip of the device kind
+    pub fn new(kind: Kind) -> Result<LcdZones, AjazzError> {
+        let (w, h) = kind
+            .lcd_strip_size()
+            .ok_or(AjazzError::UnsupportedOperation)?;
+
+        Ok(LcdZones::with_size(w as u32, h as u32, kind.column_count()))
+    }
+
+    /// Creates zones of the strip of the size, spanning under `columns` key columns
+    pub fn with_size(w: u32, h: u32, columns: u8) -> LcdZones {
+        LcdZones {
+            w,
+            h,
+            columns: columns.max(1),
+        }
+    }
+
+    /// Returns area of the zone, or `None` if there's no such column
+    pub fn rect(&self, zone: LcdZone) -> Option<Rect> {
+        match zone {
+            LcdZone::Full => Some(Rect::new(0, 0, self.w, self.h)),
+            LcdZone::Left => Some(self.slice(0, 3)),
+            LcdZone::Center => Some(self.slice(1, 3)),
+            LcdZone::Right => Some(self.slice(2, 3)),
+            LcdZone::Column(column) if column < self.columns => {
+                Some(self.slice(column.into(), self.columns.into()))
+            }
+            LcdZone::Column(_) => None,
+        }
+    }
+
+    /// Returns areas under every key column, from left to right
+    pub fn columns(&self) -> impl Iterator<Item = Rect> + '_ {
+        (0..self.columns).filter_map(|column| self.rect(LcdZone::Column(column)))
+    }
+
+    /// Returns the part of `count` equal parts, spreading rounding over all of them
+    fn slice(&self, index: u32, count: u32) -> Rect {
+        let left = self.w * index / count;
+        let right = self.w * (index + 1) / count;
+        Rect::new(left, 0, right - left, self.h)
+    }
+}
+
+/// Element drawn on the [LcdCanvas]
+pub trait Widget {
+    /// Area of the strip the widget occupies
+    fn rect(&self) -> Rect;
+
+    /// Returns true if the widget changed since it was last rendered
+    fn is_dirty(&self) -> bool;
+
+    /// Renders the widget into the image of its size, which is filled with background color
+    fn render(&mut self, image: &mut RgbImage);
+}
+
+/// Framebuffer of the LCD strip, which keeps track of areas changed by widgets,
+/// so only them have to be sent to the device
+pub struct LcdCanvas {
+    image: RgbImage,
+    background: Rgb<u8>,
+    dirty: Option<Rect>,
+}
+
+impl LcdCanvas {
+    /// Creates black canvas matching LCD strip of the device kind
+    pub fn new(kind: Kind) -> Result<LcdCanvas, AjazzError> {
+        let (w, h) = kind
+            .lcd_strip_size()
+            .ok_or(AjazzError::UnsupportedOperation)?;
+
+        Ok(LcdCanvas::with_size(w as u32, h as u32, Rgb([0, 0, 0])))
+    }
+
+    /// Creates canvas of the size filled with background color
+    pub fn with_size(w: u32, h: u32, background: Rgb<u8>) -> LcdCanvas {
+        LcdCanvas {
+            image: RgbImage::from_pixel(w, h, background),
+            background,
+            dirty: Some(Rect::new(0, 0, w, h)),
+        }
+    }
+
+    /// Renders the widget if it changed. Returns area that was redrawn
+    pub fn draw(&mut self, widget: &mut impl Widget) -> Option<Rect> {
+        if !widget.is_dirty() {
+            return None;
+        }
+
+        let rect = self.clip(widget.rect())?;
+        let mut image = RgbImage::from_pixel(rect.w, rect.h, self.background);
+        widget.render(&mut image);
+        imageops::replace(&mut self.image, &image, rect.x.into(), rect.y.into());
+
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(&rect),
+            None => rect,
+        });
+
+        Some(rect)
+    }
+
+    /// Returns area changed since the last call
+    pub fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    /// Returns current content of the canvas
+    pub fn image(&self) -> &RgbImage {
+        &self.image
+    }
+
+    /// Returns copy of the whole canvas, suitable for [Ajazz::set_logo_image](crate::Ajazz::set_logo_image)
+    pub fn to_image(&self) -> DynamicImage {
+        DynamicImage::ImageRgb8(self.image.clone())
+    }
+
+    /// Encodes area of the canvas to be sent to the device
+    pub fn region(&self, rect: Rect) -> Result<ImageRect, AjazzError> {
+        let rect = self.clip(rect).unwrap_or_default();
+        let image = imageops::crop_imm(&self.image, rect.x, rect.y, rect.w, rect.h).to_image();
+
+        ImageRect::from_image(DynamicImage::ImageRgb8(image))
+    }
+
+    fn clip(&self, rect: Rect) -> Option<Rect> {
+        let (w, h) = self.image.dimensions();
+        if rect.x >= w || rect.y >= h {
+            return None;
+        }
+
+        let rect = Rect::new(
+            rect.x,
+            rect.y,
+            rect.w.min(w - rect.x),
+            rect.h.min(h - rect.y),
+        );
+        (rect.w > 0 && rect.h > 0).then_some(rect)
+    }
+}
+
+/// Audio level meter with peak hold. Horizontal if wider than high, vertical otherwise
+pub struct VuMeter {
+    rect: Rect,
+    /// Color of the low levels
+    pub low_color: Rgb<u8>,
+    /// Color of the levels above 60%
+    pub mid_color: Rgb<u8>,
+    /// Color of the levels above 85%
+    pub high_color: Rgb<u8>,
+    /// How much the peak marker falls on every level update
+    pub peak_decay: f32,
+    level: f32,
+    peak: f32,
+    drawn: Option<(u32, u32)>,
+}
+
+impl VuMeter {
+    /// Creates meter occupying the area
+    pub fn new(rect: Rect) -> VuMeter {
+        VuMeter {
+            rect,
+            low_color: Rgb([0, 200, 0]),
+            mid_color: Rgb([230, 200, 0]),
+            high_color: Rgb([230, 0, 0]),
+            peak_decay: 0.02,
+            level: 0.0,
+            peak: 0.0,
+            drawn: None,
+        }
+    }
+
+    /// Sets current level in range from 0 to 1
+    pub fn set_level(&mut self, level: f32) {
+        self.level = level.clamp(0.0, 1.0);
+        self.peak = self.level.max(self.peak - self.peak_decay);
+    }
+
+    /// Returns current level
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    fn length(&self) -> u32 {
+        self.rect.w.max(self.rect.h)
+    }
+
+    fn state(&self) -> (u32, u32) {
+        let length = self.length() as f32;
+        ((self.level * length) as u32, (self.peak * length) as u32)
+    }
+
+    fn color_at(&self, position: u32) -> Rgb<u8> {
+        let ratio = position as f32 / self.length() as f32;
+        if ratio >= 0.85 {
+            self.high_color
+        } else if ratio >= 0.6 {
+            self.mid_color
+        } else {
+            self.low_color
+        }
+    }
+}
+
+impl Widget for VuMeter {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.drawn != Some(self.state())
+    }
+
+    fn render(&mut self, image: &mut RgbImage) {
+        let (lit, peak) = self.state();
+        let horizontal = self.rect.w >= self.rect.h;
+
+        for position in 0..self.length() {
+            if position >= lit && position + 1 != peak {
+                continue;
+            }
+
+            let color = self.color_at(position);
+            if horizontal {
+                fill_rect(image, Rect::new(position, 0, 1, self.rect.h), color);
+            } else {
+                let y = self.rect.h - 1 - position;
+                fill_rect(image, Rect::new(0, y, self.rect.w, 1), color);
+            }
+        }
+
+        self.drawn = Some((lit, peak));
+    }
+}
+
+/// Horizontal bar showing progress of the task
+pub struct ProgressBar {
+    rect: Rect,
+    /// Color of the completed part
+    pub fill_color: Rgb<u8>,
+    /// Color of the remaining part
+    pub track_color: Rgb<u8>,
+    progress: f32,
+    drawn: Option<u32>,
+}
+
+impl ProgressBar {
+    /// Creates bar occupying the area
+    pub fn new(rect: Rect) -> ProgressBar {
+        ProgressBar {
+            rect,
+            fill_color: Rgb([255, 255, 255]),
+            track_color: Rgb([60, 60, 60]),
+            progress: 0.0,
+            drawn: None,
+        }
+    }
+
+    /// Sets progress in range from 0 to 1
+    pub fn set_progress(&mut self, progress: f32) {
+        self.progress = progress.clamp(0.0, 1.0);
+    }
+
+    /// Returns current progress
+    pub fn progress(&self) -> f32 {
+        self.progress
+    }
+
+    fn filled(&self) -> u32 {
+        (self.progress * self.rect.w as f32) as u32
+    }
+}
+
+impl Widget for ProgressBar {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.drawn != Some(self.filled())
+    }
+
+    fn render(&mut self, image: &mut RgbImage) {
+        let filled = self.filled();
+        fill_rect(image, Rect::new(0, 0, filled, self.rect.h), self.fill_color);
+        fill_rect(
+            image,
+            Rect::new(filled, 0, self.rect.w - filled, self.rect.h),
+            self.track_color,
+        );
+
+        self.drawn = Some(filled);
+    }
+}
+
+/// Segments of digits from 0 to 9 as bits, from `a` (top) to `g` (middle)
+const DIGIT_SEGMENTS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+    0b1111111, 0b1101111,
+];
+
+/// Clock showing hours and minutes with seven-segment digits.
+///
+/// Library doesn't know the local time zone, so the time is set by the application
+pub struct Clock {
+    rect: Rect,
+    /// Color of the digits
+    pub color: Rgb<u8>,
+    time: (u8, u8),
+    drawn: Option<(u8, u8)>,
+}
+
+impl Clock {
+    /// Creates clock occupying the area
+    pub fn new(rect: Rect) -> Clock {
+        Clock {
+            rect,
+            color: Rgb([255, 255, 255]),
+            time: (0, 0),
+            drawn: None,
+        }
+    }
+
+    /// Sets displayed time
+    pub fn set_time(&mut self, hours: u8, minutes: u8) {
+        self.time = (hours % 24, minutes % 60);
+    }
+
+    /// Returns displayed time as (hours, minutes)
+    pub fn time(&self) -> (u8, u8) {
+        self.time
+    }
+
+    fn draw_digit(&self, image: &mut RgbImage, x: u32, w: u32, digit: u8) {
+        draw_digit(image, Rect::new(x, 0, w, self.rect.h), digit, self.color);
+    }
+}
+
+impl Widget for Clock {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.drawn != Some(self.time)
+    }
+
+    fn render(&mut self, image: &mut RgbImage) {
+        // Four digits and a colon, with gaps of a quarter of digit width
+        let digit_w = self.rect.w * 4 / 19;
+        let gap = digit_w / 4;
+        let (hours, minutes) = self.time;
+
+        let mut x = 0;
+        for (index, digit) in [hours / 10, hours % 10, minutes / 10, minutes % 10]
+            .into_iter()
+            .enumerate()
+        {
+            self.draw_digit(image, x, digit_w, digit);
+            x += digit_w + gap;
+
+            if index == 1 {
+                let dot = gap.max(1);
+                fill_rect(image, Rect::new(x, self.rect.h / 3, dot, dot), self.color);
+                fill_rect(
+                    image,
+                    Rect::new(x, self.rect.h * 2 / 3, dot, dot),
+                    self.color,
+                );
+                x += dot + gap;
+            }
+        }
+
+        self.drawn = Some(self.time);
+    }
+}
+
+/// Draws seven-segment digit filling the area
+pub(crate) fn draw_digit(image: &mut RgbImage, rect: Rect, digit: u8, color: Rgb<u8>) {
+    let Rect { x, y, w, h } = rect;
+    let t = (w / 5).max(1);
+    let half = h / 2;
+    let segments = [
+        Rect::new(x, y, w, t),
+        Rect::new(x + w - t, y, t, half),
+        Rect::new(x + w - t, y + half, t, h - half),
+        Rect::new(x, y + h - t, w, t),
+        Rect::new(x, y + half, t, h - half),
+        Rect::new(x, y, t, half),
+        Rect::new(x, y + half - t / 2, w, t),
+    ];
+
+    for (bit, segment) in segments.into_iter().enumerate() {
+        if DIGIT_SEGMENTS[digit as usize % 10] & (1 << bit) != 0 {
+            fill_rect(image, segment, color);
+        }
+    }
+}
+
+pub(crate) fn fill_rect(image: &mut RgbImage, rect: Rect, color: Rgb<u8>) {
+    let (w, h) = image.dimensions();
+    for y in rect.y.min(h)..(rect.y + rect.h).min(h) {
+        for x in rect.x.min(w)..(rect.x + rect.w).min(w) {
+            image.put_pixel(x, y, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canvas() -> LcdCanvas {
+        let mut canvas = LcdCanvas::with_size(100, 50, Rgb([0, 0, 0]));
+        canvas.take_dirty();
+        canvas
+    }
+
+    #[test]
+    fn test_widget_redraws_only_on_change() {
+        let mut canvas = canvas();
+        let mut bar = ProgressBar::new(Rect::new(10, 10, 50, 10));
+
+        assert_eq!(canvas.draw(&mut bar), Some(Rect::new(10, 10, 50, 10)));
+        assert_eq!(canvas.draw(&mut bar), None);
+
+        bar.set_progress(0.001);
+        assert_eq!(canvas.draw(&mut bar), None);
+
+        bar.set_progress(0.5);
+        assert!(canvas.draw(&mut bar).is_some());
+        assert_eq!(canvas.image().get_pixel(34, 15), &bar.fill_color);
+        assert_eq!(canvas.image().get_pixel(35, 15), &bar.track_color);
+    }
+
+    #[test]
+    fn test_dirty_area_is_union() {
+        let mut canvas = canvas();
+        let mut meter = VuMeter::new(Rect::new(0, 0, 10, 40));
+        let mut clock = Clock::new(Rect::new(50, 20, 40, 20));
+
+        canvas.draw(&mut meter);
+        canvas.draw(&mut clock);
+        assert_eq!(canvas.take_dirty(), Some(Rect::new(0, 0, 90, 40)));
+        assert_eq!(canvas.take_dirty(), None);
+    }
+
+    #[test]
+    fn test_vu_meter_fills_from_bottom() {
+        let mut canvas = canvas();
+        let mut meter = VuMeter::new(Rect::new(0, 0, 10, 40));
+        meter.set_level(0.5);
+        canvas.draw(&mut meter);
+
+        assert_eq!(canvas.image().get_pixel(5, 39), &meter.low_color);
+        assert_eq!(canvas.image().get_pixel(5, 10), &Rgb([0, 0, 0]));
+
+        meter.set_level(0.0);
+        assert!(meter.is_dirty());
+    }
+
+    #[test]
+    fn test_region_is_clipped() {
+        let canvas = canvas();
+        let region = canvas.region(Rect::new(90, 40, 20, 20)).unwrap();
+        assert_eq!((region.w, region.h), (10, 10));
+    }
+
+    #[test]
+    fn test_lcd_zones_cover_strip() {
+        let zones = LcdZones::new(Kind::Akp153).unwrap();
+        assert_eq!(zones.rect(LcdZone::Full), Some(Rect::new(0, 0, 854, 480)));
+        assert_eq!(zones.rect(LcdZone::Left), Some(Rect::new(0, 0, 284, 480)));
+        assert_eq!(
+            zones.rect(LcdZone::Center),
+            Some(Rect::new(284, 0, 285, 480))
+        );
+        assert_eq!(
+            zones.rect(LcdZone::Right),
+            Some(Rect::new(569, 0, 285, 480))
+        );
+        assert_eq!(zones.rect(LcdZone::Column(5)), None);
+
+        let columns: Vec<Rect> = zones.columns().collect();
+        assert_eq!(columns.len(), 5);
+        assert_eq!(columns[0], Rect::new(0, 0, 170, 480));
+        assert_eq!(columns[4].x + columns[4].w, 854);
+
+        assert!(matches!(
+            LcdZones::new(Kind::Akp03),
+            Err(AjazzError::UnsupportedOperation)
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+//! Tests against a real device, the first connected one is used.
+//!
+//! They are ignored by default, run them with a device plugged in:
+//!
+//! ```sh
+//! cargo test --features hw-tests --test hardware -- --ignored --test-threads 1
+//! ```
+//!
+//! Input test asks to press keys, the key to press is lit green on the device.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use ajazz_sdk::{list_devices, new_hidapi, Ajazz, Event};
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Longest time connecting may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest time a brightness change may take
+const BRIGHTNESS_TIMEOUT: Duration = Duration::from_millis(100);
+/// Longest time sending one key image during the flush may take
+const KEY_FLUSH_TIMEOUT: Duration = Duration::from_millis(250);
+/// How long to wait for the prompted key press
+const PRESS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Only one test can talk to the device at a time
+static DEVICE: Mutex<()> = Mutex::new(());
+
+/// Connects to the first device, holding the lock until the guard is dropped
+fn connect() -> (MutexGuard<'static, ()>, Ajazz) {
+    let guard = DEVICE.lock().unwrap_or_else(|e| e.into_inner());
+    let hidapi = new_hidapi().expect("hidapi must initialize");
+    let (kind, serial) = list_devices(&hidapi)
+        .into_iter()
+        .next()
+        .expect("no device connected");
+    let device = Ajazz::connect(&hidapi, kind, &serial).expect("device must connect");
+
+    (guard, device)
+}
+
+/// Returns key image filled with the color
+fn solid(device: &Ajazz, color: [u8; 3]) -> DynamicImage {
+    let (width, height) = device.kind().key_image_format().size;
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(
+        width as u32,
+        height as u32,
+        Rgb(color),
+    ))
+}
+
+#[test]
+#[ignore = "needs a connected device"]
+fn test_connect() {
+    let started = Instant::now();
+    let (_guard, device) = connect();
+    assert!(
+        started.elapsed() < CONNECT_TIMEOUT,
+        "{:?}",
+        started.elapsed()
+    );
+
+    assert!(!device.firmware_version().unwrap().is_empty());
+    assert!(!device.serial_number().unwrap().is_empty());
+}
+
+#[test]
+#[ignore = "needs a connected device"]
+fn test_brightness_sweep() {
+    let (_guard, device) = connect();
+
+    for percent in (0..=100).step_by(10).chain((0..100).step_by(10).rev()) {
+        let started = Instant::now();
+        device.set_brightness(percent).unwrap();
+        assert!(
+            started.elapsed() < BRIGHTNESS_TIMEOUT,
+            "{percent}% took {:?}",
+            started.elapsed()
+        );
+    }
+    device.set_brightness(50).unwrap();
+}
+
+#[test]
+#[ignore = "needs a connected device"]
+fn test_full_image_flush() {
+    let (_guard, device) = connect();
+    device.set_brightness(50).unwrap();
+
+    let keys = device.display_keys();
+    let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+    for key in keys.clone() {
+        let color = colors[key as usize % colors.len()];
+        device.set_button_image(key, solid(&device, color)).unwrap();
+    }
+    assert_eq!(device.cache_len(), keys.len());
+
+    let started = Instant::now();
+    device.flush().unwrap();
+    let limit = KEY_FLUSH_TIMEOUT * u32::from(device.display_key_count());
+    assert!(started.elapsed() < limit, "{:?}", started.elapsed());
+    assert_eq!(device.cache_len(), 0);
+
+    device.clear_all_button_images().unwrap();
+    device.flush().unwrap();
+}
+
+#[test]
+#[ignore = "needs a connected device"]
+#[allow(clippy::arc_with_non_send_sync)]
+fn test_input_echo() {
+    let (_guard, device) = connect();
+    let device = Arc::new(device);
+    let reader = device.get_reader();
+    device.set_brightness(50).unwrap();
+
+    for key in device.display_keys() {
+        device.clear_all_button_images().unwrap();
+        device
+            .set_button_image(key, solid(&device, [0, 255, 0]))
+            .unwrap();
+        device.flush().unwrap();
+        eprintln!("Press the green key");
+
+        let deadline = Instant::now() + PRESS_TIMEOUT;
+        let pressed = loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            assert!(!left.is_zero(), "key {key} wasn't pressed in time");
+
+            let events = reader.read(Some(left)).unwrap();
+            if let Some(pressed) = events.iter().find_map(|event| match event {
+                Event::ButtonDown(pressed) => Some(*pressed),
+                _ => None,
+            }) {
+                break pressed;
+            }
+        };
+        assert_eq!(pressed, key);
+    }
+
+    device.clear_all_button_images().unwrap();
+    device.flush().unwrap();
+}